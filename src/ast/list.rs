@@ -1,8 +1,10 @@
 use std::fmt;
+use std::hash::{Hash, Hasher};
 use std::iter::Iterator;
+use std::mem;
 use std::rc::Rc;
 
-use super::Expr;
+use super::{Expr, Span};
 
 #[derive(Clone, Debug)]
 pub enum List {
@@ -14,54 +16,97 @@ pub enum List {
 pub struct Node {
   pub head: Expr,
   pub tail: List,
+  /// Where `head` was read from, if it came from source code.
+  pub span: Option<Span>,
 }
 
 impl List {
   pub fn cons(head: Expr, tail: List) -> List {
+    List::cons_spanned(head, tail, None)
+  }
+
+  pub fn cons_spanned(head: Expr, tail: List, span: Option<Span>) -> List {
     use List::*;
 
-    let node = Node { head, tail };
+    let node = Node { head, tail, span };
     Cons(Rc::new(node))
   }
 
   pub fn get(&self, index: usize) -> Option<&Expr> {
-    use List::*;
+    self.iter().nth(index).map(|node| &node.head)
+  }
 
-    let node = match self {
-      Cons(node) => node,
-      Nil => return None,
-    };
+  pub fn len(&self) -> usize {
+    self.iter().count()
+  }
 
-    if index == 0 {
-      Some(&node.head)
-    } else {
-      node.tail.get(index - 1)
-    }
+  pub fn is_empty(&self) -> bool {
+    matches!(self, List::Nil)
   }
 
-  pub fn len(&self) -> usize {
+  pub fn iter(&self) -> Iter<'_> {
+    Iter(self)
+  }
+
+  /// Returns whether both lists are the same instance, rather than just
+  /// having equal elements.
+  pub fn ptr_eq(&self, other: &List) -> bool {
     use List::*;
 
-    match self {
-      Cons(node) => 1 + node.tail.len(),
-      Nil => 0,
+    match (self, other) {
+      (Cons(left), Cons(right)) => Rc::ptr_eq(left, right),
+      (Nil, Nil) => true,
+      _ => false,
     }
   }
+}
 
-  pub fn into_iter(self) -> IntoIter {
-    IntoIter(self)
+impl Drop for Node {
+  fn drop(&mut self) {
+    use List::*;
+
+    // Unlink the tail iteratively, since dropping it recursively overflows the
+    // stack for long lists.
+    let mut tail = mem::replace(&mut self.tail, Nil);
+    while let Cons(node) = tail {
+      tail = match Rc::try_unwrap(node) {
+        Ok(mut node) => mem::replace(&mut node.tail, Nil),
+        Err(_) => break,
+      };
+    }
   }
 }
 
 impl PartialEq for List {
   fn eq(&self, other: &List) -> bool {
-    use List::*;
+    let mut left = self.iter();
+    let mut right = other.iter();
+
+    loop {
+      match (left.next(), right.next()) {
+        // Lists often share their tails, which saves comparing the rest.
+        (Some(left), Some(right)) if std::ptr::eq(left, right) => return true,
+        (Some(left), Some(right)) if left.head == right.head => {}
+        (None, None) => return true,
+        _ => return false,
+      }
+    }
+  }
+}
 
-    match (self, other) {
-      (Cons(left), Cons(right)) => Rc::ptr_eq(&left, &right),
-      (Nil, Nil) => true,
-      _ => false,
+impl Eq for List {}
+
+impl Hash for List {
+  fn hash<H>(&self, state: &mut H)
+  where
+    H: Hasher,
+  {
+    let mut len = 0;
+    for node in self.iter() {
+      node.head.hash(state);
+      len += 1;
     }
+    state.write_usize(len);
   }
 }
 
@@ -77,15 +122,23 @@ impl fmt::Display for List {
       f,
       "({})",
       self
-        .clone()
-        .into_iter()
-        .map(|expr| format!("{}", expr))
+        .iter()
+        .map(|node| format!("{}", node.head))
         .collect::<Vec<String>>()
         .join(" ")
     )
   }
 }
 
+impl IntoIterator for List {
+  type Item = Expr;
+  type IntoIter = IntoIter;
+
+  fn into_iter(self) -> IntoIter {
+    IntoIter(self)
+  }
+}
+
 pub struct IntoIter(List);
 
 impl Iterator for IntoIter {
@@ -107,3 +160,22 @@ impl Iterator for IntoIter {
     Some(head)
   }
 }
+
+pub struct Iter<'a>(&'a List);
+
+impl<'a> Iterator for Iter<'a> {
+  type Item = &'a Node;
+
+  fn next(&mut self) -> Option<&'a Node> {
+    use List::*;
+
+    let node = match self.0 {
+      Cons(node) => node.as_ref(),
+      Nil => return None,
+    };
+
+    self.0 = &node.tail;
+
+    Some(node)
+  }
+}
@@ -0,0 +1,364 @@
+use std::collections::hash_map::DefaultHasher;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::iter::{FromIterator, Iterator};
+use std::rc::Rc;
+use std::slice;
+
+use super::Expr;
+
+const BITS: usize = 5;
+const MASK: u64 = (1 << BITS) - 1;
+
+/// A persistent hash map, stored as a hash array mapped trie so that lookups
+/// and updates take O(log n) time. Updates return a new map which shares most
+/// of its structure with the old one.
+///
+/// Keys are compared with `==`, so lists and vectors with equal elements are
+/// the same key.
+#[derive(Clone, Default)]
+pub struct Map {
+  len: usize,
+  root: Option<Rc<Trie>>,
+}
+
+enum Trie {
+  Branch {
+    bitmap: u32,
+    children: Vec<Rc<Trie>>,
+  },
+  /// All the entries whose keys have the same hash.
+  Leaf {
+    hash: u64,
+    entries: Vec<(Expr, Expr)>,
+  },
+}
+
+impl Map {
+  pub fn new() -> Map {
+    Map::default()
+  }
+
+  pub fn len(&self) -> usize {
+    self.len
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.len == 0
+  }
+
+  pub fn get(&self, key: &Expr) -> Option<&Expr> {
+    let hash = hash_key(key);
+
+    let mut trie = self.root.as_deref()?;
+    let mut shift = 0;
+    loop {
+      match trie {
+        Trie::Branch { bitmap, children } => {
+          let bit = bit_at(hash, shift);
+          if bitmap & bit == 0 {
+            return None;
+          }
+          trie = children[index_of(*bitmap, bit)].as_ref();
+          shift += BITS;
+        }
+        Trie::Leaf {
+          hash: leaf_hash,
+          entries,
+        } => {
+          if *leaf_hash != hash {
+            return None;
+          }
+          return entries
+            .iter()
+            .find(|(entry_key, _)| entry_key == key)
+            .map(|(_, value)| value);
+        }
+      }
+    }
+  }
+
+  pub fn contains_key(&self, key: &Expr) -> bool {
+    self.get(key).is_some()
+  }
+
+  /// Returns a copy of the map with `key` set to `value`.
+  pub fn insert(&self, key: Expr, value: Expr) -> Map {
+    let hash = hash_key(&key);
+
+    match &self.root {
+      Some(root) => {
+        let (root, is_added) = insert_in(root, 0, hash, key, value);
+        Map {
+          len: if is_added { self.len + 1 } else { self.len },
+          root: Some(Rc::new(root)),
+        }
+      }
+      None => Map {
+        len: 1,
+        root: Some(Rc::new(Trie::Leaf {
+          hash,
+          entries: vec![(key, value)],
+        })),
+      },
+    }
+  }
+
+  /// Returns a copy of the map without `key`.
+  pub fn remove(&self, key: &Expr) -> Map {
+    let hash = hash_key(key);
+
+    let root = match &self.root {
+      Some(root) => root,
+      None => return self.clone(),
+    };
+
+    match remove_in(root, 0, hash, key) {
+      Some(root) => Map {
+        len: self.len - 1,
+        root,
+      },
+      None => self.clone(),
+    }
+  }
+
+  pub fn iter(&self) -> Iter<'_> {
+    Iter {
+      tries: self.root.iter().map(|root| root.as_ref()).collect(),
+      entries: [].iter(),
+    }
+  }
+
+  pub fn keys(&self) -> impl Iterator<Item = &Expr> {
+    self.iter().map(|(key, _)| key)
+  }
+
+  pub fn values(&self) -> impl Iterator<Item = &Expr> {
+    self.iter().map(|(_, value)| value)
+  }
+
+  /// Returns whether both maps are the same instance, rather than just having
+  /// equal entries.
+  pub fn ptr_eq(&self, other: &Map) -> bool {
+    match (&self.root, &other.root) {
+      (Some(left), Some(right)) => Rc::ptr_eq(left, right),
+      (None, None) => true,
+      _ => false,
+    }
+  }
+}
+
+/// Returns the new trie, and whether `key` was newly added.
+fn insert_in(
+  trie: &Rc<Trie>,
+  shift: usize,
+  hash: u64,
+  key: Expr,
+  value: Expr,
+) -> (Trie, bool) {
+  match trie.as_ref() {
+    Trie::Leaf {
+      hash: leaf_hash,
+      entries,
+    } if *leaf_hash == hash => {
+      let mut entries = entries.clone();
+      let is_added =
+        match entries.iter().position(|(entry_key, _)| entry_key == &key) {
+          Some(position) => {
+            entries[position].1 = value;
+            false
+          }
+          None => {
+            entries.push((key, value));
+            true
+          }
+        };
+      (Trie::Leaf { hash, entries }, is_added)
+    }
+    // Push the existing leaf down a level, so that it can sit next to the new
+    // entry.
+    Trie::Leaf {
+      hash: leaf_hash, ..
+    } => {
+      let branch = Rc::new(Trie::Branch {
+        bitmap: bit_at(*leaf_hash, shift),
+        children: vec![trie.clone()],
+      });
+      insert_in(&branch, shift, hash, key, value)
+    }
+    Trie::Branch { bitmap, children } => {
+      let bit = bit_at(hash, shift);
+      let index = index_of(*bitmap, bit);
+      let mut children = children.clone();
+
+      let is_added = if bitmap & bit == 0 {
+        let entries = vec![(key, value)];
+        children.insert(index, Rc::new(Trie::Leaf { hash, entries }));
+        true
+      } else {
+        let (child, is_added) =
+          insert_in(&children[index], shift + BITS, hash, key, value);
+        children[index] = Rc::new(child);
+        is_added
+      };
+
+      let bitmap = bitmap | bit;
+      (Trie::Branch { bitmap, children }, is_added)
+    }
+  }
+}
+
+/// Returns `None` if `key` was not found, or else the new trie, which is
+/// `None` if it became empty.
+fn remove_in(
+  trie: &Rc<Trie>,
+  shift: usize,
+  hash: u64,
+  key: &Expr,
+) -> Option<Option<Rc<Trie>>> {
+  match trie.as_ref() {
+    Trie::Leaf {
+      hash: leaf_hash,
+      entries,
+    } => {
+      if *leaf_hash != hash {
+        return None;
+      }
+      let position =
+        entries.iter().position(|(entry_key, _)| entry_key == key)?;
+
+      if entries.len() == 1 {
+        return Some(None);
+      }
+      let mut entries = entries.clone();
+      entries.remove(position);
+      Some(Some(Rc::new(Trie::Leaf { hash, entries })))
+    }
+    Trie::Branch { bitmap, children } => {
+      let bit = bit_at(hash, shift);
+      if bitmap & bit == 0 {
+        return None;
+      }
+      let index = index_of(*bitmap, bit);
+
+      let child = remove_in(&children[index], shift + BITS, hash, key)?;
+      let mut bitmap = *bitmap;
+      let mut children = children.clone();
+      match child {
+        Some(child) => children[index] = child,
+        None => {
+          children.remove(index);
+          bitmap &= !bit;
+        }
+      }
+
+      // A lone leaf can be moved up, since leaves are found by their hash.
+      match children.as_slice() {
+        [] => Some(None),
+        [child] if matches!(child.as_ref(), Trie::Leaf { .. }) => {
+          Some(Some(child.clone()))
+        }
+        _ => Some(Some(Rc::new(Trie::Branch { bitmap, children }))),
+      }
+    }
+  }
+}
+
+fn bit_at(hash: u64, shift: usize) -> u32 {
+  1 << ((hash >> shift) & MASK)
+}
+
+/// Returns the index of the child for `bit` within the children of a branch.
+fn index_of(bitmap: u32, bit: u32) -> usize {
+  (bitmap & (bit - 1)).count_ones() as usize
+}
+
+fn hash_key(key: &Expr) -> u64 {
+  let mut hasher = DefaultHasher::new();
+  key.hash(&mut hasher);
+  hasher.finish()
+}
+
+impl PartialEq for Map {
+  fn eq(&self, other: &Map) -> bool {
+    self.len == other.len
+      && self
+        .iter()
+        .all(|(key, value)| other.get(key) == Some(value))
+  }
+}
+
+impl Eq for Map {}
+
+impl Hash for Map {
+  fn hash<H>(&self, state: &mut H)
+  where
+    H: Hasher,
+  {
+    // Entries are combined in a way that does not depend on their order.
+    let entries = self
+      .iter()
+      .map(|entry| {
+        let mut hasher = DefaultHasher::new();
+        entry.hash(&mut hasher);
+        hasher.finish()
+      })
+      .fold(0u64, u64::wrapping_add);
+    state.write_u64(entries);
+  }
+}
+
+impl FromIterator<(Expr, Expr)> for Map {
+  fn from_iter<T>(iter: T) -> Map
+  where
+    T: IntoIterator<Item = (Expr, Expr)>,
+  {
+    iter
+      .into_iter()
+      .fold(Map::new(), |map, (key, value)| map.insert(key, value))
+  }
+}
+
+impl fmt::Display for Map {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(
+      f,
+      "{{{}}}",
+      self
+        .iter()
+        .map(|(key, value)| format!("{} {}", key, value))
+        .collect::<Vec<String>>()
+        .join(" ")
+    )
+  }
+}
+
+impl fmt::Debug for Map {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    f.debug_map().entries(self.iter()).finish()
+  }
+}
+
+pub struct Iter<'a> {
+  tries: Vec<&'a Trie>,
+  entries: slice::Iter<'a, (Expr, Expr)>,
+}
+
+impl<'a> Iterator for Iter<'a> {
+  type Item = (&'a Expr, &'a Expr);
+
+  fn next(&mut self) -> Option<(&'a Expr, &'a Expr)> {
+    loop {
+      if let Some((key, value)) = self.entries.next() {
+        return Some((key, value));
+      }
+
+      match self.tries.pop()? {
+        Trie::Branch { children, .. } => self
+          .tries
+          .extend(children.iter().rev().map(|child| child.as_ref())),
+        Trie::Leaf { entries, .. } => self.entries = entries.iter(),
+      }
+    }
+  }
+}
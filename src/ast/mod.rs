@@ -1,32 +1,58 @@
 use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::mem;
 use std::rc::Rc;
-use std::sync::{Arc, Mutex};
-
-use lazy_static::lazy_static;
 
 use crate::env::Frame;
+use crate::eval::bytecode::Closure;
 use crate::eval::EvalError;
 
 pub use self::list::{List, Node};
+pub use self::map::Map;
+pub use self::span::{Position, Span};
+pub(crate) use self::symbol::SymbolTable;
+pub use self::symbol::{Symbol, SymbolMap};
+pub use self::vector::Vector;
 
 pub mod list;
-
-#[derive(Clone, Debug, PartialEq)]
+pub mod map;
+pub mod span;
+pub mod symbol;
+pub mod vector;
+
+/// Expressions are compared and hashed structurally, except for functions,
+/// macros and natives, which are compared by identity. An integer is never
+/// structurally equal to a float, so `1` and `1.0` are different map keys.
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
 pub enum Expr {
   List(List),
   Atom(Atom),
 }
 
 impl Expr {
-  pub fn is_truthy(&self) -> bool {
-    if let Expr::List(List::Nil) = self {
-      false
-    } else {
-      true
+  /// Returns whether conditionals like `if` treat the expression as true.
+  /// `false` is always false, and so is `()` unless `truthiness` is
+  /// `Truthiness::FalseOnly`. Every other value is true.
+  pub fn is_truthy(&self, truthiness: Truthiness) -> bool {
+    match self {
+      Expr::Atom(Atom::Boolean(boolean)) => *boolean,
+      Expr::List(List::Nil) => truthiness == Truthiness::FalseOnly,
+      _ => true,
     }
   }
 }
 
+/// Which values conditionals treat as false.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum Truthiness {
+  /// Both `false` and `()` are false, as `()` was the only false value before
+  /// there were booleans.
+  #[default]
+  FalseAndNil,
+  /// Only `false` is false, so an empty list is as true as any other list.
+  FalseOnly,
+}
+
 impl fmt::Display for Expr {
   fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
     use Expr::*;
@@ -38,26 +64,112 @@ impl fmt::Display for Expr {
   }
 }
 
-#[derive(Clone, Debug, PartialEq)]
+#[derive(Clone, Debug)]
 pub enum Atom {
-  Number(f64),
+  Boolean(bool),
+  Integer(i64),
+  Float(f64),
   Symbol(Symbol),
+  /// A local variable within a function body, resolved before the body is
+  /// evaluated.
+  Address(Address),
+  Keyword(Symbol),
   String(String),
+  Vector(Vector),
+  Map(Map),
   Function(Function),
+  Closure(Closure),
   Macro(Macro),
   Special(Special),
   Native(Native),
 }
 
+impl PartialEq for Atom {
+  fn eq(&self, other: &Atom) -> bool {
+    use Atom::*;
+
+    match (self, other) {
+      (Boolean(left), Boolean(right)) => left == right,
+      (Integer(left), Integer(right)) => left == right,
+      // Unlike `f64`, NaN is equal to itself so that atoms can be `Eq`.
+      (Float(left), Float(right)) => {
+        left == right || (left.is_nan() && right.is_nan())
+      }
+      (Symbol(left), Symbol(right)) => left == right,
+      (Address(left), Address(right)) => left == right,
+      (Keyword(left), Keyword(right)) => left == right,
+      (String(left), String(right)) => left == right,
+      (Vector(left), Vector(right)) => left == right,
+      (Map(left), Map(right)) => left == right,
+      (Function(left), Function(right)) => left == right,
+      (Closure(left), Closure(right)) => left.ptr_eq(right),
+      (Macro(left), Macro(right)) => left == right,
+      (Special(left), Special(right)) => left == right,
+      (Native(left), Native(right)) => left == right,
+      _ => false,
+    }
+  }
+}
+
+impl Eq for Atom {}
+
+impl Hash for Atom {
+  fn hash<H>(&self, state: &mut H)
+  where
+    H: Hasher,
+  {
+    use Atom::*;
+
+    mem::discriminant(self).hash(state);
+
+    match self {
+      Boolean(boolean) => boolean.hash(state),
+      Integer(integer) => integer.hash(state),
+      Float(number) => {
+        // Make sure that numbers which are equal have the same hash.
+        let number = if *number == 0.0 {
+          0.0
+        } else if number.is_nan() {
+          f64::NAN
+        } else {
+          *number
+        };
+        number.to_bits().hash(state);
+      }
+      Symbol(symbol) => symbol.hash(state),
+      Address(address) => address.hash(state),
+      Keyword(keyword) => keyword.hash(state),
+      String(string) => string.hash(state),
+      Vector(vector) => vector.hash(state),
+      Map(map) => map.hash(state),
+      Function(function) => function.hash(state),
+      Closure(closure) => closure.as_ptr().hash(state),
+      Macro(macr) => macr.hash(state),
+      Special(special) => special.hash(state),
+      Native(native) => native.hash(state),
+    }
+  }
+}
+
 impl fmt::Display for Atom {
   fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
     use Atom::*;
 
     match self {
-      Number(number) => write!(f, "{}", number),
+      Boolean(boolean) => write!(f, "{}", boolean),
+      Integer(integer) => write!(f, "{}", integer),
+      Float(number) if number.is_nan() => write!(f, "nan"),
+      // Floats always have a decimal point or exponent, so that `1.0` reads
+      // back as a float rather than as the integer `1`.
+      Float(number) => write!(f, "{:?}", number),
       Symbol(symbol) => write!(f, "{}", symbol),
-      String(string) => write!(f, "\"{}\"", string),
+      Address(address) => write!(f, "{}", address.symbol),
+      Keyword(keyword) => write!(f, ":{}", keyword),
+      String(string) => write_string(f, string),
+      Vector(vector) => write!(f, "{}", vector),
+      Map(map) => write!(f, "{}", map),
       Function(function) => write!(f, "{}", function),
+      Closure(closure) => write!(f, "{}", closure),
       Macro(macr) => write!(f, "{}", macr),
       Special(special) => write!(f, "{}", special),
       Native(native) => write!(f, "{}", native),
@@ -65,51 +177,31 @@ impl fmt::Display for Atom {
   }
 }
 
-lazy_static! {
-  static ref SYMBOLS: Mutex<Vec<Symbol>> = Mutex::new(Vec::new());
-}
-
-lazy_static! {
-  pub static ref SYMBOL_TRUE: Symbol = Symbol::new("true");
+/// Where a local variable is stored, as the slot it has in the frame `depth`
+/// frames up from the one it is used in.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub struct Address {
+  pub symbol: Symbol,
+  pub depth: usize,
+  pub slot: usize,
 }
 
-#[derive(Clone, Debug, Eq, Ord, PartialEq, PartialOrd)]
-pub struct Symbol {
-  inner: Arc<String>,
-}
-
-impl Symbol {
-  pub fn new<S>(symbol: S) -> Symbol
-  where
-    S: Into<String>,
-  {
-    let symbol = symbol.into();
-
-    if let Some(symbol) = SYMBOLS
-      .lock()
-      .unwrap()
-      .iter()
-      .find(|s| s.inner.as_ref() == &symbol)
-    {
-      return symbol.clone();
+/// Writes `string` quoted, escaping it so that it reads back as the same
+/// string.
+fn write_string(f: &mut fmt::Formatter<'_>, string: &str) -> fmt::Result {
+  write!(f, "\"")?;
+  for char in string.chars() {
+    match char {
+      '"' => write!(f, "\\\"")?,
+      '\\' => write!(f, "\\\\")?,
+      '\n' => write!(f, "\\n")?,
+      '\t' => write!(f, "\\t")?,
+      '\r' => write!(f, "\\r")?,
+      char if char.is_control() => write!(f, "\\u{{{:x}}}", char as u32)?,
+      char => write!(f, "{}", char)?,
     }
-
-    let symbol = Symbol {
-      inner: Arc::new(symbol),
-    };
-    SYMBOLS.lock().unwrap().push(symbol.clone());
-    symbol
-  }
-
-  pub fn as_str(&self) -> &str {
-    self.inner.as_ref()
-  }
-}
-
-impl fmt::Display for Symbol {
-  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-    write!(f, "{}", self.inner.as_str())
   }
+  write!(f, "\"")
 }
 
 #[derive(Clone)]
@@ -119,17 +211,29 @@ pub struct Function {
 
 pub struct FunctionInner {
   pub frame: Frame,
-  pub parameters: Vec<Symbol>,
+  pub parameters: Parameters,
+  /// The names of the slots in the frame of each call.
+  pub locals: Rc<[Symbol]>,
   pub body: Expr,
+  /// Where `body` was read from, if it came from source code.
+  pub span: Option<Span>,
 }
 
 impl Function {
-  pub fn new(frame: Frame, parameters: Vec<Symbol>, body: Expr) -> Function {
+  pub fn new(
+    frame: Frame,
+    parameters: Parameters,
+    locals: Rc<[Symbol]>,
+    body: Expr,
+    span: Option<Span>,
+  ) -> Function {
     Function {
       inner: Rc::new(FunctionInner {
         frame,
         parameters,
+        locals,
         body,
+        span,
       }),
     }
   }
@@ -138,13 +242,29 @@ impl Function {
     &self.inner.frame
   }
 
-  pub fn parameters(&self) -> &[Symbol] {
+  pub fn parameters(&self) -> &Parameters {
     &self.inner.parameters
   }
 
+  pub fn locals(&self) -> &Rc<[Symbol]> {
+    &self.inner.locals
+  }
+
   pub fn body(&self) -> &Expr {
     &self.inner.body
   }
+
+  pub fn span(&self) -> Option<Span> {
+    self.inner.span
+  }
+
+  pub fn as_ptr(&self) -> *const () {
+    Rc::as_ptr(&self.inner) as *const ()
+  }
+
+  pub fn strong_count(&self) -> usize {
+    Rc::strong_count(&self.inner)
+  }
 }
 
 impl PartialEq for Function {
@@ -153,6 +273,17 @@ impl PartialEq for Function {
   }
 }
 
+impl Eq for Function {}
+
+impl Hash for Function {
+  fn hash<H>(&self, state: &mut H)
+  where
+    H: Hasher,
+  {
+    Rc::as_ptr(&self.inner).hash(state);
+  }
+}
+
 impl fmt::Display for Function {
   fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
     write!(f, "Function")
@@ -169,12 +300,150 @@ impl fmt::Debug for Function {
   }
 }
 
+/// The parameter list of a function, written as
+/// `(required (optional default) & rest :keyword (:keyword default))`.
+#[derive(Clone, Debug, Default)]
+pub struct Parameters {
+  pub required: Vec<Symbol>,
+  pub optional: Vec<(Symbol, Expr)>,
+  pub rest: Option<Symbol>,
+  pub keyword: Vec<(Symbol, Expr)>,
+}
+
+impl Parameters {
+  /// Iterates over the parameter names along with their defaults, in the
+  /// order required, optional, rest and then keyword parameters.
+  pub fn iter(&self) -> impl Iterator<Item = (&Symbol, Option<&Expr>)> {
+    let required = self.required.iter().map(|name| (name, None));
+    let optional = self
+      .optional
+      .iter()
+      .map(|(name, default)| (name, Some(default)));
+    let rest = self.rest.iter().map(|name| (name, None));
+    let keyword = self
+      .keyword
+      .iter()
+      .map(|(name, default)| (name, Some(default)));
+
+    required.chain(optional).chain(rest).chain(keyword)
+  }
+
+  /// Matches `arguments` up with the parameters, returning the argument for
+  /// each parameter in the same order as `iter`. Parameters that weren't
+  /// passed an argument are `None`, and should be set to their default.
+  pub fn bind(
+    &self,
+    mut arguments: Vec<Expr>,
+  ) -> Result<Vec<Option<Expr>>, EvalError> {
+    use EvalError::*;
+
+    // Pull keyword arguments out from between the positional ones.
+    let mut keywords = Vec::new();
+    if !self.keyword.is_empty() {
+      let mut positional = Vec::new();
+      let mut arguments_iter = arguments.into_iter();
+      while let Some(argument) = arguments_iter.next() {
+        let keyword = match argument {
+          Expr::Atom(Atom::Keyword(keyword)) => keyword,
+          argument => {
+            positional.push(argument);
+            continue;
+          }
+        };
+        if !self.keyword.iter().any(|(name, _)| name == &keyword) {
+          return Err(UnknownKeyword(keyword));
+        }
+        match arguments_iter.next() {
+          Some(argument) => keywords.push((keyword, argument)),
+          None => return Err(MissingKeywordValue(keyword)),
+        };
+      }
+      arguments = positional;
+    }
+
+    let arity = self.arity();
+    if !arity.accepts(arguments.len()) {
+      return Err(WrongArgumentCount(arity, arguments.len()));
+    }
+
+    let mut arguments = arguments.into_iter();
+    let mut bound = Vec::new();
+
+    bound.extend(self.required.iter().map(|_| arguments.next()));
+    bound.extend(self.optional.iter().map(|_| arguments.next()));
+    if self.rest.is_some() {
+      let rest = arguments
+        .rev()
+        .fold(List::Nil, |list, argument| List::cons(argument, list));
+      bound.push(Some(Expr::List(rest)));
+    }
+    for (name, _) in self.keyword.iter() {
+      // Later arguments for the same keyword win.
+      let argument = keywords
+        .iter()
+        .rposition(|(keyword, _)| keyword == name)
+        .map(|position| keywords.swap_remove(position).1);
+      bound.push(argument);
+    }
+
+    Ok(bound)
+  }
+
+  pub fn arity(&self) -> Arity {
+    use Arity::*;
+
+    let required = self.required.len();
+    let optional = self.optional.len();
+
+    if self.rest.is_some() {
+      AtLeast(required)
+    } else if optional > 0 {
+      Between(required, required + optional)
+    } else {
+      Exactly(required)
+    }
+  }
+}
+
+/// The number of positional arguments a function accepts.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Arity {
+  Exactly(usize),
+  AtLeast(usize),
+  Between(usize, usize),
+}
+
+impl Arity {
+  pub fn accepts(&self, count: usize) -> bool {
+    use Arity::*;
+
+    match *self {
+      Exactly(expected) => count == expected,
+      AtLeast(min) => count >= min,
+      Between(min, max) => count >= min && count <= max,
+    }
+  }
+}
+
+impl fmt::Display for Arity {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    use Arity::*;
+
+    match self {
+      Exactly(expected) => write!(f, "{}", expected),
+      AtLeast(min) => write!(f, "at least {}", min),
+      Between(min, max) => write!(f, "{} to {}", min, max),
+    }
+  }
+}
+
 #[derive(Clone)]
 pub struct Macro {
   inner: Rc<MacroInner>,
 }
 
 pub struct MacroInner {
+  pub frame: Option<Frame>,
   pub parameter: Symbol,
   pub body: Expr,
 }
@@ -182,10 +451,34 @@ pub struct MacroInner {
 impl Macro {
   pub fn new(parameter: Symbol, body: Expr) -> Macro {
     Macro {
-      inner: Rc::new(MacroInner { parameter, body }),
+      inner: Rc::new(MacroInner {
+        frame: None,
+        parameter,
+        body,
+      }),
+    }
+  }
+
+  /// Creates a hygienic macro, which is expanded in `frame` and whose
+  /// expansions cannot capture symbols at the call site.
+  pub fn hygienic(frame: Frame, parameter: Symbol, body: Expr) -> Macro {
+    Macro {
+      inner: Rc::new(MacroInner {
+        frame: Some(frame),
+        parameter,
+        body,
+      }),
     }
   }
 
+  pub fn frame(&self) -> Option<&Frame> {
+    self.inner.frame.as_ref()
+  }
+
+  pub fn is_hygienic(&self) -> bool {
+    self.inner.frame.is_some()
+  }
+
   pub fn parameter(&self) -> &Symbol {
     &self.inner.parameter
   }
@@ -193,6 +486,14 @@ impl Macro {
   pub fn body(&self) -> &Expr {
     &self.inner.body
   }
+
+  pub fn as_ptr(&self) -> *const () {
+    Rc::as_ptr(&self.inner) as *const ()
+  }
+
+  pub fn strong_count(&self) -> usize {
+    Rc::strong_count(&self.inner)
+  }
 }
 
 impl PartialEq for Macro {
@@ -201,6 +502,17 @@ impl PartialEq for Macro {
   }
 }
 
+impl Eq for Macro {}
+
+impl Hash for Macro {
+  fn hash<H>(&self, state: &mut H)
+  where
+    H: Hasher,
+  {
+    Rc::as_ptr(&self.inner).hash(state);
+  }
+}
+
 impl fmt::Display for Macro {
   fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
     write!(f, "Macro")
@@ -211,30 +523,86 @@ impl fmt::Debug for Macro {
   fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
     write!(
       f,
-      "Macro {{ parameter: {:?}, body: {:?} }}",
-      self.inner.parameter, self.inner.body
+      "Macro {{ hygienic: {:?}, parameter: {:?}, body: {:?} }}",
+      self.is_hygienic(),
+      self.inner.parameter,
+      self.inner.body
     )
   }
 }
 
-#[derive(Clone, Debug, PartialEq)]
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
 pub enum Special {
   Begin,
   Define,
   Function,
   Macro,
+  Syntax,
   If,
   Quote,
+  Quasiquote,
+  Unquote,
+  UnquoteSplicing,
+  MacroExpand,
+  MacroExpandOnce,
+  Load,
+  Import,
+  Export,
+  Try,
+  Let,
+  LetStar,
+  Letrec,
+  Set,
+  And,
+  Or,
+  Cond,
+  Case,
+  When,
+  Unless,
+  Match,
   Operator(Operator),
 }
 
 impl fmt::Display for Special {
   fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-    write!(f, "Special")
+    use Special::*;
+
+    let name = match self {
+      Begin => "begin",
+      Define => "define",
+      Function => "function",
+      Macro => "macro",
+      Syntax => "syntax",
+      If => "if",
+      Quote => "quote",
+      Quasiquote => "quasiquote",
+      Unquote => "unquote",
+      UnquoteSplicing => "unquote-splicing",
+      MacroExpand => "macroexpand",
+      MacroExpandOnce => "macroexpand-1",
+      Load => "load",
+      Import => "import",
+      Export => "export",
+      Try => "try",
+      Let => "let",
+      LetStar => "let*",
+      Letrec => "letrec",
+      Set => "set!",
+      And => "and",
+      Or => "or",
+      Cond => "cond",
+      Case => "case",
+      When => "when",
+      Unless => "unless",
+      Match => "match",
+      Operator(operator) => return write!(f, "{}", operator),
+    };
+
+    write!(f, "{}", name)
   }
 }
 
-#[derive(Clone, Debug, PartialEq)]
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
 pub enum Operator {
   Add,
   Sub,
@@ -243,7 +611,46 @@ pub enum Operator {
   Mod,
   Gt,
   Lt,
+  Ge,
+  Le,
   Eq,
+  Ne,
+  IntDiv,
+  BitAnd,
+  BitOr,
+  BitXor,
+  BitNot,
+  Shl,
+  Shr,
+}
+
+impl fmt::Display for Operator {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    use Operator::*;
+
+    let name = match self {
+      Add => "+",
+      Sub => "-",
+      Mul => "*",
+      Div => "/",
+      Mod => "%",
+      Gt => ">",
+      Lt => "<",
+      Ge => ">=",
+      Le => "<=",
+      Eq => "=",
+      Ne => "!=",
+      IntDiv => "//",
+      BitAnd => "bit-and",
+      BitOr => "bit-or",
+      BitXor => "bit-xor",
+      BitNot => "bit-not",
+      Shl => "shift-left",
+      Shr => "shift-right",
+    };
+
+    write!(f, "{}", name)
+  }
 }
 
 #[derive(Clone)]
@@ -252,7 +659,10 @@ pub struct Native {
 }
 
 impl Native {
-  pub fn new(native: NativeFn) -> Native {
+  pub fn new<F>(native: F) -> Native
+  where
+    F: Fn(Vec<Expr>) -> Result<Expr, EvalError> + 'static,
+  {
     Native {
       inner: Rc::new(native),
     }
@@ -269,6 +679,17 @@ impl PartialEq for Native {
   }
 }
 
+impl Eq for Native {}
+
+impl Hash for Native {
+  fn hash<H>(&self, state: &mut H)
+  where
+    H: Hasher,
+  {
+    (Rc::as_ptr(&self.inner) as *const ()).hash(state);
+  }
+}
+
 impl fmt::Display for Native {
   fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
     write!(f, "Native Function")
@@ -281,4 +702,4 @@ impl fmt::Debug for Native {
   }
 }
 
-pub type NativeFn = fn(arguments: Vec<Expr>) -> Result<Expr, EvalError>;
+pub type NativeFn = dyn Fn(Vec<Expr>) -> Result<Expr, EvalError>;
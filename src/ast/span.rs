@@ -0,0 +1,61 @@
+use std::fmt;
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Position {
+  pub line: usize,
+  pub column: usize,
+  pub offset: usize,
+}
+
+impl Position {
+  pub fn start() -> Position {
+    Position {
+      line: 1,
+      column: 1,
+      offset: 0,
+    }
+  }
+
+  pub fn advance(&mut self, char: char) {
+    self.offset += char.len_utf8();
+    if char == '\n' {
+      self.line += 1;
+      self.column = 1;
+    } else {
+      self.column += 1;
+    }
+  }
+}
+
+impl fmt::Display for Position {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(f, "{}:{}", self.line, self.column)
+  }
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Span {
+  pub start: Position,
+  pub end: Position,
+}
+
+impl Span {
+  pub fn new(start: Position, end: Position) -> Span {
+    Span { start, end }
+  }
+}
+
+impl From<Position> for Span {
+  fn from(position: Position) -> Span {
+    let mut end = position;
+    end.column += 1;
+    end.offset += 1;
+    Span::new(position, end)
+  }
+}
+
+impl fmt::Display for Span {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(f, "{}", self.start)
+  }
+}
@@ -0,0 +1,351 @@
+use std::cell::RefCell;
+use std::cmp::Ordering;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::hash::{BuildHasherDefault, Hash, Hasher};
+use std::marker::PhantomData;
+use std::rc::Rc;
+use std::sync::atomic::{self, AtomicU32};
+
+static GENSYM_COUNT: AtomicU32 = AtomicU32::new(1);
+static MARK_COUNT: AtomicU32 = AtomicU32::new(1);
+
+thread_local! {
+  static INTERNER: RefCell<Interner> = RefCell::new(Interner::new());
+}
+
+/// Maps the names of symbols to their ids and back. Every thread has its own
+/// interner, so interning never waits on a lock.
+///
+/// Names interned while a symbol table is entered belong to that table, and
+/// are freed once every table using them has been dropped, so short-lived
+/// evaluators don't leak the names they used. Names interned outside of any
+/// table are kept for the life of the thread. Freed ids are reused, so each
+/// id also has a generation, which tells symbols using a freed name apart
+/// from those using the name which replaced it.
+struct Interner {
+  ids: HashMap<Rc<str>, u32>,
+  slots: Vec<Slot>,
+  free: Vec<u32>,
+  /// The ids used by each symbol table which is still alive.
+  tables: HashMap<u32, HashSet<u32>>,
+  /// The symbol tables which are entered, innermost last.
+  entered: Vec<u32>,
+  next_table: u32,
+}
+
+struct Slot {
+  /// The name using the id, or `None` if the id is free.
+  name: Option<Rc<str>>,
+  generation: u32,
+  /// How many symbol tables use the name, or `None` if it is kept for the
+  /// life of the thread.
+  tables: Option<u32>,
+}
+
+impl Interner {
+  fn new() -> Interner {
+    Interner {
+      ids: HashMap::new(),
+      slots: Vec::new(),
+      free: Vec::new(),
+      tables: HashMap::new(),
+      entered: Vec::new(),
+      next_table: 0,
+    }
+  }
+
+  fn intern(&mut self, name: &str) -> (u32, u32) {
+    let id = match self.ids.get(name) {
+      Some(id) => *id,
+      None => self.insert(name),
+    };
+
+    let slot = &mut self.slots[id as usize];
+    match self.entered.last() {
+      Some(table) => {
+        let used = self.tables.get_mut(table).unwrap();
+        if used.insert(id) {
+          slot.tables = slot.tables.map(|tables| tables + 1);
+        }
+      }
+      None => slot.tables = None,
+    }
+    (id, slot.generation)
+  }
+
+  fn insert(&mut self, name: &str) -> u32 {
+    let name: Rc<str> = Rc::from(name);
+    let id = match self.free.pop() {
+      Some(id) => {
+        self.slots[id as usize].name = Some(name.clone());
+        self.slots[id as usize].tables = Some(0);
+        id
+      }
+      None => {
+        self.slots.push(Slot {
+          name: Some(name.clone()),
+          generation: 0,
+          tables: Some(0),
+        });
+        self.slots.len() as u32 - 1
+      }
+    };
+    self.ids.insert(name, id);
+    id
+  }
+
+  /// Stops `table` from using its names, freeing those no other table uses.
+  fn release(&mut self, table: u32) {
+    for id in self.tables.remove(&table).unwrap_or_default() {
+      let slot = &mut self.slots[id as usize];
+      match slot.tables {
+        Some(1) => {
+          self.ids.remove(slot.name.take().unwrap().as_ref());
+          slot.generation = slot.generation.wrapping_add(1);
+          slot.tables = Some(0);
+          self.free.push(id);
+        }
+        Some(tables) => slot.tables = Some(tables - 1),
+        None => {}
+      }
+    }
+  }
+}
+
+/// The names interned by an evaluator. Names are only added to the table
+/// while it is entered, and are freed when it is dropped, unless another table
+/// uses them too.
+pub(crate) struct SymbolTable {
+  id: u32,
+  _thread: PhantomData<*const ()>,
+}
+
+impl SymbolTable {
+  pub fn new() -> SymbolTable {
+    let id = INTERNER.with(|interner| {
+      let mut interner = interner.borrow_mut();
+      let id = interner.next_table;
+      interner.next_table += 1;
+      interner.tables.insert(id, HashSet::new());
+      id
+    });
+    SymbolTable {
+      id,
+      _thread: PhantomData,
+    }
+  }
+
+  /// Adds the names interned on this thread to the table, until the returned
+  /// guard is dropped. Entering a table which is already the innermost one
+  /// does nothing, so evaluators can enter their table on every call.
+  pub fn enter(&self) -> Entered {
+    INTERNER.with(|interner| {
+      let mut interner = interner.borrow_mut();
+      if interner.entered.last() == Some(&self.id) {
+        return Entered { table: None };
+      }
+      interner.entered.push(self.id);
+      Entered {
+        table: Some(self.id),
+      }
+    })
+  }
+
+  /// Keeps the names in the table for the life of the thread, so that symbols
+  /// using them can outlive it.
+  pub fn keep(&self) {
+    INTERNER.with(|interner| {
+      let mut interner = interner.borrow_mut();
+      let used = interner.tables.insert(self.id, HashSet::new());
+      for id in used.unwrap_or_default() {
+        interner.slots[id as usize].tables = None;
+      }
+    });
+  }
+}
+
+impl Drop for SymbolTable {
+  fn drop(&mut self) {
+    // The interner is already gone if the thread is exiting.
+    let _ =
+      INTERNER.try_with(|interner| interner.borrow_mut().release(self.id));
+  }
+}
+
+/// Leaves a symbol table when dropped.
+pub(crate) struct Entered {
+  table: Option<u32>,
+}
+
+impl Drop for Entered {
+  fn drop(&mut self) {
+    if let Some(table) = self.table {
+      let _ = INTERNER.try_with(|interner| {
+        let popped = interner.borrow_mut().entered.pop();
+        debug_assert_eq!(popped, Some(table));
+      });
+    }
+  }
+}
+
+/// An interned name, which is cheap to copy, compare and hash.
+///
+/// Symbols created by `gensym` and `mark` share the name of the symbol they
+/// were created from, and are told apart by their tag and mark respectively.
+/// Since the ids are only meaningful to the thread that interned them,
+/// symbols can't be sent to other threads.
+#[derive(Clone, Copy)]
+pub struct Symbol {
+  id: u32,
+  /// Which use of the id this is, since ids are reused once their names are
+  /// freed.
+  generation: u32,
+  /// Which gensym this is, or 0 if it isn't one.
+  tag: u32,
+  /// Which call to `mark` this was created by, or 0 if it wasn't.
+  mark: u32,
+  _thread: PhantomData<*const ()>,
+}
+
+impl Symbol {
+  pub fn new<S>(name: S) -> Symbol
+  where
+    S: AsRef<str>,
+  {
+    let (id, generation) =
+      INTERNER.with(|interner| interner.borrow_mut().intern(name.as_ref()));
+    Symbol {
+      id,
+      generation,
+      tag: 0,
+      mark: 0,
+      _thread: PhantomData,
+    }
+  }
+
+  /// Creates a new symbol that is distinct from every other symbol, including
+  /// those that can be read from source code.
+  pub fn gensym(prefix: &str) -> Symbol {
+    let tag = GENSYM_COUNT.fetch_add(1, atomic::Ordering::Relaxed);
+    Symbol {
+      tag,
+      ..Symbol::new(prefix)
+    }
+  }
+
+  /// Returns a copy of the symbol which is equal to it, but which can be told
+  /// apart from it using `is_same`.
+  pub fn mark(&self) -> Symbol {
+    let mark = MARK_COUNT.fetch_add(1, atomic::Ordering::Relaxed);
+    Symbol { mark, ..*self }
+  }
+
+  /// Returns the symbol as it was before being marked.
+  pub fn unmark(&self) -> Symbol {
+    Symbol { mark: 0, ..*self }
+  }
+
+  /// Returns whether both symbols are the same instance, rather than just
+  /// being equal.
+  pub fn is_same(&self, other: &Symbol) -> bool {
+    self == other && self.mark == other.mark
+  }
+
+  /// Returns the name the symbol was interned with, which doesn't include the
+  /// tag of a gensym. Symbols which outlived the evaluator that interned them
+  /// no longer have a name, and return `#<freed>`.
+  pub fn name(&self) -> Rc<str> {
+    INTERNER.with(|interner| {
+      let slot = &interner.borrow().slots[self.id as usize];
+      match &slot.name {
+        Some(name) if slot.generation == self.generation => name.clone(),
+        _ => Rc::from("#<freed>"),
+      }
+    })
+  }
+
+  /// Returns how many names are interned on the current thread.
+  pub fn interned_count() -> usize {
+    INTERNER.with(|interner| interner.borrow().ids.len())
+  }
+}
+
+impl PartialEq for Symbol {
+  fn eq(&self, other: &Symbol) -> bool {
+    self.id == other.id
+      && self.generation == other.generation
+      && self.tag == other.tag
+  }
+}
+
+impl Eq for Symbol {}
+
+impl Hash for Symbol {
+  fn hash<H>(&self, state: &mut H)
+  where
+    H: Hasher,
+  {
+    state.write_u64((self.id as u64) << 32 | self.tag as u64);
+  }
+}
+
+/// Symbols are ordered by their ids, rather than by name.
+impl Ord for Symbol {
+  fn cmp(&self, other: &Symbol) -> Ordering {
+    (self.id, self.generation, self.tag).cmp(&(
+      other.id,
+      other.generation,
+      other.tag,
+    ))
+  }
+}
+
+impl PartialOrd for Symbol {
+  fn partial_cmp(&self, other: &Symbol) -> Option<Ordering> {
+    Some(self.cmp(other))
+  }
+}
+
+impl fmt::Display for Symbol {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(f, "{}", self.name())?;
+    if self.tag != 0 {
+      write!(f, "#{}", self.tag)?;
+    }
+    Ok(())
+  }
+}
+
+impl fmt::Debug for Symbol {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    f.debug_tuple("Symbol").field(&self.to_string()).finish()
+  }
+}
+
+/// A map keyed by symbols, which hashes their ids without the overhead of a
+/// general purpose hasher.
+pub type SymbolMap<V> = HashMap<Symbol, V, BuildHasherDefault<SymbolHasher>>;
+
+#[derive(Default)]
+pub struct SymbolHasher {
+  hash: u64,
+}
+
+impl Hasher for SymbolHasher {
+  fn finish(&self) -> u64 {
+    self.hash
+  }
+
+  fn write(&mut self, bytes: &[u8]) {
+    for byte in bytes {
+      self.write_u64(*byte as u64);
+    }
+  }
+
+  fn write_u64(&mut self, integer: u64) {
+    // The multiplier from FxHash, which spreads the bits of small integers.
+    self.hash = (self.hash.rotate_left(5) ^ integer)
+      .wrapping_mul(0x51_7c_c1_b7_27_22_0a_95);
+  }
+}
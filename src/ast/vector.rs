@@ -0,0 +1,237 @@
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::iter::{FromIterator, Iterator};
+use std::rc::Rc;
+
+use super::Expr;
+
+const BITS: usize = 5;
+const WIDTH: usize = 1 << BITS;
+const MASK: usize = WIDTH - 1;
+
+/// A persistent vector, stored as a tree with 32 children per node so that
+/// indexing and updating take O(log n) time. Updates return a new vector which
+/// shares most of its structure with the old one.
+#[derive(Clone)]
+pub struct Vector {
+  len: usize,
+  shift: usize,
+  root: Rc<Chunk>,
+}
+
+#[derive(Clone)]
+enum Chunk {
+  Branch(Vec<Rc<Chunk>>),
+  Leaf(Vec<Expr>),
+}
+
+impl Vector {
+  pub fn new() -> Vector {
+    Vector {
+      len: 0,
+      shift: 0,
+      root: Rc::new(Chunk::Leaf(Vec::new())),
+    }
+  }
+
+  pub fn len(&self) -> usize {
+    self.len
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.len == 0
+  }
+
+  pub fn get(&self, index: usize) -> Option<&Expr> {
+    use Chunk::*;
+
+    if index >= self.len {
+      return None;
+    }
+
+    let mut chunk = self.root.as_ref();
+    let mut shift = self.shift;
+    loop {
+      match chunk {
+        Branch(children) => {
+          chunk = children[(index >> shift) & MASK].as_ref();
+          shift -= BITS;
+        }
+        Leaf(exprs) => return exprs.get(index & MASK),
+      }
+    }
+  }
+
+  /// Returns a copy of the vector with the element at `index` replaced, or
+  /// `None` if `index` is out of bounds.
+  pub fn set(&self, index: usize, expr: Expr) -> Option<Vector> {
+    if index >= self.len {
+      return None;
+    }
+
+    Some(Vector {
+      len: self.len,
+      shift: self.shift,
+      root: Rc::new(set_in(&self.root, self.shift, index, expr)),
+    })
+  }
+
+  /// Returns a copy of the vector with `expr` appended.
+  pub fn push(&self, expr: Expr) -> Vector {
+    use Chunk::*;
+
+    // Grow the tree by a level once the root is full.
+    if self.len == 1 << (self.shift + BITS) {
+      let path = new_path(self.shift, expr);
+      return Vector {
+        len: self.len + 1,
+        shift: self.shift + BITS,
+        root: Rc::new(Branch(vec![self.root.clone(), Rc::new(path)])),
+      };
+    }
+
+    Vector {
+      len: self.len + 1,
+      shift: self.shift,
+      root: Rc::new(push_in(&self.root, self.shift, self.len, expr)),
+    }
+  }
+
+  pub fn iter(&self) -> Iter<'_> {
+    Iter {
+      vector: self,
+      index: 0,
+    }
+  }
+
+  /// Returns whether both vectors are the same instance, rather than just
+  /// having equal elements.
+  pub fn ptr_eq(&self, other: &Vector) -> bool {
+    self.len == other.len && Rc::ptr_eq(&self.root, &other.root)
+  }
+}
+
+fn set_in(chunk: &Chunk, shift: usize, index: usize, expr: Expr) -> Chunk {
+  use Chunk::*;
+
+  match chunk {
+    Branch(children) => {
+      let mut children = children.clone();
+      let child = (index >> shift) & MASK;
+      children[child] =
+        Rc::new(set_in(&children[child], shift - BITS, index, expr));
+      Branch(children)
+    }
+    Leaf(exprs) => {
+      let mut exprs = exprs.clone();
+      exprs[index & MASK] = expr;
+      Leaf(exprs)
+    }
+  }
+}
+
+fn push_in(chunk: &Chunk, shift: usize, index: usize, expr: Expr) -> Chunk {
+  use Chunk::*;
+
+  match chunk {
+    Branch(children) => {
+      let mut children = children.clone();
+      let child = (index >> shift) & MASK;
+      if child < children.len() {
+        children[child] =
+          Rc::new(push_in(&children[child], shift - BITS, index, expr));
+      } else {
+        children.push(Rc::new(new_path(shift - BITS, expr)));
+      }
+      Branch(children)
+    }
+    Leaf(exprs) => {
+      let mut exprs = exprs.clone();
+      exprs.push(expr);
+      Leaf(exprs)
+    }
+  }
+}
+
+/// Builds a chain of chunks down to a leaf holding only `expr`.
+fn new_path(shift: usize, expr: Expr) -> Chunk {
+  use Chunk::*;
+
+  if shift == 0 {
+    Leaf(vec![expr])
+  } else {
+    Branch(vec![Rc::new(new_path(shift - BITS, expr))])
+  }
+}
+
+impl Default for Vector {
+  fn default() -> Vector {
+    Vector::new()
+  }
+}
+
+impl PartialEq for Vector {
+  fn eq(&self, other: &Vector) -> bool {
+    self.len == other.len && self.iter().eq(other.iter())
+  }
+}
+
+impl Eq for Vector {}
+
+impl Hash for Vector {
+  fn hash<H>(&self, state: &mut H)
+  where
+    H: Hasher,
+  {
+    for expr in self.iter() {
+      expr.hash(state);
+    }
+    state.write_usize(self.len);
+  }
+}
+
+impl FromIterator<Expr> for Vector {
+  fn from_iter<T>(iter: T) -> Vector
+  where
+    T: IntoIterator<Item = Expr>,
+  {
+    iter
+      .into_iter()
+      .fold(Vector::new(), |vector, expr| vector.push(expr))
+  }
+}
+
+impl fmt::Display for Vector {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(
+      f,
+      "[{}]",
+      self
+        .iter()
+        .map(|expr| format!("{}", expr))
+        .collect::<Vec<String>>()
+        .join(" ")
+    )
+  }
+}
+
+impl fmt::Debug for Vector {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    f.debug_list().entries(self.iter()).finish()
+  }
+}
+
+pub struct Iter<'a> {
+  vector: &'a Vector,
+  index: usize,
+}
+
+impl<'a> Iterator for Iter<'a> {
+  type Item = &'a Expr;
+
+  fn next(&mut self) -> Option<&'a Expr> {
+    let expr = self.vector.get(self.index)?;
+    self.index += 1;
+    Some(expr)
+  }
+}
@@ -0,0 +1,312 @@
+use std::convert::TryFrom;
+
+use crate::ast::{
+  Atom, Expr, Function, List, Map, Native, Symbol, Truthiness, Vector,
+};
+use crate::eval::EvalError;
+
+/// Converts a Rust value into a Zuko expression.
+pub trait IntoExpr {
+  fn into_expr(self) -> Expr;
+}
+
+/// Converts a Zuko expression into a Rust value, failing with
+/// `EvalError::InvalidType` if the expression has the wrong type.
+pub trait FromExpr: Sized {
+  fn from_expr(expr: Expr) -> Result<Self, EvalError>;
+}
+
+/// Converts a group of Rust values into the arguments of a Zuko function
+/// call. Implemented for tuples of `IntoExpr` values and for `Vec<Expr>`.
+pub trait IntoArguments {
+  fn into_arguments(self) -> Vec<Expr>;
+}
+
+/// Converts the arguments of a native function call into a group of Rust
+/// values. Implemented for tuples of `FromExpr` values.
+pub trait FromArguments: Sized {
+  fn from_arguments(arguments: Vec<Expr>) -> Result<Self, EvalError>;
+}
+
+impl IntoExpr for Expr {
+  fn into_expr(self) -> Expr {
+    self
+  }
+}
+
+impl FromExpr for Expr {
+  fn from_expr(expr: Expr) -> Result<Expr, EvalError> {
+    Ok(expr)
+  }
+}
+
+impl IntoExpr for () {
+  fn into_expr(self) -> Expr {
+    Expr::List(List::Nil)
+  }
+}
+
+impl FromExpr for () {
+  fn from_expr(_: Expr) -> Result<(), EvalError> {
+    Ok(())
+  }
+}
+
+impl IntoExpr for bool {
+  fn into_expr(self) -> Expr {
+    Expr::Atom(Atom::Boolean(self))
+  }
+}
+
+/// Since there is no evaluator to ask, values are converted using the default
+/// truthiness.
+impl FromExpr for bool {
+  fn from_expr(expr: Expr) -> Result<bool, EvalError> {
+    Ok(expr.is_truthy(Truthiness::default()))
+  }
+}
+
+impl IntoExpr for f64 {
+  fn into_expr(self) -> Expr {
+    Expr::Atom(Atom::Float(self))
+  }
+}
+
+impl FromExpr for f64 {
+  fn from_expr(expr: Expr) -> Result<f64, EvalError> {
+    match expr {
+      Expr::Atom(Atom::Integer(integer)) => Ok(integer as f64),
+      Expr::Atom(Atom::Float(float)) => Ok(float),
+      _ => Err(EvalError::InvalidType),
+    }
+  }
+}
+
+macro_rules! impl_integer {
+  ($($integer:ty),*) => {
+    $(
+      impl IntoExpr for $integer {
+        fn into_expr(self) -> Expr {
+          Expr::Atom(Atom::Integer(self as i64))
+        }
+      }
+
+      impl FromExpr for $integer {
+        fn from_expr(expr: Expr) -> Result<$integer, EvalError> {
+          match expr {
+            Expr::Atom(Atom::Integer(integer)) => {
+              <$integer>::try_from(integer).map_err(|_| EvalError::IntegerOverflow)
+            }
+            _ => Err(EvalError::InvalidType),
+          }
+        }
+      }
+    )*
+  };
+}
+
+impl_integer!(i32, i64, u32, u64, usize);
+
+impl IntoExpr for String {
+  fn into_expr(self) -> Expr {
+    Expr::Atom(Atom::String(self))
+  }
+}
+
+impl IntoExpr for &str {
+  fn into_expr(self) -> Expr {
+    Expr::Atom(Atom::String(self.to_string()))
+  }
+}
+
+impl FromExpr for String {
+  fn from_expr(expr: Expr) -> Result<String, EvalError> {
+    match expr {
+      Expr::Atom(Atom::String(string)) => Ok(string),
+      _ => Err(EvalError::InvalidType),
+    }
+  }
+}
+
+impl IntoExpr for Symbol {
+  fn into_expr(self) -> Expr {
+    Expr::Atom(Atom::Symbol(self))
+  }
+}
+
+impl FromExpr for Symbol {
+  fn from_expr(expr: Expr) -> Result<Symbol, EvalError> {
+    match expr {
+      Expr::Atom(Atom::Symbol(symbol)) => Ok(symbol),
+      _ => Err(EvalError::InvalidType),
+    }
+  }
+}
+
+impl IntoExpr for List {
+  fn into_expr(self) -> Expr {
+    Expr::List(self)
+  }
+}
+
+impl FromExpr for List {
+  fn from_expr(expr: Expr) -> Result<List, EvalError> {
+    match expr {
+      Expr::List(list) => Ok(list),
+      _ => Err(EvalError::InvalidType),
+    }
+  }
+}
+
+impl IntoExpr for Vector {
+  fn into_expr(self) -> Expr {
+    Expr::Atom(Atom::Vector(self))
+  }
+}
+
+impl FromExpr for Vector {
+  fn from_expr(expr: Expr) -> Result<Vector, EvalError> {
+    match expr {
+      Expr::Atom(Atom::Vector(vector)) => Ok(vector),
+      _ => Err(EvalError::InvalidType),
+    }
+  }
+}
+
+impl IntoExpr for Map {
+  fn into_expr(self) -> Expr {
+    Expr::Atom(Atom::Map(self))
+  }
+}
+
+impl FromExpr for Map {
+  fn from_expr(expr: Expr) -> Result<Map, EvalError> {
+    match expr {
+      Expr::Atom(Atom::Map(map)) => Ok(map),
+      _ => Err(EvalError::InvalidType),
+    }
+  }
+}
+
+impl IntoExpr for Function {
+  fn into_expr(self) -> Expr {
+    Expr::Atom(Atom::Function(self))
+  }
+}
+
+impl FromExpr for Function {
+  fn from_expr(expr: Expr) -> Result<Function, EvalError> {
+    match expr {
+      Expr::Atom(Atom::Function(function)) => Ok(function),
+      _ => Err(EvalError::InvalidType),
+    }
+  }
+}
+
+impl IntoExpr for Native {
+  fn into_expr(self) -> Expr {
+    Expr::Atom(Atom::Native(self))
+  }
+}
+
+impl<T> IntoExpr for Vec<T>
+where
+  T: IntoExpr,
+{
+  fn into_expr(self) -> Expr {
+    let list = self
+      .into_iter()
+      .rev()
+      .fold(List::Nil, |list, item| List::cons(item.into_expr(), list));
+    Expr::List(list)
+  }
+}
+
+impl<T> FromExpr for Vec<T>
+where
+  T: FromExpr,
+{
+  fn from_expr(expr: Expr) -> Result<Vec<T>, EvalError> {
+    List::from_expr(expr)?
+      .into_iter()
+      .map(T::from_expr)
+      .collect()
+  }
+}
+
+/// `None` is converted to and from the empty list.
+impl<T> IntoExpr for Option<T>
+where
+  T: IntoExpr,
+{
+  fn into_expr(self) -> Expr {
+    match self {
+      Some(value) => value.into_expr(),
+      None => Expr::List(List::Nil),
+    }
+  }
+}
+
+impl<T> FromExpr for Option<T>
+where
+  T: FromExpr,
+{
+  fn from_expr(expr: Expr) -> Result<Option<T>, EvalError> {
+    match expr {
+      Expr::List(List::Nil) => Ok(None),
+      expr => T::from_expr(expr).map(Some),
+    }
+  }
+}
+
+impl IntoArguments for Vec<Expr> {
+  fn into_arguments(self) -> Vec<Expr> {
+    self
+  }
+}
+
+macro_rules! impl_arguments {
+  ($count:expr; $($name:ident),*) => {
+    impl<$($name),*> IntoArguments for ($($name,)*)
+    where
+      $($name: IntoExpr),*
+    {
+      #[allow(non_snake_case)]
+      fn into_arguments(self) -> Vec<Expr> {
+        let ($($name,)*) = self;
+        vec![$($name.into_expr()),*]
+      }
+    }
+
+    impl<$($name),*> FromArguments for ($($name,)*)
+    where
+      $($name: FromExpr),*
+    {
+      #[allow(non_snake_case)]
+      fn from_arguments(arguments: Vec<Expr>) -> Result<Self, EvalError> {
+        use crate::ast::Arity;
+
+        if arguments.len() != $count {
+          return Err(EvalError::WrongArgumentCount(
+            Arity::Exactly($count),
+            arguments.len(),
+          ));
+        }
+
+        #[allow(unused_variables, unused_mut)]
+        let mut arguments = arguments.into_iter();
+        $(let $name = $name::from_expr(arguments.next().unwrap())?;)*
+
+        Ok(($($name,)*))
+      }
+    }
+  };
+}
+
+impl_arguments!(0;);
+impl_arguments!(1; A);
+impl_arguments!(2; A, B);
+impl_arguments!(3; A, B, C);
+impl_arguments!(4; A, B, C, D);
+impl_arguments!(5; A, B, C, D, E);
+impl_arguments!(6; A, B, C, D, E, F);
@@ -0,0 +1,63 @@
+use std::fmt;
+
+use crate::ast::Span;
+
+/// An error message pointing at the source code that caused it.
+#[derive(Debug)]
+pub struct Diagnostic {
+  pub path: String,
+  pub span: Span,
+  pub message: String,
+  excerpt: String,
+}
+
+impl Diagnostic {
+  pub fn new<P, M>(path: P, source: &str, span: Span, message: M) -> Diagnostic
+  where
+    P: Into<String>,
+    M: Into<String>,
+  {
+    let excerpt = source
+      .lines()
+      .nth(span.start.line - 1)
+      .unwrap_or("")
+      .to_string();
+
+    Diagnostic {
+      path: path.into(),
+      span,
+      message: message.into(),
+      excerpt,
+    }
+  }
+}
+
+impl fmt::Display for Diagnostic {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    let Span { start, end } = self.span;
+
+    let line_number = start.line.to_string();
+    let gutter = " ".repeat(line_number.len());
+
+    // Underline until the end of the span, or the end of the line if the span
+    // covers multiple lines.
+    let line_length = self.excerpt.chars().count() + 1;
+    let end_column = if end.line == start.line {
+      end.column.min(line_length)
+    } else {
+      line_length
+    };
+    let underline_length = end_column.saturating_sub(start.column).max(1);
+
+    writeln!(f, "{}:{}: {}", self.path, start, self.message)?;
+    writeln!(f, "{} |", gutter)?;
+    writeln!(f, "{} | {}", line_number, self.excerpt)?;
+    write!(
+      f,
+      "{} | {}{}",
+      gutter,
+      " ".repeat(start.column - 1),
+      "^".repeat(underline_length)
+    )
+  }
+}
@@ -0,0 +1,330 @@
+//! Collects the cycles which reference counting can't free.
+//!
+//! A function captures the frame it is created in, so defining a function
+//! stores it in the very frame it captures, and neither is ever freed. Every
+//! frame is registered here, and a collection traces everything reachable from
+//! them, counting how often each object is referenced by the others. An object
+//! referenced more often than that is in use from outside, and so is anything
+//! reachable from it. The frames and cells left over only keep each other
+//! alive, and are freed by clearing them.
+//!
+//! Vectors and maps aren't traced, so anything stored in one is treated as
+//! being in use.
+
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
+use std::mem;
+use std::rc::{Rc, Weak};
+
+use crate::ast::{Atom, Expr, Function, List, Macro, Node};
+use crate::eval::bytecode::{Cell, Closure};
+
+use super::{Frame, FrameInner};
+
+/// The number of frames which can be created before looking for garbage.
+const MIN_THRESHOLD: usize = 1024;
+
+thread_local! {
+  static HEAP: RefCell<Heap> = const { RefCell::new(Heap {
+    frames: Vec::new(),
+    threshold: MIN_THRESHOLD,
+    collections: 0,
+    collected: 0,
+  }) };
+}
+
+struct Heap {
+  frames: Vec<Weak<RefCell<FrameInner>>>,
+  /// How many frames can be registered before the next check for garbage.
+  threshold: usize,
+  collections: usize,
+  collected: usize,
+}
+
+impl Heap {
+  /// Forgets the frames that have already been freed.
+  fn prune(&mut self) {
+    self.frames.retain(|frame| frame.strong_count() > 0);
+  }
+}
+
+/// Statistics about the frames on the heap and the collections run so far.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct HeapStats {
+  /// The frames still alive, including those only kept alive by a cycle.
+  pub frames: usize,
+  pub collections: usize,
+  /// The objects freed by every collection so far.
+  pub collected: usize,
+}
+
+/// Registers a newly created frame. Once enough frames have been created
+/// without most of them being freed, this also runs a collection.
+pub fn register(frame: &Frame) {
+  let should_collect = HEAP.with(|heap| {
+    let mut heap = heap.borrow_mut();
+    heap.frames.push(Rc::downgrade(&frame.inner));
+    if heap.frames.len() < heap.threshold {
+      return false;
+    }
+
+    // Most frames are freed as soon as a call returns, so only collect if
+    // many of them are still around.
+    heap.prune();
+    let should_collect = heap.frames.len() * 2 > heap.threshold;
+    heap.threshold = MIN_THRESHOLD.max(heap.frames.len() * 2);
+    should_collect
+  });
+
+  if should_collect {
+    collect();
+    HEAP.with(|heap| {
+      let mut heap = heap.borrow_mut();
+      heap.threshold = MIN_THRESHOLD.max(heap.frames.len() * 2);
+    });
+  }
+}
+
+/// Frees every frame, function and list node which is only kept alive by a
+/// cycle, returning how many objects were freed.
+pub fn collect() -> usize {
+  let frames: Vec<Frame> = HEAP.with(|heap| {
+    heap
+      .borrow()
+      .frames
+      .iter()
+      .filter_map(Weak::upgrade)
+      .map(|inner| Frame { inner })
+      .collect()
+  });
+
+  let mut graph = Graph::default();
+  for frame in frames {
+    graph.trace(Object::Frame(frame));
+  }
+
+  let garbage = graph.garbage();
+  for object in garbage.iter() {
+    object.clear();
+  }
+  let count = garbage.len();
+  drop(garbage);
+
+  HEAP.with(|heap| {
+    let mut heap = heap.borrow_mut();
+    heap.prune();
+    heap.collections += 1;
+    heap.collected += count;
+  });
+
+  count
+}
+
+pub fn stats() -> HeapStats {
+  HEAP.with(|heap| {
+    let heap = heap.borrow();
+    HeapStats {
+      frames: heap.frames.iter().filter(|f| f.strong_count() > 0).count(),
+      collections: heap.collections,
+      collected: heap.collected,
+    }
+  })
+}
+
+/// An object which can be part of a cycle.
+enum Object {
+  Frame(Frame),
+  Function(Function),
+  Closure(Closure),
+  Macro(Macro),
+  Node(Rc<Node>),
+  Cell(Cell),
+}
+
+impl Object {
+  fn id(&self) -> *const () {
+    match self {
+      Object::Frame(frame) => Rc::as_ptr(&frame.inner) as *const (),
+      Object::Function(function) => function.as_ptr(),
+      Object::Closure(closure) => closure.as_ptr(),
+      Object::Macro(macr) => macr.as_ptr(),
+      Object::Node(node) => Rc::as_ptr(node) as *const (),
+      Object::Cell(cell) => Rc::as_ptr(cell) as *const (),
+    }
+  }
+
+  fn strong_count(&self) -> usize {
+    match self {
+      Object::Frame(frame) => Rc::strong_count(&frame.inner),
+      Object::Function(function) => function.strong_count(),
+      Object::Closure(closure) => closure.strong_count(),
+      Object::Macro(macr) => macr.strong_count(),
+      Object::Node(node) => Rc::strong_count(node),
+      Object::Cell(cell) => Rc::strong_count(cell),
+    }
+  }
+
+  /// Returns the objects this one refers to, or `None` if they can't be found
+  /// because the object is being modified.
+  fn children(&self) -> Option<Vec<Object>> {
+    let mut children = Vec::new();
+
+    match self {
+      Object::Frame(frame) => {
+        let inner = frame.inner.try_borrow().ok()?;
+        if let Some(parent) = &inner.parent {
+          children.push(Object::Frame(parent.clone()));
+        }
+        for expr in inner.variables.values().chain(inner.slots.iter().flatten())
+        {
+          push_expr(&mut children, expr);
+        }
+      }
+      Object::Function(function) => {
+        children.push(Object::Frame(function.frame().clone()));
+        for (_, default) in function.parameters().iter() {
+          if let Some(default) = default {
+            push_expr(&mut children, default);
+          }
+        }
+        push_expr(&mut children, function.body());
+      }
+      Object::Closure(closure) => {
+        children.push(Object::Frame(closure.frame().clone()));
+        for cell in closure.upvalues() {
+          children.push(Object::Cell(cell.clone()));
+        }
+      }
+      Object::Macro(macr) => {
+        if let Some(frame) = macr.frame() {
+          children.push(Object::Frame(frame.clone()));
+        }
+        push_expr(&mut children, macr.body());
+      }
+      Object::Node(node) => {
+        push_expr(&mut children, &node.head);
+        if let List::Cons(tail) = &node.tail {
+          children.push(Object::Node(tail.clone()));
+        }
+      }
+      Object::Cell(cell) => {
+        if let Some(expr) = cell.try_borrow().ok()?.as_ref() {
+          push_expr(&mut children, expr);
+        }
+      }
+    }
+
+    Some(children)
+  }
+
+  /// Drops the references held by a garbage object, breaking any cycle it is
+  /// part of.
+  fn clear(&self) {
+    match self {
+      Object::Frame(frame) => {
+        if let Ok(mut inner) = frame.inner.try_borrow_mut() {
+          let variables = mem::take(&mut inner.variables);
+          let slots = mem::take(&mut inner.slots);
+          drop(inner);
+          drop(variables);
+          drop(slots);
+        }
+      }
+      Object::Cell(cell) => {
+        if let Ok(mut value) = cell.try_borrow_mut() {
+          let expr = value.take();
+          drop(value);
+          drop(expr);
+        }
+      }
+      _ => {}
+    }
+  }
+}
+
+fn push_expr(children: &mut Vec<Object>, expr: &Expr) {
+  match expr {
+    Expr::List(List::Cons(node)) => children.push(Object::Node(node.clone())),
+    Expr::Atom(Atom::Function(function)) => {
+      children.push(Object::Function(function.clone()))
+    }
+    Expr::Atom(Atom::Closure(closure)) => {
+      children.push(Object::Closure(closure.clone()))
+    }
+    Expr::Atom(Atom::Macro(macr)) => children.push(Object::Macro(macr.clone())),
+    _ => {}
+  }
+}
+
+#[derive(Default)]
+struct Graph {
+  /// Every object traced, along with the objects it refers to. Objects which
+  /// couldn't be traced have no entry for what they refer to.
+  objects: HashMap<*const (), (Object, Option<Vec<*const ()>>)>,
+}
+
+impl Graph {
+  fn trace(&mut self, root: Object) {
+    let mut pending = vec![root];
+
+    while let Some(object) = pending.pop() {
+      let id = object.id();
+      if self.objects.contains_key(&id) {
+        continue;
+      }
+
+      let children = object.children();
+      let ids = children
+        .as_ref()
+        .map(|children| children.iter().map(Object::id).collect());
+      pending.extend(children.into_iter().flatten());
+
+      self.objects.insert(id, (object, ids));
+    }
+  }
+
+  /// Removes and returns the objects which aren't reachable from outside the
+  /// graph.
+  fn garbage(&mut self) -> Vec<Object> {
+    let mut references: HashMap<*const (), usize> = HashMap::new();
+    for (_, children) in self.objects.values() {
+      for id in children.iter().flatten() {
+        *references.entry(*id).or_insert(0) += 1;
+      }
+    }
+
+    // The graph itself holds one reference to each object.
+    let mut pending: Vec<*const ()> = self
+      .objects
+      .iter()
+      .filter(|(id, (object, children))| {
+        let references = references.get(*id).cloned().unwrap_or(0);
+        children.is_none() || object.strong_count() - 1 > references
+      })
+      .map(|(id, _)| *id)
+      .collect();
+
+    let mut alive = HashSet::new();
+    while let Some(id) = pending.pop() {
+      if !alive.insert(id) {
+        continue;
+      }
+      if let Some((_, Some(children))) = self.objects.get(&id) {
+        pending.extend(children.iter().cloned());
+      }
+    }
+
+    let garbage: Vec<*const ()> = self
+      .objects
+      .keys()
+      .filter(|id| !alive.contains(*id))
+      .cloned()
+      .collect();
+
+    garbage
+      .into_iter()
+      .filter_map(|id| self.objects.remove(&id))
+      .map(|(object, _)| object)
+      .collect()
+  }
+}
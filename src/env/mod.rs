@@ -1,11 +1,12 @@
-use std::cell::RefCell;
-use std::collections::BTreeMap;
+use std::cell::{Cell, RefCell};
 use std::rc::Rc;
 
-use crate::ast::{Expr, Symbol};
+use crate::ast::{Expr, Symbol, SymbolMap, Truthiness};
 
+pub use self::gc::HeapStats;
 pub use self::prelude::build_base_frame;
 
+pub mod gc;
 mod prelude;
 
 #[derive(Clone, Debug)]
@@ -16,46 +17,166 @@ pub struct Frame {
 #[derive(Debug)]
 struct FrameInner {
   parent: Option<Frame>,
-  variables: BTreeMap<Symbol, Expr>,
+  variables: SymbolMap<Expr>,
+  /// The names of the slots, which are shared by every call to the same
+  /// function.
+  locals: Rc<[Symbol]>,
+  /// The values of the locals, which are `None` until they are bound.
+  slots: Vec<Option<Expr>>,
 }
 
 impl Frame {
   pub fn new() -> Frame {
-    Frame {
-      inner: Rc::new(RefCell::new(FrameInner {
-        parent: None,
-        variables: BTreeMap::new(),
-      })),
-    }
+    Frame::with_inner(None, Rc::from([]))
   }
 
-  pub fn base() -> Frame {
-    build_base_frame()
+  pub fn base(truthiness: Rc<Cell<Truthiness>>) -> Frame {
+    build_base_frame(truthiness)
   }
 
   pub fn with_parent(parent: Frame) -> Frame {
-    Frame {
+    Frame::with_inner(Some(parent), Rc::from([]))
+  }
+
+  /// Creates a frame with a slot for each of `locals`, which can be accessed
+  /// by address as well as by name.
+  pub fn with_locals(parent: Frame, locals: Rc<[Symbol]>) -> Frame {
+    Frame::with_inner(Some(parent), locals)
+  }
+
+  fn with_inner(parent: Option<Frame>, locals: Rc<[Symbol]>) -> Frame {
+    let frame = Frame {
       inner: Rc::new(RefCell::new(FrameInner {
-        parent: Some(parent),
-        variables: BTreeMap::new(),
+        parent,
+        variables: SymbolMap::default(),
+        slots: vec![None; locals.len()],
+        locals,
       })),
-    }
+    };
+    gc::register(&frame);
+    frame
   }
 
   pub fn get(&self, symbol: &Symbol) -> Option<Expr> {
-    if let Some(expr) = self.inner.borrow().variables.get(symbol) {
+    let inner = self.inner.borrow();
+    if let Some(expr) = inner.local(symbol) {
+      Some(expr)
+    } else if let Some(expr) = inner.variables.get(symbol) {
       Some(expr.clone())
     } else {
-      self
-        .inner
-        .borrow()
-        .parent
-        .as_ref()
-        .and_then(|parent| parent.get(symbol))
+      inner.parent.as_ref().and_then(|parent| parent.get(symbol))
+    }
+  }
+
+  /// Returns the value in `slot` of the frame `depth` frames up. If the local
+  /// hasn't been bound yet, `symbol` is looked up in the frames around it
+  /// instead, just as if it had been looked up by name.
+  pub fn get_at(
+    &self,
+    depth: usize,
+    slot: usize,
+    symbol: &Symbol,
+  ) -> Option<Expr> {
+    let inner = self.inner.borrow();
+    if depth > 0 {
+      return inner.parent.as_ref()?.get_at(depth - 1, slot, symbol);
+    }
+
+    match inner.slots.get(slot) {
+      Some(Some(expr)) => Some(expr.clone()),
+      _ => inner.parent.as_ref()?.get(symbol),
     }
   }
 
   pub fn set(&mut self, symbol: Symbol, expr: Expr) {
-    self.inner.borrow_mut().variables.insert(symbol, expr);
+    let mut inner = self.inner.borrow_mut();
+    match inner.locals.iter().position(|local| local == &symbol) {
+      Some(slot) => inner.slots[slot] = Some(expr),
+      None => {
+        inner.variables.insert(symbol, expr);
+      }
+    }
+  }
+
+  /// Binds the value in `slot` of the frame `depth` frames up.
+  pub fn set_at(&mut self, depth: usize, slot: usize, expr: Expr) {
+    let mut inner = self.inner.borrow_mut();
+    if depth > 0 {
+      if let Some(parent) = inner.parent.as_mut() {
+        parent.set_at(depth - 1, slot, expr);
+      }
+      return;
+    }
+
+    if let Some(value) = inner.slots.get_mut(slot) {
+      *value = Some(expr);
+    }
+  }
+
+  /// Returns whether this frame itself binds `symbol`, ignoring its parents.
+  pub fn contains(&self, symbol: &Symbol) -> bool {
+    let inner = self.inner.borrow();
+    inner.bound_slot(symbol).is_some() || inner.variables.contains_key(symbol)
+  }
+
+  /// Rebinds `symbol` in the nearest frame which binds it, returning whether
+  /// such a frame was found.
+  pub fn assign(&mut self, symbol: Symbol, expr: Expr) -> bool {
+    let mut inner = self.inner.borrow_mut();
+    if let Some(slot) = inner.bound_slot(&symbol) {
+      inner.slots[slot] = Some(expr);
+      true
+    } else if let Some(value) = inner.variables.get_mut(&symbol) {
+      *value = expr;
+      true
+    } else {
+      match inner.parent.as_mut() {
+        Some(parent) => parent.assign(symbol, expr),
+        None => false,
+      }
+    }
+  }
+
+  /// Rebinds the value in `slot` of the frame `depth` frames up. If the local
+  /// hasn't been bound yet, `symbol` is assigned in the frames around it
+  /// instead, just as in `get_at`.
+  pub fn assign_at(
+    &mut self,
+    depth: usize,
+    slot: usize,
+    symbol: Symbol,
+    expr: Expr,
+  ) -> bool {
+    let mut inner = self.inner.borrow_mut();
+    if depth > 0 {
+      return match inner.parent.as_mut() {
+        Some(parent) => parent.assign_at(depth - 1, slot, symbol, expr),
+        None => false,
+      };
+    }
+
+    match inner.slots.get_mut(slot) {
+      Some(value @ Some(_)) => {
+        *value = Some(expr);
+        true
+      }
+      _ => match inner.parent.as_mut() {
+        Some(parent) => parent.assign(symbol, expr),
+        None => false,
+      },
+    }
+  }
+}
+
+impl FrameInner {
+  fn local(&self, symbol: &Symbol) -> Option<Expr> {
+    let slot = self.locals.iter().position(|local| local == symbol)?;
+    self.slots[slot].clone()
+  }
+
+  /// Returns the slot of `symbol`, if it is a local which has been bound.
+  fn bound_slot(&self, symbol: &Symbol) -> Option<usize> {
+    let slot = self.locals.iter().position(|local| local == symbol)?;
+    self.slots[slot].as_ref().map(|_| slot)
   }
 }
@@ -1,8 +1,15 @@
-use crate::ast::{self, Atom, Expr, List, SYMBOL_TRUE};
-use crate::env::Frame;
+use std::cell::Cell;
+use std::convert::TryFrom;
+use std::rc::Rc;
+
+use crate::ast::{self, Atom, Expr, List, Map, Truthiness};
+use crate::env::{gc, Frame};
 use crate::eval::EvalError;
+use crate::read::Reader;
 
-pub fn build_base_frame() -> Frame {
+/// Builds the frame of natives. `not` follows `truthiness`, which the
+/// evaluator owning the frame can change.
+pub fn build_base_frame(truthiness: Rc<Cell<Truthiness>>) -> Frame {
   use ast::Atom::*;
   use ast::Native;
   use ast::Symbol;
@@ -10,14 +17,21 @@ pub fn build_base_frame() -> Frame {
 
   let mut frame = Frame::new();
 
-  frame.set(SYMBOL_TRUE.clone(), Atom(Symbol(SYMBOL_TRUE.clone())));
-
   frame.set(Symbol::new("print"), Atom(Native(Native::new(print))));
   frame.set(Symbol::new("head"), Atom(Native(Native::new(head))));
   frame.set(Symbol::new("tail"), Atom(Native(Native::new(tail))));
   frame.set(Symbol::new("cons"), Atom(Native(Native::new(cons))));
 
+  frame.set(
+    Symbol::new("boolean?"),
+    Atom(Native(Native::new(is_boolean))),
+  );
   frame.set(Symbol::new("number?"), Atom(Native(Native::new(is_number))));
+  frame.set(
+    Symbol::new("integer?"),
+    Atom(Native(Native::new(is_integer))),
+  );
+  frame.set(Symbol::new("float?"), Atom(Native(Native::new(is_float))));
   frame.set(Symbol::new("string?"), Atom(Native(Native::new(is_string))));
   frame.set(Symbol::new("symbol?"), Atom(Native(Native::new(is_symbol))));
   frame.set(
@@ -29,8 +43,97 @@ pub fn build_base_frame() -> Frame {
     Atom(Native(Native::new(is_special))),
   );
   frame.set(Symbol::new("native?"), Atom(Native(Native::new(is_native))));
+  frame.set(Symbol::new("vector?"), Atom(Native(Native::new(is_vector))));
+  frame.set(Symbol::new("map?"), Atom(Native(Native::new(is_map))));
+
+  frame.set(Symbol::new("get"), Atom(Native(Native::new(get))));
+  frame.set(Symbol::new("assoc"), Atom(Native(Native::new(assoc))));
+  frame.set(Symbol::new("dissoc"), Atom(Native(Native::new(dissoc))));
+  frame.set(Symbol::new("keys"), Atom(Native(Native::new(keys))));
+  frame.set(Symbol::new("vals"), Atom(Native(Native::new(vals))));
+  frame.set(Symbol::new("count"), Atom(Native(Native::new(count))));
+  frame.set(Symbol::new("conj"), Atom(Native(Native::new(conj))));
+
+  let not = move |arguments| not(arguments, truthiness.get());
+  frame.set(Symbol::new("not"), Atom(Native(Native::new(not))));
+
+  frame.set(Symbol::new("eq?"), Atom(Native(Native::new(is_eq))));
+  frame.set(Symbol::new("equal?"), Atom(Native(Native::new(is_equal))));
+
+  frame.set(
+    Symbol::new("string-length"),
+    Atom(Native(Native::new(string_length))),
+  );
+  frame.set(
+    Symbol::new("string-append"),
+    Atom(Native(Native::new(string_append))),
+  );
+  frame.set(
+    Symbol::new("substring"),
+    Atom(Native(Native::new(substring))),
+  );
+  frame.set(
+    Symbol::new("string-split"),
+    Atom(Native(Native::new(string_split))),
+  );
+  frame.set(
+    Symbol::new("string-join"),
+    Atom(Native(Native::new(string_join))),
+  );
+  frame.set(
+    Symbol::new("string-trim"),
+    Atom(Native(Native::new(string_trim))),
+  );
+  frame.set(
+    Symbol::new("string-upcase"),
+    Atom(Native(Native::new(string_upcase))),
+  );
+  frame.set(
+    Symbol::new("string-downcase"),
+    Atom(Native(Native::new(string_downcase))),
+  );
+  frame.set(
+    Symbol::new("string-index"),
+    Atom(Native(Native::new(string_index))),
+  );
+  frame.set(
+    Symbol::new("string->number"),
+    Atom(Native(Native::new(string_to_number))),
+  );
+  frame.set(
+    Symbol::new("number->string"),
+    Atom(Native(Native::new(number_to_string))),
+  );
+  frame.set(
+    Symbol::new("string->symbol"),
+    Atom(Native(Native::new(string_to_symbol))),
+  );
+  frame.set(
+    Symbol::new("symbol->string"),
+    Atom(Native(Native::new(symbol_to_string))),
+  );
+  frame.set(
+    Symbol::new("string->list"),
+    Atom(Native(Native::new(string_to_list))),
+  );
+
+  frame.set(Symbol::new("raise"), Atom(Native(Native::new(raise))));
+  frame.set(Symbol::new("error"), Atom(Native(Native::new(error))));
 
   frame.set(Symbol::new("sqrt"), Atom(Native(Native::new(sqrt))));
+  frame.set(Symbol::new("integer"), Atom(Native(Native::new(integer))));
+  frame.set(Symbol::new("float"), Atom(Native(Native::new(float))));
+
+  frame.set(Symbol::new("gensym"), Atom(Native(Native::new(gensym))));
+
+  frame.set(
+    Symbol::new("gc"),
+    Atom(Native(Native::new(collect_garbage))),
+  );
+  frame.set(
+    Symbol::new("heap-stats"),
+    Atom(Native(Native::new(heap_stats))),
+  );
 
   frame
 }
@@ -42,7 +145,7 @@ pub fn print(arguments: Vec<Expr>) -> Result<Expr, EvalError> {
     return Err(WrongArity);
   }
 
-  let expr = arguments.get(0).unwrap().clone();
+  let expr = arguments.first().unwrap().clone();
 
   println!("{}", expr);
 
@@ -56,7 +159,7 @@ fn head(arguments: Vec<Expr>) -> Result<Expr, EvalError> {
     return Err(WrongArity);
   }
 
-  let list = match arguments.get(0) {
+  let list = match arguments.first() {
     Some(Expr::List(list)) => list,
     _ => return Err(InvalidType),
   };
@@ -76,7 +179,7 @@ fn tail(arguments: Vec<Expr>) -> Result<Expr, EvalError> {
     return Err(WrongArity);
   }
 
-  let list = match arguments.get(0) {
+  let list = match arguments.first() {
     Some(Expr::List(list)) => list,
     _ => return Err(InvalidType),
   };
@@ -96,7 +199,7 @@ fn cons(arguments: Vec<Expr>) -> Result<Expr, EvalError> {
     return Err(WrongArity);
   }
 
-  let head = arguments.get(0).unwrap().clone();
+  let head = arguments.first().unwrap().clone();
 
   let tail = match arguments.get(1) {
     Some(Expr::List(list)) => list.clone(),
@@ -106,6 +209,22 @@ fn cons(arguments: Vec<Expr>) -> Result<Expr, EvalError> {
   Ok(Expr::List(List::cons(head, tail)))
 }
 
+pub fn is_boolean(arguments: Vec<Expr>) -> Result<Expr, EvalError> {
+  use EvalError::*;
+
+  if arguments.len() != 1 {
+    return Err(WrongArity);
+  }
+
+  let expr = arguments.first().unwrap().clone();
+
+  if let Expr::Atom(Atom::Boolean(_)) = expr {
+    Ok(Expr::Atom(Atom::Boolean(true)))
+  } else {
+    Ok(Expr::Atom(Atom::Boolean(false)))
+  }
+}
+
 pub fn is_number(arguments: Vec<Expr>) -> Result<Expr, EvalError> {
   use EvalError::*;
 
@@ -113,12 +232,44 @@ pub fn is_number(arguments: Vec<Expr>) -> Result<Expr, EvalError> {
     return Err(WrongArity);
   }
 
-  let expr = arguments.get(0).unwrap().clone();
+  let expr = arguments.first().unwrap().clone();
+
+  if let Expr::Atom(Atom::Integer(_)) | Expr::Atom(Atom::Float(_)) = expr {
+    Ok(Expr::Atom(Atom::Boolean(true)))
+  } else {
+    Ok(Expr::Atom(Atom::Boolean(false)))
+  }
+}
+
+pub fn is_integer(arguments: Vec<Expr>) -> Result<Expr, EvalError> {
+  use EvalError::*;
+
+  if arguments.len() != 1 {
+    return Err(WrongArity);
+  }
+
+  let expr = arguments.first().unwrap().clone();
 
-  if let Expr::Atom(Atom::Number(_)) = expr {
-    Ok(Expr::Atom(Atom::Symbol(SYMBOL_TRUE.clone())))
+  if let Expr::Atom(Atom::Integer(_)) = expr {
+    Ok(Expr::Atom(Atom::Boolean(true)))
   } else {
-    Ok(Expr::List(List::Nil))
+    Ok(Expr::Atom(Atom::Boolean(false)))
+  }
+}
+
+pub fn is_float(arguments: Vec<Expr>) -> Result<Expr, EvalError> {
+  use EvalError::*;
+
+  if arguments.len() != 1 {
+    return Err(WrongArity);
+  }
+
+  let expr = arguments.first().unwrap().clone();
+
+  if let Expr::Atom(Atom::Float(_)) = expr {
+    Ok(Expr::Atom(Atom::Boolean(true)))
+  } else {
+    Ok(Expr::Atom(Atom::Boolean(false)))
   }
 }
 
@@ -129,12 +280,12 @@ pub fn is_string(arguments: Vec<Expr>) -> Result<Expr, EvalError> {
     return Err(WrongArity);
   }
 
-  let expr = arguments.get(0).unwrap().clone();
+  let expr = arguments.first().unwrap().clone();
 
   if let Expr::Atom(Atom::String(_)) = expr {
-    Ok(Expr::Atom(Atom::Symbol(SYMBOL_TRUE.clone())))
+    Ok(Expr::Atom(Atom::Boolean(true)))
   } else {
-    Ok(Expr::List(List::Nil))
+    Ok(Expr::Atom(Atom::Boolean(false)))
   }
 }
 
@@ -145,12 +296,12 @@ pub fn is_symbol(arguments: Vec<Expr>) -> Result<Expr, EvalError> {
     return Err(WrongArity);
   }
 
-  let expr = arguments.get(0).unwrap().clone();
+  let expr = arguments.first().unwrap().clone();
 
   if let Expr::Atom(Atom::Symbol(_)) = expr {
-    Ok(Expr::Atom(Atom::Symbol(SYMBOL_TRUE.clone())))
+    Ok(Expr::Atom(Atom::Boolean(true)))
   } else {
-    Ok(Expr::List(List::Nil))
+    Ok(Expr::Atom(Atom::Boolean(false)))
   }
 }
 
@@ -161,12 +312,12 @@ pub fn is_function(arguments: Vec<Expr>) -> Result<Expr, EvalError> {
     return Err(WrongArity);
   }
 
-  let expr = arguments.get(0).unwrap().clone();
+  let expr = arguments.first().unwrap().clone();
 
-  if let Expr::Atom(Atom::Function(_)) = expr {
-    Ok(Expr::Atom(Atom::Symbol(SYMBOL_TRUE.clone())))
+  if let Expr::Atom(Atom::Function(_)) | Expr::Atom(Atom::Closure(_)) = expr {
+    Ok(Expr::Atom(Atom::Boolean(true)))
   } else {
-    Ok(Expr::List(List::Nil))
+    Ok(Expr::Atom(Atom::Boolean(false)))
   }
 }
 
@@ -177,12 +328,12 @@ pub fn is_special(arguments: Vec<Expr>) -> Result<Expr, EvalError> {
     return Err(WrongArity);
   }
 
-  let expr = arguments.get(0).unwrap().clone();
+  let expr = arguments.first().unwrap().clone();
 
   if let Expr::Atom(Atom::Special(_)) = expr {
-    Ok(Expr::Atom(Atom::Symbol(SYMBOL_TRUE.clone())))
+    Ok(Expr::Atom(Atom::Boolean(true)))
   } else {
-    Ok(Expr::List(List::Nil))
+    Ok(Expr::Atom(Atom::Boolean(false)))
   }
 }
 
@@ -193,15 +344,559 @@ pub fn is_native(arguments: Vec<Expr>) -> Result<Expr, EvalError> {
     return Err(WrongArity);
   }
 
-  let expr = arguments.get(0).unwrap().clone();
+  let expr = arguments.first().unwrap().clone();
 
   if let Expr::Atom(Atom::Native(_)) = expr {
-    Ok(Expr::Atom(Atom::Symbol(SYMBOL_TRUE.clone())))
+    Ok(Expr::Atom(Atom::Boolean(true)))
+  } else {
+    Ok(Expr::Atom(Atom::Boolean(false)))
+  }
+}
+
+pub fn is_vector(arguments: Vec<Expr>) -> Result<Expr, EvalError> {
+  use EvalError::*;
+
+  if arguments.len() != 1 {
+    return Err(WrongArity);
+  }
+
+  let expr = arguments.first().unwrap().clone();
+
+  if let Expr::Atom(Atom::Vector(_)) = expr {
+    Ok(Expr::Atom(Atom::Boolean(true)))
+  } else {
+    Ok(Expr::Atom(Atom::Boolean(false)))
+  }
+}
+
+pub fn is_map(arguments: Vec<Expr>) -> Result<Expr, EvalError> {
+  use EvalError::*;
+
+  if arguments.len() != 1 {
+    return Err(WrongArity);
+  }
+
+  let expr = arguments.first().unwrap().clone();
+
+  if let Expr::Atom(Atom::Map(_)) = expr {
+    Ok(Expr::Atom(Atom::Boolean(true)))
   } else {
-    Ok(Expr::List(List::Nil))
+    Ok(Expr::Atom(Atom::Boolean(false)))
   }
 }
 
+/// Returns whether the argument is falsy.
+pub fn not(
+  arguments: Vec<Expr>,
+  truthiness: Truthiness,
+) -> Result<Expr, EvalError> {
+  use EvalError::*;
+
+  if arguments.len() != 1 {
+    return Err(WrongArity);
+  }
+
+  Ok(Expr::Atom(Atom::Boolean(
+    !arguments.first().unwrap().is_truthy(truthiness),
+  )))
+}
+
+/// Checks whether both arguments are the same instance. Lists, vectors and
+/// maps are compared by identity, while values that are never shared, like
+/// numbers and strings, are compared by value.
+pub fn is_eq(arguments: Vec<Expr>) -> Result<Expr, EvalError> {
+  use EvalError::*;
+
+  if arguments.len() != 2 {
+    return Err(WrongArity);
+  }
+
+  let left = arguments.first().unwrap();
+  let right = arguments.get(1).unwrap();
+
+  let is_eq = match (left, right) {
+    (Expr::List(left), Expr::List(right)) => left.ptr_eq(right),
+    (Expr::Atom(Atom::Vector(left)), Expr::Atom(Atom::Vector(right))) => {
+      left.ptr_eq(right)
+    }
+    (Expr::Atom(Atom::Map(left)), Expr::Atom(Atom::Map(right))) => {
+      left.ptr_eq(right)
+    }
+    (left, right) => left == right,
+  };
+
+  if is_eq {
+    Ok(Expr::Atom(Atom::Boolean(true)))
+  } else {
+    Ok(Expr::Atom(Atom::Boolean(false)))
+  }
+}
+
+/// Checks whether both arguments are structurally equal.
+pub fn is_equal(arguments: Vec<Expr>) -> Result<Expr, EvalError> {
+  use EvalError::*;
+
+  if arguments.len() != 2 {
+    return Err(WrongArity);
+  }
+
+  if arguments.first() == arguments.get(1) {
+    Ok(Expr::Atom(Atom::Boolean(true)))
+  } else {
+    Ok(Expr::Atom(Atom::Boolean(false)))
+  }
+}
+
+/// Looks up a key in a map, returning the default (or `()`) if it is missing,
+/// or an index in a vector or list, which fails if it is out of bounds unless
+/// a default is given.
+pub fn get(arguments: Vec<Expr>) -> Result<Expr, EvalError> {
+  use EvalError::*;
+
+  if arguments.len() != 2 && arguments.len() != 3 {
+    return Err(WrongArity);
+  }
+
+  let key = arguments.get(1).unwrap();
+  let default = arguments.get(2).cloned();
+
+  let (expr, index, len) = match arguments.first().unwrap() {
+    Expr::Atom(Atom::Map(map)) => {
+      let default = default.unwrap_or(Expr::List(List::Nil));
+      return Ok(map.get(key).cloned().unwrap_or(default));
+    }
+    Expr::Atom(Atom::Vector(vector)) => {
+      let index = as_index(key).ok_or(InvalidType)?;
+      (vector.get(index), index, vector.len())
+    }
+    Expr::List(list) => {
+      let index = as_index(key).ok_or(InvalidType)?;
+      (list.get(index), index, list.len())
+    }
+    _ => return Err(InvalidType),
+  };
+
+  match (expr, default) {
+    (Some(expr), _) => Ok(expr.clone()),
+    (None, Some(default)) => Ok(default),
+    (None, None) => Err(IndexOutOfBounds(index, len)),
+  }
+}
+
+/// Sets a key in a map, or replaces an index in a vector. A vector can also be
+/// appended to by using its length as the index.
+pub fn assoc(arguments: Vec<Expr>) -> Result<Expr, EvalError> {
+  use EvalError::*;
+
+  if arguments.len() != 3 {
+    return Err(WrongArity);
+  }
+
+  let key = arguments.get(1).unwrap().clone();
+  let value = arguments.get(2).unwrap().clone();
+
+  let atom = match arguments.first().unwrap() {
+    Expr::Atom(Atom::Map(map)) => Atom::Map(map.insert(key, value)),
+    Expr::Atom(Atom::Vector(vector)) => {
+      let index = as_index(&key).ok_or(InvalidType)?;
+      if index == vector.len() {
+        Atom::Vector(vector.push(value))
+      } else {
+        match vector.set(index, value) {
+          Some(vector) => Atom::Vector(vector),
+          None => return Err(IndexOutOfBounds(index, vector.len())),
+        }
+      }
+    }
+    _ => return Err(InvalidType),
+  };
+
+  Ok(Expr::Atom(atom))
+}
+
+pub fn dissoc(arguments: Vec<Expr>) -> Result<Expr, EvalError> {
+  use EvalError::*;
+
+  if arguments.len() != 2 {
+    return Err(WrongArity);
+  }
+
+  let map = match arguments.first() {
+    Some(Expr::Atom(Atom::Map(map))) => map,
+    _ => return Err(InvalidType),
+  };
+
+  Ok(Expr::Atom(Atom::Map(map.remove(arguments.get(1).unwrap()))))
+}
+
+pub fn keys(arguments: Vec<Expr>) -> Result<Expr, EvalError> {
+  use EvalError::*;
+
+  if arguments.len() != 1 {
+    return Err(WrongArity);
+  }
+
+  let map = match arguments.first() {
+    Some(Expr::Atom(Atom::Map(map))) => map,
+    _ => return Err(InvalidType),
+  };
+
+  let keys: Vec<Expr> = map.keys().cloned().collect();
+  let keys = keys
+    .into_iter()
+    .rev()
+    .fold(List::Nil, |list, key| List::cons(key, list));
+
+  Ok(Expr::List(keys))
+}
+
+pub fn vals(arguments: Vec<Expr>) -> Result<Expr, EvalError> {
+  use EvalError::*;
+
+  if arguments.len() != 1 {
+    return Err(WrongArity);
+  }
+
+  let map = match arguments.first() {
+    Some(Expr::Atom(Atom::Map(map))) => map,
+    _ => return Err(InvalidType),
+  };
+
+  let values: Vec<Expr> = map.values().cloned().collect();
+  let values = values
+    .into_iter()
+    .rev()
+    .fold(List::Nil, |list, value| List::cons(value, list));
+
+  Ok(Expr::List(values))
+}
+
+pub fn count(arguments: Vec<Expr>) -> Result<Expr, EvalError> {
+  use EvalError::*;
+
+  if arguments.len() != 1 {
+    return Err(WrongArity);
+  }
+
+  let count = match arguments.first().unwrap() {
+    Expr::List(list) => list.len(),
+    Expr::Atom(Atom::Vector(vector)) => vector.len(),
+    Expr::Atom(Atom::Map(map)) => map.len(),
+    Expr::Atom(Atom::String(string)) => string.chars().count(),
+    _ => return Err(InvalidType),
+  };
+
+  Ok(Expr::Atom(Atom::Integer(count as i64)))
+}
+
+/// Adds an element to a collection where it is cheapest to do so: the end of a
+/// vector, or the front of a list. Maps take a `[key value]` vector.
+pub fn conj(arguments: Vec<Expr>) -> Result<Expr, EvalError> {
+  use EvalError::*;
+
+  if arguments.len() != 2 {
+    return Err(WrongArity);
+  }
+
+  let expr = arguments.get(1).unwrap().clone();
+
+  let expr = match arguments.first().unwrap() {
+    Expr::List(list) => Expr::List(List::cons(expr, list.clone())),
+    Expr::Atom(Atom::Vector(vector)) => {
+      Expr::Atom(Atom::Vector(vector.push(expr)))
+    }
+    Expr::Atom(Atom::Map(map)) => {
+      let entry = match &expr {
+        Expr::Atom(Atom::Vector(entry)) if entry.len() == 2 => entry,
+        _ => return Err(InvalidType),
+      };
+      let key = entry.get(0).unwrap().clone();
+      let value = entry.get(1).unwrap().clone();
+      Expr::Atom(Atom::Map(map.insert(key, value)))
+    }
+    _ => return Err(InvalidType),
+  };
+
+  Ok(expr)
+}
+
+pub fn string_length(arguments: Vec<Expr>) -> Result<Expr, EvalError> {
+  use EvalError::*;
+
+  if arguments.len() != 1 {
+    return Err(WrongArity);
+  }
+
+  let string = as_string(arguments.first().unwrap())?;
+
+  Ok(Expr::Atom(Atom::Integer(string.chars().count() as i64)))
+}
+
+pub fn string_append(arguments: Vec<Expr>) -> Result<Expr, EvalError> {
+  let mut buf = String::new();
+  for expr in arguments.iter() {
+    buf.push_str(as_string(expr)?);
+  }
+
+  Ok(Expr::Atom(Atom::String(buf)))
+}
+
+/// Returns the characters of a string from a start index up to an optional end
+/// index, which defaults to the end of the string.
+pub fn substring(arguments: Vec<Expr>) -> Result<Expr, EvalError> {
+  use EvalError::*;
+
+  if arguments.len() != 2 && arguments.len() != 3 {
+    return Err(WrongArity);
+  }
+
+  let string = as_string(arguments.first().unwrap())?;
+  let len = string.chars().count();
+
+  let start = as_index(arguments.get(1).unwrap()).ok_or(InvalidType)?;
+  let end = match arguments.get(2) {
+    Some(expr) => as_index(expr).ok_or(InvalidType)?,
+    None => len,
+  };
+
+  if end > len {
+    return Err(IndexOutOfBounds(end, len));
+  }
+  if start > len {
+    return Err(IndexOutOfBounds(start, len));
+  }
+  if start > end {
+    return Err(StartExceedsEnd(start, end));
+  }
+
+  let substring = string.chars().skip(start).take(end - start).collect();
+
+  Ok(Expr::Atom(Atom::String(substring)))
+}
+
+/// Splits a string on every occurrence of a separator, or into its characters
+/// if the separator is empty.
+pub fn string_split(arguments: Vec<Expr>) -> Result<Expr, EvalError> {
+  use EvalError::*;
+
+  if arguments.len() != 2 {
+    return Err(WrongArity);
+  }
+
+  let string = as_string(arguments.first().unwrap())?;
+  let separator = as_string(arguments.get(1).unwrap())?;
+
+  let parts: Vec<String> = if separator.is_empty() {
+    string.chars().map(String::from).collect()
+  } else {
+    string.split(separator).map(String::from).collect()
+  };
+
+  Ok(Expr::List(into_string_list(parts)))
+}
+
+/// Joins a list of strings, with an optional separator between them.
+pub fn string_join(arguments: Vec<Expr>) -> Result<Expr, EvalError> {
+  use EvalError::*;
+
+  if arguments.len() != 1 && arguments.len() != 2 {
+    return Err(WrongArity);
+  }
+
+  let list = match arguments.first() {
+    Some(Expr::List(list)) => list,
+    _ => return Err(InvalidType),
+  };
+  let separator = match arguments.get(1) {
+    Some(expr) => as_string(expr)?,
+    None => "",
+  };
+
+  let parts = list
+    .iter()
+    .map(|node| as_string(&node.head))
+    .collect::<Result<Vec<&str>, EvalError>>()?;
+
+  Ok(Expr::Atom(Atom::String(parts.join(separator))))
+}
+
+pub fn string_trim(arguments: Vec<Expr>) -> Result<Expr, EvalError> {
+  use EvalError::*;
+
+  if arguments.len() != 1 {
+    return Err(WrongArity);
+  }
+
+  let string = as_string(arguments.first().unwrap())?;
+
+  Ok(Expr::Atom(Atom::String(string.trim().to_string())))
+}
+
+pub fn string_upcase(arguments: Vec<Expr>) -> Result<Expr, EvalError> {
+  use EvalError::*;
+
+  if arguments.len() != 1 {
+    return Err(WrongArity);
+  }
+
+  let string = as_string(arguments.first().unwrap())?;
+
+  Ok(Expr::Atom(Atom::String(string.to_uppercase())))
+}
+
+pub fn string_downcase(arguments: Vec<Expr>) -> Result<Expr, EvalError> {
+  use EvalError::*;
+
+  if arguments.len() != 1 {
+    return Err(WrongArity);
+  }
+
+  let string = as_string(arguments.first().unwrap())?;
+
+  Ok(Expr::Atom(Atom::String(string.to_lowercase())))
+}
+
+/// Returns the index of the first character where a substring occurs, or `()`
+/// if it doesn't occur.
+pub fn string_index(arguments: Vec<Expr>) -> Result<Expr, EvalError> {
+  use EvalError::*;
+
+  if arguments.len() != 2 {
+    return Err(WrongArity);
+  }
+
+  let string = as_string(arguments.first().unwrap())?;
+  let pattern = as_string(arguments.get(1).unwrap())?;
+
+  match string.find(pattern) {
+    Some(offset) => {
+      let index = string[..offset].chars().count();
+      Ok(Expr::Atom(Atom::Integer(index as i64)))
+    }
+    None => Ok(Expr::List(List::Nil)),
+  }
+}
+
+/// Reads a number written the same way as in source code, returning `()` if
+/// the string isn't a number.
+pub fn string_to_number(arguments: Vec<Expr>) -> Result<Expr, EvalError> {
+  use EvalError::*;
+
+  if arguments.len() != 1 {
+    return Err(WrongArity);
+  }
+
+  let string = as_string(arguments.first().unwrap())?;
+
+  let mut reader = Reader::new(string.trim().chars());
+  match reader.read_expr() {
+    Ok(expr @ Expr::Atom(Atom::Integer(_)))
+    | Ok(expr @ Expr::Atom(Atom::Float(_)))
+      if reader.is_empty() =>
+    {
+      Ok(expr)
+    }
+    _ => Ok(Expr::List(List::Nil)),
+  }
+}
+
+pub fn number_to_string(arguments: Vec<Expr>) -> Result<Expr, EvalError> {
+  use EvalError::*;
+
+  if arguments.len() != 1 {
+    return Err(WrongArity);
+  }
+
+  let expr = arguments.first().unwrap();
+  if let Expr::Atom(Atom::Integer(_)) | Expr::Atom(Atom::Float(_)) = expr {
+    Ok(Expr::Atom(Atom::String(expr.to_string())))
+  } else {
+    Err(InvalidType)
+  }
+}
+
+pub fn string_to_symbol(arguments: Vec<Expr>) -> Result<Expr, EvalError> {
+  use EvalError::*;
+
+  if arguments.len() != 1 {
+    return Err(WrongArity);
+  }
+
+  let string = as_string(arguments.first().unwrap())?;
+
+  Ok(Expr::Atom(Atom::Symbol(ast::Symbol::new(string))))
+}
+
+pub fn symbol_to_string(arguments: Vec<Expr>) -> Result<Expr, EvalError> {
+  use EvalError::*;
+
+  if arguments.len() != 1 {
+    return Err(WrongArity);
+  }
+
+  let symbol = match arguments.first() {
+    Some(Expr::Atom(Atom::Symbol(symbol))) => symbol,
+    _ => return Err(InvalidType),
+  };
+
+  Ok(Expr::Atom(Atom::String(symbol.to_string())))
+}
+
+/// Splits a string into a list of strings holding one character each.
+pub fn string_to_list(arguments: Vec<Expr>) -> Result<Expr, EvalError> {
+  use EvalError::*;
+
+  if arguments.len() != 1 {
+    return Err(WrongArity);
+  }
+
+  let string = as_string(arguments.first().unwrap())?;
+  let chars = string.chars().map(String::from).collect();
+
+  Ok(Expr::List(into_string_list(chars)))
+}
+
+/// Raises any value as an error, which a `catch` clause receives as it is.
+pub fn raise(arguments: Vec<Expr>) -> Result<Expr, EvalError> {
+  use EvalError::*;
+
+  if arguments.len() != 1 {
+    return Err(WrongArity);
+  }
+
+  Err(Raised(arguments.into_iter().next().unwrap()))
+}
+
+/// Raises an error like `{:kind :error :message "..." :data (...)}`, where the
+/// data is the list of arguments after the message, if there are any.
+pub fn error(arguments: Vec<Expr>) -> Result<Expr, EvalError> {
+  use EvalError::*;
+
+  let mut arguments = arguments.into_iter();
+  let message = match arguments.next() {
+    Some(expr @ Expr::Atom(Atom::String(_))) => expr,
+    Some(_) => return Err(InvalidType),
+    None => return Err(WrongArity),
+  };
+
+  let keyword = |name: &str| Expr::Atom(Atom::Keyword(ast::Symbol::new(name)));
+
+  let mut map = ast::Map::new()
+    .insert(keyword("kind"), keyword("error"))
+    .insert(keyword("message"), message);
+
+  let data: Vec<Expr> = arguments.collect();
+  if !data.is_empty() {
+    let data = data
+      .into_iter()
+      .rev()
+      .fold(List::Nil, |list, expr| List::cons(expr, list));
+    map = map.insert(keyword("data"), Expr::List(data));
+  }
+
+  Err(Raised(Expr::Atom(Atom::Map(map))))
+}
+
 pub fn sqrt(arguments: Vec<Expr>) -> Result<Expr, EvalError> {
   use EvalError::*;
 
@@ -209,10 +904,116 @@ pub fn sqrt(arguments: Vec<Expr>) -> Result<Expr, EvalError> {
     return Err(WrongArity);
   }
 
-  let number = match arguments.get(0) {
-    Some(Expr::Atom(Atom::Number(number))) => number,
+  let number = match arguments.first() {
+    Some(Expr::Atom(Atom::Integer(integer))) => *integer as f64,
+    Some(Expr::Atom(Atom::Float(float))) => *float,
     _ => return Err(InvalidType),
   };
 
-  Ok(Expr::Atom(Atom::Number(number.sqrt())))
+  Ok(Expr::Atom(Atom::Float(number.sqrt())))
+}
+
+/// Converts a number to an integer, rounding floats towards zero.
+pub fn integer(arguments: Vec<Expr>) -> Result<Expr, EvalError> {
+  use EvalError::*;
+
+  if arguments.len() != 1 {
+    return Err(WrongArity);
+  }
+
+  let integer = match arguments.first().unwrap() {
+    Expr::Atom(Atom::Integer(integer)) => *integer,
+    Expr::Atom(Atom::Float(float)) => {
+      let float = float.trunc();
+      // Casting saturates, so check that the float is actually in range.
+      if !(float >= i64::MIN as f64 && float < i64::MAX as f64) {
+        return Err(IntegerOverflow);
+      }
+      float as i64
+    }
+    _ => return Err(InvalidType),
+  };
+
+  Ok(Expr::Atom(Atom::Integer(integer)))
+}
+
+pub fn float(arguments: Vec<Expr>) -> Result<Expr, EvalError> {
+  use EvalError::*;
+
+  if arguments.len() != 1 {
+    return Err(WrongArity);
+  }
+
+  let float = match arguments.first().unwrap() {
+    Expr::Atom(Atom::Integer(integer)) => *integer as f64,
+    Expr::Atom(Atom::Float(float)) => *float,
+    _ => return Err(InvalidType),
+  };
+
+  Ok(Expr::Atom(Atom::Float(float)))
+}
+
+pub fn gensym(arguments: Vec<Expr>) -> Result<Expr, EvalError> {
+  use EvalError::*;
+
+  let symbol = match arguments.as_slice() {
+    [] => ast::Symbol::gensym("g"),
+    [Expr::Atom(Atom::Symbol(symbol))] => ast::Symbol::gensym(&symbol.name()),
+    [Expr::Atom(Atom::String(string))] => ast::Symbol::gensym(string),
+    [_] => return Err(InvalidType),
+    _ => return Err(WrongArity),
+  };
+
+  Ok(Expr::Atom(Atom::Symbol(symbol)))
+}
+
+/// Runs a garbage collection, returning how many objects were freed.
+pub fn collect_garbage(arguments: Vec<Expr>) -> Result<Expr, EvalError> {
+  use EvalError::*;
+
+  if !arguments.is_empty() {
+    return Err(WrongArity);
+  }
+
+  Ok(Expr::Atom(Atom::Integer(gc::collect() as i64)))
+}
+
+/// Returns a map like `{:frames 12 :collections 1 :collected 40}`.
+pub fn heap_stats(arguments: Vec<Expr>) -> Result<Expr, EvalError> {
+  use EvalError::*;
+
+  if !arguments.is_empty() {
+    return Err(WrongArity);
+  }
+
+  let stats = gc::stats();
+  let keyword = |name: &str| Expr::Atom(Atom::Keyword(ast::Symbol::new(name)));
+  let integer = |count: usize| Expr::Atom(Atom::Integer(count as i64));
+
+  let map = Map::new()
+    .insert(keyword("frames"), integer(stats.frames))
+    .insert(keyword("collections"), integer(stats.collections))
+    .insert(keyword("collected"), integer(stats.collected));
+
+  Ok(Expr::Atom(Atom::Map(map)))
+}
+
+fn as_string(expr: &Expr) -> Result<&str, EvalError> {
+  match expr {
+    Expr::Atom(Atom::String(string)) => Ok(string),
+    _ => Err(EvalError::InvalidType),
+  }
+}
+
+fn into_string_list(strings: Vec<String>) -> List {
+  strings.into_iter().rev().fold(List::Nil, |list, string| {
+    List::cons(Expr::Atom(Atom::String(string)), list)
+  })
+}
+
+fn as_index(expr: &Expr) -> Option<usize> {
+  match expr {
+    Expr::Atom(Atom::Integer(integer)) => usize::try_from(*integer).ok(),
+    _ => None,
+  }
 }
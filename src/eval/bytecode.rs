@@ -0,0 +1,187 @@
+use std::cell::RefCell;
+use std::fmt;
+use std::rc::Rc;
+
+use crate::ast::{Expr, Operator, Parameters, Span, Symbol};
+use crate::env::Frame;
+
+/// A single instruction for the virtual machine, which works on a stack of
+/// values. Operands refer to the constants, symbols, local slots, upvalues or
+/// prototypes of the prototype being run.
+#[derive(Clone, Copy, Debug)]
+pub enum Instruction {
+  /// Pushes a constant.
+  Constant(usize),
+  Pop,
+  /// Pushes a copy of the value on top of the stack.
+  Dup,
+  GetLocal(usize),
+  /// Sets a local slot to the value on top of the stack, leaving it there.
+  SetLocal(usize),
+  GetUpvalue(usize),
+  GetGlobal(usize),
+  /// Like `SetLocal`, but fails in strict mode if the slot is already bound.
+  DefineLocal(usize),
+  /// Defines a symbol in the global frame as the value on top of the stack,
+  /// leaving it there.
+  DefineGlobal(usize),
+  /// Sets a bound local slot to the value on top of the stack, leaving it
+  /// there. Fails if the slot hasn't been bound yet.
+  AssignLocal(usize),
+  /// Like `AssignLocal`, but for an upvalue.
+  AssignUpvalue(usize),
+  /// Rebinds a symbol in the nearest global frame which defines it.
+  AssignGlobal(usize),
+  Jump(usize),
+  /// Pops a value, and jumps if it is falsy.
+  JumpIfFalse(usize),
+  /// Jumps if the value on top of the stack is falsy, leaving it there, and
+  /// pops it otherwise.
+  JumpIfFalseOrPop(usize),
+  /// Like `JumpIfFalseOrPop`, but jumps if the value is truthy.
+  JumpIfTrueOrPop(usize),
+  /// Jumps if the local slot was passed an argument, skipping the code that
+  /// evaluates its default.
+  JumpIfBound(usize, usize),
+  /// Calls a function with the given number of arguments, which are on top
+  /// of the function on the stack.
+  Call(usize),
+  /// Like `Call`, but replaces the current call instead of returning to it.
+  TailCall(usize),
+  Return,
+  /// Creates a closure from a prototype, capturing its upvalues.
+  Closure(usize),
+  /// Creates a macro from a parameter symbol and a body constant.
+  Macro(usize, usize),
+  /// Like `Macro`, but creates a hygienic macro.
+  Syntax(usize, usize),
+  Operator(Operator, usize),
+  /// Pops values into a list.
+  List(usize),
+  /// Pops lists and joins them together into a single list.
+  Append(usize),
+  /// Pops values into a vector.
+  Vector(usize),
+  /// Pops alternating keys and values into a map.
+  Map(usize),
+  MacroExpand,
+  MacroExpandOnce,
+  /// Pops a path, and loads the file at it.
+  Load,
+  /// Imports the module at a path constant into the global frame, under an
+  /// alias symbol.
+  Import(usize, usize),
+  /// Exports a constant vector of symbols from the module being evaluated.
+  Export(usize),
+  /// Pops the closures for the body, `catch` and `finally` clauses of a
+  /// `try`, and runs them. Missing clauses are `()`.
+  Try,
+  /// Pops a value and matches it against a pattern constant, binding the
+  /// symbols in the pattern to the local slots from the given one on. Pushes
+  /// whether it matched.
+  Match(usize, usize),
+  /// Pops the value that no clause of a `match` matched, and fails.
+  NoMatch,
+}
+
+/// Where a closure finds each of its upvalues when it is created.
+#[derive(Clone, Copy, Debug)]
+pub enum Capture {
+  /// A local slot of the function creating the closure.
+  Local(usize),
+  /// An upvalue of the function creating the closure.
+  Upvalue(usize),
+}
+
+/// The compiled form of a function body, or of a top-level expression.
+#[derive(Debug, Default)]
+pub struct Prototype {
+  pub parameters: Parameters,
+  pub code: Vec<Instruction>,
+  /// The span of the expression each instruction was compiled from.
+  pub spans: Vec<Option<Span>>,
+  pub constants: Vec<Expr>,
+  pub symbols: Vec<Symbol>,
+  pub prototypes: Vec<Rc<Prototype>>,
+  /// The name of each local slot. Parameters come first, in the same order
+  /// as `Parameters::iter`.
+  pub locals: Vec<Symbol>,
+  /// The local slots which are captured by closures, and so have to be
+  /// stored in cells.
+  pub cells: Vec<usize>,
+  pub captures: Vec<Capture>,
+  pub upvalues: Vec<Symbol>,
+}
+
+/// A shared, possibly unset, variable.
+pub type Cell = Rc<RefCell<Option<Expr>>>;
+
+/// A function compiled to bytecode, along with the variables it captured.
+#[derive(Clone)]
+pub struct Closure {
+  inner: Rc<ClosureInner>,
+}
+
+struct ClosureInner {
+  prototype: Rc<Prototype>,
+  upvalues: Vec<Cell>,
+  frame: Frame,
+}
+
+impl Closure {
+  pub fn new(
+    prototype: Rc<Prototype>,
+    upvalues: Vec<Cell>,
+    frame: Frame,
+  ) -> Closure {
+    Closure {
+      inner: Rc::new(ClosureInner {
+        prototype,
+        upvalues,
+        frame,
+      }),
+    }
+  }
+
+  pub fn prototype(&self) -> &Rc<Prototype> {
+    &self.inner.prototype
+  }
+
+  pub fn upvalues(&self) -> &[Cell] {
+    &self.inner.upvalues
+  }
+
+  /// The frame that global symbols are looked up in.
+  pub fn frame(&self) -> &Frame {
+    &self.inner.frame
+  }
+
+  /// Returns whether both closures are the same instance.
+  pub fn ptr_eq(&self, other: &Closure) -> bool {
+    Rc::ptr_eq(&self.inner, &other.inner)
+  }
+
+  pub fn as_ptr(&self) -> *const () {
+    Rc::as_ptr(&self.inner) as *const ()
+  }
+
+  pub fn strong_count(&self) -> usize {
+    Rc::strong_count(&self.inner)
+  }
+}
+
+impl fmt::Display for Closure {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(f, "Function")
+  }
+}
+
+impl fmt::Debug for Closure {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(
+      f,
+      "Closure {{ parameters: {:?}, code: {:?} }}",
+      self.inner.prototype.parameters, self.inner.prototype.code
+    )
+  }
+}
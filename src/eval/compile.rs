@@ -0,0 +1,1153 @@
+use std::rc::Rc;
+
+use crate::ast::{self, Atom, Expr, List, Node, Span, Special, Symbol};
+
+use super::bytecode::{Capture, Instruction, Prototype};
+use super::{as_body, is_else, pattern, EvalError, Evaluator};
+
+/// Compiles expressions into bytecode for the virtual machine.
+///
+/// Symbols bound by an enclosing function are resolved to local slots or
+/// upvalues up front, while every other symbol is looked up in the global
+/// frame when it is run. Macro calls are expanded as they are compiled, so a
+/// macro has to be defined before the code using it is compiled.
+struct Compiler<'a> {
+  evaluator: &'a mut Evaluator,
+  /// The top-level expression, followed by the functions being compiled
+  /// within it, innermost last.
+  scopes: Vec<Prototype>,
+}
+
+impl Evaluator {
+  /// Compiles a top-level expression into a prototype without parameters.
+  pub fn compile(&mut self, expr: &Expr) -> Result<Prototype, EvalError> {
+    let mut compiler = Compiler {
+      evaluator: self,
+      scopes: vec![Prototype::default()],
+    };
+
+    compiler.compile_expr(expr, None, false)?;
+    compiler.emit(Instruction::Return, None);
+
+    Ok(compiler.scopes.pop().unwrap())
+  }
+}
+
+impl<'a> Compiler<'a> {
+  /// Compiles `expr`, which was read from `span`. Expressions in tail
+  /// position are compiled into tail calls.
+  fn compile_expr(
+    &mut self,
+    expr: &Expr,
+    span: Option<Span>,
+    is_tail: bool,
+  ) -> Result<(), EvalError> {
+    match expr {
+      Expr::List(List::Cons(node)) => self
+        .compile_call(node, span, is_tail)
+        .map_err(|error| error.with_span(span)),
+      Expr::Atom(Atom::Symbol(symbol)) => {
+        self.compile_symbol(symbol, span);
+        Ok(())
+      }
+      Expr::Atom(Atom::Vector(vector)) => {
+        for expr in vector.iter() {
+          self.compile_expr(expr, span, false)?;
+        }
+        self.emit(Instruction::Vector(vector.len()), span);
+        Ok(())
+      }
+      Expr::Atom(Atom::Map(map)) => {
+        for (key, value) in map.iter() {
+          self.compile_expr(key, span, false)?;
+          self.compile_expr(value, span, false)?;
+        }
+        self.emit(Instruction::Map(map.len()), span);
+        Ok(())
+      }
+      expr => {
+        self.emit_constant(expr.clone(), span);
+        Ok(())
+      }
+    }
+  }
+
+  fn compile_symbol(&mut self, symbol: &Symbol, span: Option<Span>) {
+    use Instruction::*;
+
+    let depth = self.scopes.len() - 1;
+    let instruction = match self.resolve(depth, symbol) {
+      Some(Capture::Local(slot)) => match self.resolve_outer(slot, symbol) {
+        Some(index) => {
+          // Until the local is defined, the symbol still refers to the
+          // binding of an enclosing function.
+          let jump_to_local = self.emit(JumpIfBound(slot, 0), span);
+          self.emit(GetUpvalue(index), span);
+          let jump_to_end = self.emit(Jump(0), span);
+          self.patch(jump_to_local);
+          self.emit(GetLocal(slot), span);
+          self.patch(jump_to_end);
+          return;
+        }
+        None => GetLocal(slot),
+      },
+      Some(Capture::Upvalue(index)) => GetUpvalue(index),
+      None => GetGlobal(self.symbol(symbol)),
+    };
+
+    self.emit(instruction, span);
+  }
+
+  /// Compiles a call, which was read from `span`.
+  fn compile_call(
+    &mut self,
+    call: &Rc<Node>,
+    span: Option<Span>,
+    is_tail: bool,
+  ) -> Result<(), EvalError> {
+    use Instruction::*;
+
+    match &call.head {
+      Expr::Atom(Atom::Special(special)) => {
+        return self.compile_special(
+          special.clone(),
+          &call.tail,
+          span,
+          is_tail,
+        );
+      }
+      Expr::Atom(Atom::Symbol(symbol)) if !self.is_local(symbol) => {
+        if let Some(Expr::Atom(Atom::Macro(macr))) =
+          self.evaluator.frame.get(symbol)
+        {
+          let expr = self.evaluator.expand_call_site(call, &macr)?;
+          return self.compile_expr(&expr, span, is_tail);
+        }
+        if let Some(special) = self.evaluator.as_conditional(symbol) {
+          return self.compile_special(special, &call.tail, span, is_tail);
+        }
+      }
+      _ => {}
+    }
+
+    self.compile_expr(&call.head, span, false)?;
+    let count = self.compile_arguments(&call.tail)?;
+
+    if is_tail {
+      self.emit(TailCall(count), span);
+    } else {
+      self.emit(Call(count), span);
+    }
+
+    Ok(())
+  }
+
+  /// Compiles each expression in `tail`, returning how many there were.
+  fn compile_arguments(&mut self, tail: &List) -> Result<usize, EvalError> {
+    let mut count = 0;
+    for node in tail.iter() {
+      self.compile_expr(&node.head, node.span, false)?;
+      count += 1;
+    }
+    Ok(count)
+  }
+
+  fn compile_special(
+    &mut self,
+    special: Special,
+    tail: &List,
+    span: Option<Span>,
+    is_tail: bool,
+  ) -> Result<(), EvalError> {
+    use EvalError::*;
+    use Special::*;
+
+    match special {
+      Begin => self.compile_begin(tail, is_tail),
+      Define => self.compile_define(tail, span),
+      Function => self.compile_function(tail, span),
+      Macro | Syntax => self.compile_macro(special, tail, span),
+      If => self.compile_if(tail, span, is_tail),
+      And => self.compile_short_circuit(tail, span, false, is_tail),
+      Or => self.compile_short_circuit(tail, span, true, is_tail),
+      Cond => self.compile_cond(tail, span, is_tail),
+      Case => self.compile_case(tail, span, is_tail),
+      When => self.compile_when(tail, span, true, is_tail),
+      Unless => self.compile_when(tail, span, false, is_tail),
+      Quote => {
+        let expr = self.as_single(tail)?;
+        self.emit_constant(expr.head.clone(), span);
+        Ok(())
+      }
+      Quasiquote => {
+        let expr = self.as_single(tail)?;
+        self.compile_quasiquote(&expr.head, expr.span, 1)
+      }
+      Unquote | UnquoteSplicing => Err(OutsideQuasiquote(special)),
+      MacroExpand => {
+        let expr = self.as_single(tail)?;
+        self.compile_expr(&expr.head, expr.span, false)?;
+        self.emit(Instruction::MacroExpand, span);
+        Ok(())
+      }
+      MacroExpandOnce => {
+        let expr = self.as_single(tail)?;
+        self.compile_expr(&expr.head, expr.span, false)?;
+        self.emit(Instruction::MacroExpandOnce, span);
+        Ok(())
+      }
+      Load => {
+        let expr = self.as_single(tail)?;
+        self.compile_expr(&expr.head, expr.span, false)?;
+        self.emit(Instruction::Load, span);
+        Ok(())
+      }
+      Import => {
+        let (path, alias) = self.evaluator.as_import(tail.clone())?;
+        let path = self.constant(Expr::Atom(Atom::String(path)));
+        let alias = self.symbol(&alias);
+        self.emit(Instruction::Import(path, alias), span);
+        Ok(())
+      }
+      Export => {
+        let names = self.evaluator.as_export(tail.clone())?;
+        let names =
+          names.into_iter().map(|name| Expr::Atom(Atom::Symbol(name)));
+        let names = self.constant(Expr::Atom(Atom::Vector(names.collect())));
+        self.emit(Instruction::Export(names), span);
+        Ok(())
+      }
+      Try => self.compile_try(tail, span),
+      Let | LetStar | Letrec => self.compile_let(special, tail, span, is_tail),
+      Set => self.compile_set(tail, span),
+      Match => self.compile_match(tail, span, is_tail),
+      Operator(operator) => {
+        let count = self.compile_arguments(tail)?;
+        self.emit(Instruction::Operator(operator, count), span);
+        Ok(())
+      }
+    }
+  }
+
+  fn compile_begin(
+    &mut self,
+    tail: &List,
+    is_tail: bool,
+  ) -> Result<(), EvalError> {
+    use EvalError::*;
+
+    if tail.is_empty() {
+      return Err(WrongArity);
+    }
+
+    let mut nodes = tail.iter().peekable();
+    while let Some(node) = nodes.next() {
+      let is_last = nodes.peek().is_none();
+      self.compile_expr(&node.head, node.span, is_tail && is_last)?;
+      if !is_last {
+        self.emit(Instruction::Pop, node.span);
+      }
+    }
+
+    Ok(())
+  }
+
+  /// Compiles a `define`, which binds a local slot within a function, or a
+  /// global symbol at the top level.
+  fn compile_define(
+    &mut self,
+    tail: &List,
+    span: Option<Span>,
+  ) -> Result<(), EvalError> {
+    use EvalError::*;
+    use Instruction::*;
+
+    let (target, value) = match tail {
+      ast::List::Cons(node) => (node.head.clone(), &node.tail),
+      ast::List::Nil => return Err(WrongArity),
+    };
+    let (symbol, value, value_span) = match target {
+      // `(define (name parameters) body...)` defines a function.
+      Expr::List(ast::List::Cons(signature)) => {
+        let body_span = value.iter().next().and_then(|node| node.span);
+        let body = ast::List::cons_spanned(
+          as_body(value.clone())?,
+          ast::List::Nil,
+          body_span,
+        );
+        let function = ast::List::cons(
+          Expr::Atom(Atom::Special(Special::Function)),
+          ast::List::cons(Expr::List(signature.tail.clone()), body),
+        );
+        (signature.head.clone(), Expr::List(function), span)
+      }
+      target => match value.iter().collect::<Vec<_>>()[..] {
+        [value] => (target, value.head.clone(), value.span),
+        _ => return Err(WrongArity),
+      },
+    };
+    let symbol = self.evaluator.as_symbol(symbol)?;
+
+    // Outside of functions, only the clauses of a `match` have local slots.
+    if self.scopes.len() > 1 || self.scopes[0].locals.contains(&symbol) {
+      // Declare the slot first, so that the value can refer to it.
+      let slot = self.declare(&symbol);
+      self.compile_expr(&value, value_span, false)?;
+      self.emit(DefineLocal(slot), span);
+    } else {
+      self.compile_expr(&value, value_span, false)?;
+      let index = self.symbol(&symbol);
+      self.emit(DefineGlobal(index), span);
+    }
+
+    Ok(())
+  }
+
+  /// Compiles a `set!`, which rebinds the local slot, upvalue or global
+  /// symbol that the name refers to.
+  fn compile_set(
+    &mut self,
+    tail: &List,
+    span: Option<Span>,
+  ) -> Result<(), EvalError> {
+    use EvalError::*;
+    use Instruction::*;
+
+    if tail.len() != 2 {
+      return Err(WrongArity);
+    }
+
+    let symbol = self.evaluator.as_symbol(tail.get(0).unwrap().clone())?;
+    let value = tail.iter().nth(1).unwrap();
+    self.compile_expr(&value.head, value.span, false)?;
+
+    let depth = self.scopes.len() - 1;
+    let instruction = match self.resolve(depth, &symbol) {
+      Some(Capture::Local(slot)) => AssignLocal(slot),
+      Some(Capture::Upvalue(index)) => AssignUpvalue(index),
+      None => AssignGlobal(self.symbol(&symbol)),
+    };
+    self.emit(instruction, span);
+
+    Ok(())
+  }
+
+  fn compile_function(
+    &mut self,
+    tail: &List,
+    span: Option<Span>,
+  ) -> Result<(), EvalError> {
+    use EvalError::*;
+    use Instruction::*;
+
+    if tail.len() != 2 {
+      return Err(WrongArity);
+    }
+
+    let parameters = self.evaluator.as_list(tail.get(0).unwrap().clone())?;
+    let parameters = self.evaluator.as_parameters(parameters)?;
+    let body = tail.iter().nth(1).unwrap();
+
+    self.scopes.push(Prototype {
+      parameters: parameters.clone(),
+      locals: parameters.iter().map(|(name, _)| *name).collect(),
+      ..Prototype::default()
+    });
+
+    // Symbols defined anywhere in the body are local to the whole function.
+    let mut defines = Vec::new();
+    collect_defines(&body.head, &mut defines);
+    for symbol in defines.iter() {
+      self.declare(symbol);
+    }
+
+    // Evaluate the defaults of parameters which weren't passed an argument.
+    let defaults: Vec<(usize, Expr)> = parameters
+      .iter()
+      .enumerate()
+      .filter_map(|(slot, (_, default))| Some((slot, default?.clone())))
+      .collect();
+    for (slot, default) in defaults {
+      let jump = self.emit(JumpIfBound(slot, 0), span);
+      self.compile_expr(&default, span, false)?;
+      self.emit(SetLocal(slot), span);
+      self.emit(Pop, span);
+      self.patch(jump);
+    }
+
+    let result = self.compile_expr(&body.head, body.span, true);
+    self.emit(Return, body.span);
+
+    let prototype = self.scopes.pop().unwrap();
+    result?;
+
+    let scope = self.scope();
+    scope.prototypes.push(Rc::new(prototype));
+    let index = scope.prototypes.len() - 1;
+    self.emit(Closure(index), span);
+
+    Ok(())
+  }
+
+  fn compile_macro(
+    &mut self,
+    special: Special,
+    tail: &List,
+    span: Option<Span>,
+  ) -> Result<(), EvalError> {
+    use Instruction::*;
+
+    let (parameter, body) = self.evaluator.as_macro_parts(tail.clone())?;
+
+    let parameter = self.symbol(&parameter);
+    let body = self.constant(body);
+
+    if special == Special::Syntax {
+      self.emit(Syntax(parameter, body), span);
+    } else {
+      self.emit(Macro(parameter, body), span);
+    }
+
+    Ok(())
+  }
+
+  fn compile_if(
+    &mut self,
+    tail: &List,
+    span: Option<Span>,
+    is_tail: bool,
+  ) -> Result<(), EvalError> {
+    use EvalError::*;
+    use Instruction::*;
+
+    if tail.len() != 2 && tail.len() != 3 {
+      return Err(WrongArity);
+    }
+
+    let mut nodes = tail.iter();
+    let condition = nodes.next().unwrap();
+    let consequent = nodes.next().unwrap();
+
+    self.compile_expr(&condition.head, condition.span, false)?;
+    let jump_to_alternative = self.emit(JumpIfFalse(0), span);
+
+    self.compile_expr(&consequent.head, consequent.span, is_tail)?;
+    let jump_to_end = self.emit(Jump(0), span);
+
+    self.patch(jump_to_alternative);
+    match nodes.next() {
+      Some(alternative) => {
+        self.compile_expr(&alternative.head, alternative.span, is_tail)?
+      }
+      None => self.emit_constant(Expr::List(ast::List::Nil), span),
+    }
+    self.patch(jump_to_end);
+
+    Ok(())
+  }
+
+  /// Compiles an `and`, or an `or` if `stop_at` is true. Every value but the
+  /// last jumps to the end if its truthiness is `stop_at`, leaving it on the
+  /// stack as the result, and is popped otherwise.
+  fn compile_short_circuit(
+    &mut self,
+    tail: &List,
+    span: Option<Span>,
+    stop_at: bool,
+    is_tail: bool,
+  ) -> Result<(), EvalError> {
+    use Instruction::*;
+
+    if tail.is_empty() {
+      let empty = if stop_at {
+        Expr::List(ast::List::Nil)
+      } else {
+        Expr::Atom(Atom::Boolean(true))
+      };
+      self.emit_constant(empty, span);
+      return Ok(());
+    }
+
+    let mut jumps = Vec::new();
+    let mut nodes = tail.iter().peekable();
+    while let Some(node) = nodes.next() {
+      let is_last = nodes.peek().is_none();
+      self.compile_expr(&node.head, node.span, is_tail && is_last)?;
+      if !is_last {
+        let jump = if stop_at {
+          JumpIfTrueOrPop(0)
+        } else {
+          JumpIfFalseOrPop(0)
+        };
+        jumps.push(self.emit(jump, span));
+      }
+    }
+
+    for jump in jumps {
+      self.patch(jump);
+    }
+
+    Ok(())
+  }
+
+  fn compile_cond(
+    &mut self,
+    tail: &List,
+    span: Option<Span>,
+    is_tail: bool,
+  ) -> Result<(), EvalError> {
+    use Instruction::*;
+
+    let mut jumps_to_end = Vec::new();
+    let mut has_else = false;
+
+    for node in tail.iter() {
+      let (test, body) = self.evaluator.as_clause(node.head.clone())?;
+
+      if is_else(&test) {
+        self.compile_begin(&body, is_tail)?;
+        has_else = true;
+        break;
+      }
+
+      self.compile_expr(&test, node.span, false)?;
+      if body.is_empty() {
+        // The value of the test is the result.
+        jumps_to_end.push(self.emit(JumpIfTrueOrPop(0), node.span));
+      } else {
+        let jump_to_next = self.emit(JumpIfFalse(0), node.span);
+        self.compile_begin(&body, is_tail)?;
+        jumps_to_end.push(self.emit(Jump(0), node.span));
+        self.patch(jump_to_next);
+      }
+    }
+
+    if !has_else {
+      self.emit_constant(Expr::List(ast::List::Nil), span);
+    }
+    for jump in jumps_to_end {
+      self.patch(jump);
+    }
+
+    Ok(())
+  }
+
+  /// Compiles a `case`, which keeps the key on the stack while it is compared
+  /// with the data of each clause.
+  fn compile_case(
+    &mut self,
+    tail: &List,
+    span: Option<Span>,
+    is_tail: bool,
+  ) -> Result<(), EvalError> {
+    use EvalError::*;
+    use Instruction::*;
+
+    let mut nodes = tail.iter();
+    let key = nodes.next().ok_or(WrongArity)?;
+    self.compile_expr(&key.head, key.span, false)?;
+
+    let mut jumps_to_end = Vec::new();
+    let mut has_else = false;
+
+    for node in nodes {
+      let (data, body) = self.evaluator.as_clause(node.head.clone())?;
+
+      if is_else(&data) {
+        self.emit(Pop, node.span);
+        self.compile_begin(&body, is_tail)?;
+        has_else = true;
+        break;
+      }
+
+      let mut jumps_to_body = Vec::new();
+      for datum in self.evaluator.as_list(data)?.iter() {
+        self.emit(Dup, node.span);
+        self.emit_constant(datum.head.clone(), node.span);
+        self.emit(Operator(ast::Operator::Eq, 2), node.span);
+        jumps_to_body.push(self.emit(JumpIfTrueOrPop(0), node.span));
+      }
+      let jump_to_next = self.emit(Jump(0), node.span);
+
+      for jump in jumps_to_body {
+        self.patch(jump);
+      }
+      // Pop the result of the comparison, and then the key.
+      self.emit(Pop, node.span);
+      self.emit(Pop, node.span);
+      self.compile_begin(&body, is_tail)?;
+      jumps_to_end.push(self.emit(Jump(0), node.span));
+
+      self.patch(jump_to_next);
+    }
+
+    if !has_else {
+      self.emit(Pop, span);
+      self.emit_constant(Expr::List(ast::List::Nil), span);
+    }
+    for jump in jumps_to_end {
+      self.patch(jump);
+    }
+
+    Ok(())
+  }
+
+  /// Compiles a `when`, or an `unless` if `expected` is false.
+  fn compile_when(
+    &mut self,
+    tail: &List,
+    span: Option<Span>,
+    expected: bool,
+    is_tail: bool,
+  ) -> Result<(), EvalError> {
+    use EvalError::*;
+    use Instruction::*;
+
+    let (condition, body) = match tail {
+      ast::List::Cons(node) if !node.tail.is_empty() => (node, &node.tail),
+      _ => return Err(WrongArity),
+    };
+
+    self.compile_expr(&condition.head, condition.span, false)?;
+    let jump_to_alternative = self.emit(JumpIfFalse(0), span);
+
+    // The body is the consequent of `when`, and the alternative of `unless`.
+    let nil = Expr::List(ast::List::Nil);
+    if expected {
+      self.compile_begin(body, is_tail)?;
+    } else {
+      self.emit_constant(nil.clone(), span);
+    }
+    let jump_to_end = self.emit(Jump(0), span);
+
+    self.patch(jump_to_alternative);
+    if expected {
+      self.emit_constant(nil, span);
+    } else {
+      self.compile_begin(body, is_tail)?;
+    }
+    self.patch(jump_to_end);
+
+    Ok(())
+  }
+
+  /// Compiles a `try` into closures for its body and clauses, which the
+  /// virtual machine calls so that it can intercept errors from the body.
+  fn compile_try(
+    &mut self,
+    tail: &List,
+    span: Option<Span>,
+  ) -> Result<(), EvalError> {
+    let (body, catch, finally) = self.evaluator.as_try_parts(tail.clone())?;
+
+    self.compile_closure(List::Nil, body, span)?;
+
+    match catch {
+      Some((name, handler)) => {
+        let parameters = List::cons(Expr::Atom(Atom::Symbol(name)), List::Nil);
+        self.compile_closure(parameters, handler, span)?;
+      }
+      None => self.emit_constant(Expr::List(List::Nil), span),
+    }
+
+    match finally {
+      Some(finally) => self.compile_closure(List::Nil, finally, span)?,
+      None => self.emit_constant(Expr::List(List::Nil), span),
+    }
+
+    self.emit(Instruction::Try, span);
+
+    Ok(())
+  }
+
+  /// Compiles a closure as if it were written `(function parameters body)`.
+  fn compile_closure(
+    &mut self,
+    parameters: List,
+    body: Expr,
+    span: Option<Span>,
+  ) -> Result<(), EvalError> {
+    let body = List::cons_spanned(body, List::Nil, span);
+    let tail = List::cons(Expr::List(parameters), body);
+    self.compile_function(&tail, span)
+  }
+
+  /// Compiles a `match`, which keeps the value on the stack while it is
+  /// matched against the pattern of each clause. The symbols a pattern binds,
+  /// and those its clause defines, are given slots of their own, which only
+  /// the clause can see.
+  fn compile_match(
+    &mut self,
+    tail: &List,
+    span: Option<Span>,
+    is_tail: bool,
+  ) -> Result<(), EvalError> {
+    use Instruction::*;
+
+    let mut nodes = tail.iter();
+    let value = nodes.next().ok_or(EvalError::WrongArity)?;
+    self.compile_expr(&value.head, value.span, false)?;
+
+    let mut jumps_to_end = Vec::new();
+    for node in nodes {
+      let (pattern, guard, body) =
+        self.evaluator.as_match_clause(node.head.clone())?;
+
+      let mut locals = pattern::names(&pattern);
+      let mut defines = Vec::new();
+      collect_defines(&body, &mut defines);
+      if let Some(guard) = &guard {
+        collect_defines(guard, &mut defines);
+      }
+      for symbol in defines {
+        if !locals.contains(&symbol) {
+          locals.push(symbol);
+        }
+      }
+
+      let scope = self.scope();
+      let first = scope.locals.len();
+      scope.locals.extend(locals);
+
+      let pattern = self.constant(pattern);
+      self.emit(Dup, node.span);
+      self.emit(Match(pattern, first), node.span);
+      let mut jumps_to_next = vec![self.emit(JumpIfFalse(0), node.span)];
+      if let Some(guard) = &guard {
+        self.compile_expr(guard, node.span, false)?;
+        jumps_to_next.push(self.emit(JumpIfFalse(0), node.span));
+      }
+      self.emit(Pop, node.span);
+      self.compile_expr(&body, node.span, is_tail)?;
+      jumps_to_end.push(self.emit(Jump(0), node.span));
+
+      for jump in jumps_to_next {
+        self.patch(jump);
+      }
+
+      // Hide the slots from the code after the clause.
+      for local in self.scope().locals[first..].iter_mut() {
+        *local = Symbol::gensym(&local.name());
+      }
+    }
+
+    self.emit(NoMatch, span);
+    for jump in jumps_to_end {
+      self.patch(jump);
+    }
+
+    Ok(())
+  }
+
+  /// Compiles a `let` into a call to a closure taking the bindings as
+  /// parameters, and a `let*` into nested `let`s. The bindings of a `letrec`
+  /// are defined within a closure taking no parameters, as is the function
+  /// bound by a named `let`.
+  fn compile_let(
+    &mut self,
+    special: Special,
+    tail: &List,
+    span: Option<Span>,
+    is_tail: bool,
+  ) -> Result<(), EvalError> {
+    use Instruction::*;
+
+    let name = match (&special, tail) {
+      (Special::Let, ast::List::Cons(node)) => match &node.head {
+        Expr::Atom(Atom::Symbol(name)) => Some((*name, node.tail.clone())),
+        _ => None,
+      },
+      _ => None,
+    };
+    let (bindings, body, body_span) = match &name {
+      Some((_, rest)) => self.evaluator.as_let_parts(rest.clone())?,
+      None => self.evaluator.as_let_parts(tail.clone())?,
+    };
+
+    let names = bindings
+      .iter()
+      .map(|(name, _)| Expr::Atom(Atom::Symbol(*name)));
+    let names = to_list(names.collect());
+
+    match (special, name) {
+      (Special::LetStar, _) if bindings.len() > 1 => {
+        // `(let* (first rest...) body)` is `(let (first) (let* (rest) body))`.
+        let mut bindings = bindings.into_iter().map(|(name, value)| {
+          let name = Expr::Atom(Atom::Symbol(name));
+          Expr::List(to_list(vec![name, value]))
+        });
+        let first = bindings.next().unwrap();
+        let inner = to_list_spanned(
+          vec![
+            Expr::Atom(Atom::Special(Special::LetStar)),
+            Expr::List(to_list(bindings.collect())),
+          ],
+          body,
+          body_span,
+        );
+        let outer = to_list_spanned(
+          vec![
+            Expr::Atom(Atom::Special(Special::Let)),
+            Expr::List(to_list(vec![first])),
+          ],
+          Expr::List(inner),
+          span,
+        );
+        return self.compile_expr(&Expr::List(outer), span, is_tail);
+      }
+      (Special::Letrec, _) => {
+        let define = Expr::Atom(Atom::Special(Special::Define));
+        let mut exprs = vec![Expr::Atom(Atom::Special(Special::Begin))];
+        for (name, value) in bindings {
+          let name = Expr::Atom(Atom::Symbol(name));
+          exprs.push(Expr::List(to_list(vec![define.clone(), name, value])));
+        }
+        self.compile_closure(
+          ast::List::Nil,
+          Expr::List(to_list_spanned(exprs, body, body_span)),
+          span,
+        )?;
+        self.emit(if is_tail { TailCall(0) } else { Call(0) }, span);
+        return Ok(());
+      }
+      (_, Some((name, _))) => {
+        let name = Expr::Atom(Atom::Symbol(name));
+        let function = to_list_spanned(
+          vec![
+            Expr::Atom(Atom::Special(Special::Function)),
+            Expr::List(names),
+          ],
+          body,
+          body_span,
+        );
+        let define = to_list(vec![
+          Expr::Atom(Atom::Special(Special::Define)),
+          name.clone(),
+          Expr::List(function),
+        ]);
+        let body = to_list(vec![
+          Expr::Atom(Atom::Special(Special::Begin)),
+          Expr::List(define),
+          name,
+        ]);
+        self.compile_closure(ast::List::Nil, Expr::List(body), span)?;
+        self.emit(Call(0), span);
+      }
+      (_, None) => self.compile_closure(names, body, body_span)?,
+    }
+
+    for (_, value) in bindings.iter() {
+      self.compile_expr(value, span, false)?;
+    }
+    let count = bindings.len();
+    self.emit(
+      if is_tail {
+        TailCall(count)
+      } else {
+        Call(count)
+      },
+      span,
+    );
+
+    Ok(())
+  }
+
+  /// Compiles `expr` quasiquoted at `depth`, in the same way as
+  /// `Evaluator::quasiquote` evaluates it.
+  fn compile_quasiquote(
+    &mut self,
+    expr: &Expr,
+    span: Option<Span>,
+    depth: usize,
+  ) -> Result<(), EvalError> {
+    use Special::*;
+
+    let list = match expr {
+      Expr::List(list) if contains_unquote(list) => list,
+      expr => {
+        self.emit_constant(expr.clone(), span);
+        return Ok(());
+      }
+    };
+
+    let special = match list.get(0) {
+      Some(Expr::Atom(Atom::Special(special))) if list.len() == 2 => {
+        Some(special.clone())
+      }
+      _ => None,
+    };
+
+    match special {
+      Some(Unquote) if depth == 1 => {
+        let expr = list.iter().nth(1).unwrap();
+        self.compile_expr(&expr.head, expr.span, false)
+      }
+      Some(Unquote) | Some(UnquoteSplicing) => {
+        self.compile_quasiquote_elements(list, span, depth - 1)
+      }
+      Some(Quasiquote) => {
+        self.compile_quasiquote_elements(list, span, depth + 1)
+      }
+      _ => self.compile_quasiquote_elements(list, span, depth),
+    }
+  }
+
+  /// Compiles the elements of `list` into runs of elements, which are built
+  /// into lists and appended to the lists spliced in between them.
+  fn compile_quasiquote_elements(
+    &mut self,
+    list: &List,
+    span: Option<Span>,
+    depth: usize,
+  ) -> Result<(), EvalError> {
+    use EvalError::*;
+    use Instruction::*;
+
+    let mut lists = 0;
+    let mut elements = 0;
+    let mut is_spliced = false;
+
+    for node in list.iter() {
+      let splice = match &node.head {
+        Expr::List(element) if depth == 1 => match element.get(0) {
+          Some(Expr::Atom(Atom::Special(Special::UnquoteSplicing))) => {
+            if element.len() != 2 {
+              return Err(WrongArity);
+            }
+            element.iter().nth(1)
+          }
+          _ => None,
+        },
+        _ => None,
+      };
+
+      match splice {
+        Some(spliced) => {
+          if elements > 0 {
+            self.emit(List(elements), span);
+            lists += 1;
+            elements = 0;
+          }
+          self.compile_expr(&spliced.head, node.span, false)?;
+          lists += 1;
+          is_spliced = true;
+        }
+        None => {
+          self.compile_quasiquote(&node.head, node.span, depth)?;
+          elements += 1;
+        }
+      }
+    }
+
+    if elements > 0 || lists == 0 {
+      self.emit(List(elements), span);
+      lists += 1;
+    }
+    if is_spliced {
+      self.emit(Append(lists), span);
+    }
+
+    Ok(())
+  }
+
+  /// Finds the local slot or upvalue that `symbol` refers to within the
+  /// function at `depth`, capturing it from the enclosing functions if
+  /// needed. Returns `None` if the symbol is global.
+  fn resolve(&mut self, depth: usize, symbol: &Symbol) -> Option<Capture> {
+    let scope = &self.scopes[depth];
+
+    if let Some(slot) = scope.locals.iter().rposition(|local| local == symbol) {
+      return Some(Capture::Local(slot));
+    }
+    if let Some(index) = scope.upvalues.iter().position(|name| name == symbol) {
+      return Some(Capture::Upvalue(index));
+    }
+    if depth == 0 {
+      return None;
+    }
+
+    let capture = self.resolve(depth - 1, symbol)?;
+    Some(Capture::Upvalue(self.capture(depth, symbol, capture)))
+  }
+
+  /// Adds an upvalue for `symbol` to the function at `depth`, found where
+  /// `capture` says in the function enclosing it.
+  fn capture(
+    &mut self,
+    depth: usize,
+    symbol: &Symbol,
+    capture: Capture,
+  ) -> usize {
+    if let Capture::Local(slot) = capture {
+      let enclosing = &mut self.scopes[depth - 1];
+      if !enclosing.cells.contains(&slot) {
+        enclosing.cells.push(slot);
+      }
+    }
+
+    let scope = &mut self.scopes[depth];
+    scope.captures.push(capture);
+    scope.upvalues.push(*symbol);
+    scope.upvalues.len() - 1
+  }
+
+  /// Returns an upvalue for the binding of `symbol` in the functions
+  /// enclosing the current one, if the current function `define`s its own
+  /// `symbol` in `slot` and so could refer to it before it is defined.
+  fn resolve_outer(&mut self, slot: usize, symbol: &Symbol) -> Option<usize> {
+    let depth = self.scopes.len() - 1;
+    if depth == 0 || slot < self.scopes[depth].parameters.iter().count() {
+      return None;
+    }
+
+    let capture = self.resolve(depth - 1, symbol)?;
+    Some(self.capture(depth, symbol, capture))
+  }
+
+  fn is_local(&self, symbol: &Symbol) -> bool {
+    self.scopes.iter().any(|scope| {
+      scope.locals.contains(symbol) || scope.upvalues.contains(symbol)
+    })
+  }
+
+  /// Returns the local slot for `symbol` in the current function, adding one
+  /// if there isn't one yet.
+  fn declare(&mut self, symbol: &Symbol) -> usize {
+    let scope = self.scope();
+
+    match scope.locals.iter().rposition(|local| local == symbol) {
+      Some(slot) => slot,
+      None => {
+        scope.locals.push(*symbol);
+        scope.locals.len() - 1
+      }
+    }
+  }
+
+  fn as_single<'l>(&mut self, tail: &'l List) -> Result<&'l Node, EvalError> {
+    use EvalError::*;
+
+    if tail.len() != 1 {
+      return Err(WrongArity);
+    }
+
+    Ok(tail.iter().next().unwrap())
+  }
+
+  fn scope(&mut self) -> &mut Prototype {
+    self.scopes.last_mut().unwrap()
+  }
+
+  /// Appends `instruction`, returning its index.
+  fn emit(&mut self, instruction: Instruction, span: Option<Span>) -> usize {
+    let scope = self.scope();
+    scope.code.push(instruction);
+    scope.spans.push(span);
+    scope.code.len() - 1
+  }
+
+  fn emit_constant(&mut self, expr: Expr, span: Option<Span>) {
+    let index = self.constant(expr);
+    self.emit(Instruction::Constant(index), span);
+  }
+
+  /// Points the jump at `index` to the next instruction to be emitted.
+  fn patch(&mut self, index: usize) {
+    use Instruction::*;
+
+    let scope = self.scope();
+    let target = scope.code.len();
+    scope.code[index] = match scope.code[index] {
+      Jump(_) => Jump(target),
+      JumpIfFalse(_) => JumpIfFalse(target),
+      JumpIfFalseOrPop(_) => JumpIfFalseOrPop(target),
+      JumpIfTrueOrPop(_) => JumpIfTrueOrPop(target),
+      JumpIfBound(slot, _) => JumpIfBound(slot, target),
+      instruction => instruction,
+    };
+  }
+
+  fn constant(&mut self, expr: Expr) -> usize {
+    let scope = self.scope();
+    scope.constants.push(expr);
+    scope.constants.len() - 1
+  }
+
+  fn symbol(&mut self, symbol: &Symbol) -> usize {
+    let scope = self.scope();
+
+    match scope.symbols.iter().position(|other| other == symbol) {
+      Some(index) => index,
+      None => {
+        scope.symbols.push(*symbol);
+        scope.symbols.len() - 1
+      }
+    }
+  }
+}
+
+/// Collects the symbols defined within `expr`, without looking inside nested
+/// functions or quoted expressions.
+fn collect_defines(expr: &Expr, defines: &mut Vec<Symbol>) {
+  use Special::*;
+
+  let list = match expr {
+    Expr::List(list) => list,
+    Expr::Atom(_) => return,
+  };
+
+  match list.get(0) {
+    Some(Expr::Atom(Atom::Special(Define))) => match list.get(1) {
+      Some(Expr::Atom(Atom::Symbol(symbol))) => defines.push(*symbol),
+      Some(Expr::List(signature)) => {
+        if let Some(Expr::Atom(Atom::Symbol(symbol))) = signature.get(0) {
+          defines.push(*symbol);
+        }
+        return;
+      }
+      _ => {}
+    },
+    Some(Expr::Atom(Atom::Special(Let)))
+    | Some(Expr::Atom(Atom::Special(LetStar)))
+    | Some(Expr::Atom(Atom::Special(Letrec))) => return,
+    Some(Expr::Atom(Atom::Special(Quote)))
+    | Some(Expr::Atom(Atom::Special(Quasiquote)))
+    | Some(Expr::Atom(Atom::Special(Function)))
+    | Some(Expr::Atom(Atom::Special(Try)))
+    | Some(Expr::Atom(Atom::Special(Match)))
+    | Some(Expr::Atom(Atom::Special(Macro)))
+    | Some(Expr::Atom(Atom::Special(Syntax))) => return,
+    _ => {}
+  }
+
+  for node in list.iter() {
+    collect_defines(&node.head, defines);
+  }
+}
+
+fn to_list(exprs: Vec<Expr>) -> List {
+  exprs
+    .into_iter()
+    .rev()
+    .fold(List::Nil, |list, expr| List::cons(expr, list))
+}
+
+/// Like `to_list`, but ends the list with `last`, which was read from `span`.
+fn to_list_spanned(exprs: Vec<Expr>, last: Expr, span: Option<Span>) -> List {
+  let last = List::cons_spanned(last, List::Nil, span);
+  exprs
+    .into_iter()
+    .rev()
+    .fold(last, |list, expr| List::cons(expr, list))
+}
+
+/// Returns whether `list` contains an `unquote` or `unquote-splicing` at any
+/// depth, since otherwise it can be quoted as it is.
+fn contains_unquote(list: &List) -> bool {
+  list.iter().any(|node| match &node.head {
+    Expr::Atom(Atom::Special(Special::Unquote))
+    | Expr::Atom(Atom::Special(Special::UnquoteSplicing)) => true,
+    Expr::List(list) => contains_unquote(list),
+    Expr::Atom(_) => false,
+  })
+}
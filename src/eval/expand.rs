@@ -0,0 +1,379 @@
+use std::collections::HashMap;
+use std::rc::{Rc, Weak};
+
+use crate::ast::{Atom, Expr, List, Macro, Node, Special, Symbol};
+
+use super::{pattern, EvalError, Evaluator};
+
+/// Remembers the expansion of each macro call site, so that a macro used in a
+/// loop is only expanded once.
+#[derive(Default)]
+pub struct Expansions {
+  entries: HashMap<*const Node, Expansion>,
+  prune_at: usize,
+}
+
+struct Expansion {
+  call: Weak<Node>,
+  macr: Macro,
+  expr: Expr,
+}
+
+impl Expansions {
+  pub fn new() -> Expansions {
+    Expansions::default()
+  }
+
+  fn get(&self, call: &Rc<Node>, macr: &Macro) -> Option<Expr> {
+    let expansion = self.entries.get(&Rc::as_ptr(call))?;
+
+    // The call site might have been freed and its address reused, or the
+    // symbol might now refer to another macro.
+    let is_same_call = expansion
+      .call
+      .upgrade()
+      .map(|expansion_call| Rc::ptr_eq(&expansion_call, call))
+      .unwrap_or(false);
+    if !is_same_call || &expansion.macr != macr {
+      return None;
+    }
+
+    Some(expansion.expr.clone())
+  }
+
+  fn insert(&mut self, call: &Rc<Node>, macr: &Macro, expr: Expr) {
+    // Forget call sites that no longer exist every now and then.
+    if self.entries.len() >= self.prune_at {
+      self
+        .entries
+        .retain(|_, expansion| expansion.call.strong_count() > 0);
+      self.prune_at = (self.entries.len() * 2).max(256);
+    }
+
+    let expansion = Expansion {
+      call: Rc::downgrade(call),
+      macr: macr.clone(),
+      expr,
+    };
+    self.entries.insert(Rc::as_ptr(call), expansion);
+  }
+}
+
+impl Evaluator {
+  /// Expands every macro call within the body of a function whose macro is
+  /// visible from the current frame, leaving quoted expressions and calls to
+  /// locally bound symbols, including the function's `parameters`, alone.
+  pub fn expand(
+    &mut self,
+    expr: Expr,
+    mut parameters: Vec<Symbol>,
+  ) -> Result<Expr, EvalError> {
+    collect_defines(&expr, &mut parameters);
+    let expanded = self.expand_in(&expr, &mut parameters)?;
+    Ok(expanded.unwrap_or(expr))
+  }
+
+  /// Expands `expr` once if it is a macro call, or returns it unchanged.
+  pub fn macroexpand_1(&mut self, expr: Expr) -> Result<Expr, EvalError> {
+    match self.as_macro_call(&expr, &[]) {
+      Some((call, macr)) => self.expand_call_site(&call, &macr),
+      None => Ok(expr),
+    }
+  }
+
+  /// Expands `expr` repeatedly until it is no longer a macro call.
+  pub fn macroexpand(&mut self, mut expr: Expr) -> Result<Expr, EvalError> {
+    while let Some((call, macr)) = self.as_macro_call(&expr, &[]) {
+      expr = self.expand_call_site(&call, &macr)?;
+    }
+    Ok(expr)
+  }
+
+  /// Expands the macro call at `call`, reusing the previous expansion of the
+  /// same call site if there is one.
+  pub fn expand_call_site(
+    &mut self,
+    call: &Rc<Node>,
+    macr: &Macro,
+  ) -> Result<Expr, EvalError> {
+    if let Some(expr) = self.expansions.get(call, macr) {
+      return Ok(expr);
+    }
+
+    let expr = self.expand_macro(macr, call.tail.clone())?;
+    self.expansions.insert(call, macr, expr.clone());
+
+    Ok(expr)
+  }
+
+  /// Returns the expanded expression, or `None` if nothing was expanded.
+  fn expand_in(
+    &mut self,
+    expr: &Expr,
+    locals: &mut Vec<Symbol>,
+  ) -> Result<Option<Expr>, EvalError> {
+    let list = match expr {
+      Expr::List(list) => list,
+      Expr::Atom(_) => return Ok(None),
+    };
+
+    if let Some((call, macr)) = self.as_macro_call(expr, locals) {
+      let expanded = self.expand_call_site(&call, &macr)?;
+      let expanded = self.expand_in(&expanded, locals)?.unwrap_or(expanded);
+      return Ok(Some(expanded));
+    }
+
+    // Conditionals are recognised now, as the resolver can't tell whether
+    // their names are bound.
+    if let (Some(special), List::Cons(call)) =
+      (self.as_conditional_call(list, locals), list)
+    {
+      let head = Expr::Atom(Atom::Special(special));
+      let list =
+        Expr::List(List::cons_spanned(head, call.tail.clone(), call.span));
+      let expanded = self.expand_in(&list, locals)?.unwrap_or(list);
+      return Ok(Some(expanded));
+    }
+
+    let locals_len = locals.len();
+    let special = match list.get(0) {
+      Some(Expr::Atom(Atom::Special(special))) => Some(special),
+      _ => None,
+    };
+    match special {
+      Some(Special::Quote) | Some(Special::Quasiquote) => return Ok(None),
+      Some(Special::Function)
+      | Some(Special::Macro)
+      | Some(Special::Syntax) => {
+        if let Some(Expr::List(parameters)) = list.get(1) {
+          collect_parameters(parameters, locals);
+        }
+        collect_defines(expr, locals);
+      }
+      Some(Special::Define) => {
+        // `(define (name parameters) body)` binds its parameters in the body.
+        if let Some(Expr::List(signature)) = list.get(1) {
+          collect_parameters(signature, locals);
+          collect_defines(expr, locals);
+        }
+      }
+      Some(Special::Let) | Some(Special::LetStar) | Some(Special::Letrec) => {
+        // Bindings are written like optional parameters, and a named `let`
+        // binds its name too.
+        let bindings = match list.get(1) {
+          Some(Expr::Atom(Atom::Symbol(name))) => {
+            locals.push(*name);
+            list.get(2)
+          }
+          bindings => bindings,
+        };
+        if let Some(Expr::List(bindings)) = bindings {
+          collect_parameters(bindings, locals);
+        }
+        collect_defines(expr, locals);
+      }
+      Some(Special::Try) => collect_catch(list, locals),
+      Some(Special::Cond) | Some(Special::Case) | Some(Special::Match) => {
+        // The patterns of a `match` bind their symbols in the clause bodies.
+        if special == Some(&Special::Match) {
+          for node in list.iter().skip(2) {
+            if let Expr::List(List::Cons(clause)) = &node.head {
+              locals.extend(pattern::names(&clause.head));
+            }
+          }
+        }
+        let has_key = special != Some(&Special::Cond);
+        let result = self.expand_clauses(list, has_key, locals);
+        locals.truncate(locals_len);
+        return Ok(result?.map(Expr::List));
+      }
+      _ => {}
+    }
+
+    let result = self.expand_elements(list, locals);
+    locals.truncate(locals_len);
+
+    Ok(result?.map(Expr::List))
+  }
+
+  /// Expands a `cond`, `case` or `match`. Its clauses aren't calls, so only
+  /// their elements are expanded. If it has a key, like `case` and `match`,
+  /// the heads of the clauses are data or patterns, which aren't expanded.
+  fn expand_clauses(
+    &mut self,
+    list: &List,
+    has_key: bool,
+    locals: &mut Vec<Symbol>,
+  ) -> Result<Option<List>, EvalError> {
+    let mut is_expanded = false;
+    let mut elements = Vec::new();
+
+    for (index, node) in list.iter().enumerate() {
+      let expanded = match &node.head {
+        _ if index == 0 => None,
+        expr if has_key && index == 1 => self.expand_in(expr, locals)?,
+        Expr::List(List::Cons(clause)) if has_key => {
+          self.expand_elements(&clause.tail, locals)?.map(|body| {
+            Expr::List(List::cons_spanned(
+              clause.head.clone(),
+              body,
+              clause.span,
+            ))
+          })
+        }
+        Expr::List(clause) => {
+          self.expand_elements(clause, locals)?.map(Expr::List)
+        }
+        Expr::Atom(_) => None,
+      };
+      if expanded.is_some() {
+        is_expanded = true;
+      }
+      elements.push((expanded.unwrap_or_else(|| node.head.clone()), node.span));
+    }
+
+    if !is_expanded {
+      return Ok(None);
+    }
+
+    let list = elements
+      .into_iter()
+      .rev()
+      .fold(List::Nil, |list, (expr, span)| {
+        List::cons_spanned(expr, list, span)
+      });
+
+    Ok(Some(list))
+  }
+
+  fn expand_elements(
+    &mut self,
+    list: &List,
+    locals: &mut Vec<Symbol>,
+  ) -> Result<Option<List>, EvalError> {
+    let mut is_expanded = false;
+    let mut elements = Vec::new();
+
+    for node in list.iter() {
+      let element = match self.expand_in(&node.head, locals)? {
+        Some(expanded) => {
+          is_expanded = true;
+          expanded
+        }
+        None => node.head.clone(),
+      };
+      elements.push((element, node.span));
+    }
+
+    if !is_expanded {
+      return Ok(None);
+    }
+
+    let list = elements
+      .into_iter()
+      .rev()
+      .fold(List::Nil, |list, (expr, span)| {
+        List::cons_spanned(expr, list, span)
+      });
+
+    Ok(Some(list))
+  }
+
+  /// Returns the call site and macro if `expr` is a call to a macro that is
+  /// visible from the current frame and not shadowed by `locals`.
+  fn as_macro_call(
+    &mut self,
+    expr: &Expr,
+    locals: &[Symbol],
+  ) -> Option<(Rc<Node>, Macro)> {
+    let call = match expr {
+      Expr::List(List::Cons(call)) => call,
+      _ => return None,
+    };
+
+    let symbol = match &call.head {
+      Expr::Atom(Atom::Symbol(symbol)) => symbol,
+      _ => return None,
+    };
+    if locals.contains(symbol) {
+      return None;
+    }
+
+    match self.frame.get(symbol) {
+      Some(Expr::Atom(Atom::Macro(macr))) => Some((call.clone(), macr)),
+      _ => None,
+    }
+  }
+
+  /// Returns the conditional form `list` is a call to, if its head names one
+  /// and isn't bound.
+  fn as_conditional_call(
+    &self,
+    list: &List,
+    locals: &[Symbol],
+  ) -> Option<Special> {
+    match list.get(0) {
+      Some(Expr::Atom(Atom::Symbol(symbol))) if !locals.contains(symbol) => {
+        self.as_conditional(symbol)
+      }
+      _ => None,
+    }
+  }
+}
+
+fn collect_parameters(parameters: &List, locals: &mut Vec<Symbol>) {
+  for node in parameters.iter() {
+    match &node.head {
+      Expr::Atom(Atom::Symbol(symbol)) => locals.push(*symbol),
+      Expr::Atom(Atom::Keyword(keyword)) => locals.push(*keyword),
+      Expr::List(parameter) => match parameter.get(0) {
+        Some(Expr::Atom(Atom::Symbol(symbol)))
+        | Some(Expr::Atom(Atom::Keyword(symbol))) => locals.push(*symbol),
+        _ => {}
+      },
+      _ => {}
+    }
+  }
+}
+
+/// Collects the symbol bound by the `catch` clause of a `try`, if it has one.
+fn collect_catch(list: &List, locals: &mut Vec<Symbol>) {
+  for node in list.iter().skip(2) {
+    if let Expr::List(clause) = &node.head {
+      if let Some(Expr::Atom(Atom::Symbol(name))) = clause.get(0) {
+        if &*name.name() == "catch" {
+          if let Some(Expr::Atom(Atom::Symbol(symbol))) = clause.get(1) {
+            locals.push(*symbol);
+          }
+        }
+      }
+    }
+  }
+}
+
+/// Collects the symbols defined anywhere within `expr`, since they shadow
+/// macros for the whole function body.
+fn collect_defines(expr: &Expr, locals: &mut Vec<Symbol>) {
+  let list = match expr {
+    Expr::List(list) => list,
+    Expr::Atom(_) => return,
+  };
+
+  match list.get(0) {
+    Some(Expr::Atom(Atom::Special(Special::Quote))) => return,
+    Some(Expr::Atom(Atom::Special(Special::Define))) => match list.get(1) {
+      Some(Expr::Atom(Atom::Symbol(symbol))) => locals.push(*symbol),
+      Some(Expr::List(signature)) => {
+        if let Some(Expr::Atom(Atom::Symbol(symbol))) = signature.get(0) {
+          locals.push(*symbol);
+        }
+      }
+      _ => {}
+    },
+    _ => {}
+  }
+
+  for node in list.iter() {
+    collect_defines(&node.head, locals);
+  }
+}
@@ -0,0 +1,259 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use crate::ast::{Atom, Expr, List, Special, Symbol};
+
+use super::pattern;
+
+/// Tracks the symbols passed into a hygienic macro, so that they can be told
+/// apart from the symbols the macro introduces into its expansion.
+///
+/// Marked symbols are equal to the symbols they were marked from, so the
+/// macro can inspect its arguments as usual.
+#[derive(Default)]
+pub struct Marks {
+  symbols: Vec<Symbol>,
+}
+
+impl Marks {
+  pub fn new() -> Marks {
+    Marks::default()
+  }
+
+  /// Replaces every symbol within `expr` with a marked copy.
+  pub fn mark(&mut self, expr: Expr) -> Expr {
+    map_symbols(expr, &mut |symbol| {
+      let marked = symbol.mark();
+      self.symbols.push(marked);
+      marked
+    })
+  }
+
+  fn is_marked(&self, symbol: &Symbol) -> bool {
+    self.symbols.iter().any(|marked| marked.is_same(symbol))
+  }
+}
+
+/// Renames the bindings introduced by a macro expansion to fresh symbols, so
+/// that they cannot capture symbols passed in by the caller. Symbols passed
+/// in by the caller are restored to their unmarked selves.
+///
+/// `conditionals` are the conditional forms which aren't bound where the
+/// macro is called, so calls to them are recognised by name.
+pub fn rename(
+  expr: Expr,
+  marks: &Marks,
+  conditionals: &[(Symbol, Special)],
+) -> Expr {
+  let mut introduced = BTreeSet::new();
+  collect_bindings(&expr, marks, conditionals, &mut introduced);
+
+  let mut renames: BTreeMap<Symbol, Symbol> = BTreeMap::new();
+  for symbol in introduced {
+    let rename = Symbol::gensym(&symbol.name());
+    renames.insert(symbol, rename);
+  }
+
+  rename_symbols(expr, marks, conditionals, &renames)
+}
+
+/// Returns the special form `list` is a call to, if any.
+fn special_of<'a>(
+  list: &'a List,
+  conditionals: &'a [(Symbol, Special)],
+) -> Option<&'a Special> {
+  match list.get(0)? {
+    Expr::Atom(Atom::Special(special)) => Some(special),
+    Expr::Atom(Atom::Symbol(symbol)) => conditionals
+      .iter()
+      .find(|(conditional, _)| conditional == symbol)
+      .map(|(_, special)| special),
+    _ => None,
+  }
+}
+
+/// Collects the symbols bound by binding forms within `expr` that were not
+/// passed in by the caller.
+fn collect_bindings(
+  expr: &Expr,
+  marks: &Marks,
+  conditionals: &[(Symbol, Special)],
+  bindings: &mut BTreeSet<Symbol>,
+) {
+  let list = match expr {
+    Expr::List(list) => list,
+    Expr::Atom(_) => return,
+  };
+
+  let mut introduce = |expr: &Expr| {
+    if let Expr::Atom(Atom::Symbol(symbol)) = expr {
+      if !marks.is_marked(symbol) && &*symbol.name() != "&" {
+        bindings.insert(*symbol);
+      }
+    }
+  };
+
+  match special_of(list, conditionals) {
+    Some(Special::Quote) => return,
+    Some(Special::Define) => match list.get(1) {
+      // `(define (name parameters) body)` binds the name and parameters.
+      Some(Expr::List(signature)) => introduce_each(signature, &mut introduce),
+      Some(expr) => introduce(expr),
+      None => {}
+    },
+    Some(Special::Function) | Some(Special::Macro) | Some(Special::Syntax) => {
+      if let Some(Expr::List(parameters)) = list.get(1) {
+        introduce_each(parameters, &mut introduce);
+      }
+    }
+    Some(Special::Match) => {
+      for node in list.iter().skip(2) {
+        if let Expr::List(List::Cons(clause)) = &node.head {
+          for name in pattern::names(&clause.head) {
+            introduce(&Expr::Atom(Atom::Symbol(name)));
+          }
+        }
+      }
+    }
+    Some(Special::Let) | Some(Special::LetStar) | Some(Special::Letrec) => {
+      // A named `let` binds its name before the bindings.
+      let bindings = match list.get(1) {
+        Some(name @ Expr::Atom(Atom::Symbol(_))) => {
+          introduce(name);
+          list.get(2)
+        }
+        bindings => bindings,
+      };
+      if let Some(Expr::List(bindings)) = bindings {
+        introduce_each(bindings, &mut introduce);
+      }
+    }
+    Some(Special::Try) => {
+      for node in list.iter().skip(2) {
+        if let Expr::List(clause) = &node.head {
+          if let Some(Expr::Atom(Atom::Symbol(name))) = clause.get(0) {
+            if &*name.name() == "catch" {
+              if let Some(expr) = clause.get(1) {
+                introduce(expr);
+              }
+            }
+          }
+        }
+      }
+    }
+    _ => {}
+  }
+
+  for node in list.iter() {
+    collect_bindings(&node.head, marks, conditionals, bindings);
+  }
+}
+
+/// Introduces each name in a parameter list or the bindings of a `let`, where
+/// optional parameters and bindings are written as `(name expr)`.
+fn introduce_each<F>(list: &List, introduce: &mut F)
+where
+  F: FnMut(&Expr),
+{
+  for node in list.iter() {
+    match &node.head {
+      Expr::List(pair) => introduce(pair.get(0).unwrap_or(&node.head)),
+      expr => introduce(expr),
+    }
+  }
+}
+
+fn rename_symbols(
+  expr: Expr,
+  marks: &Marks,
+  conditionals: &[(Symbol, Special)],
+  renames: &BTreeMap<Symbol, Symbol>,
+) -> Expr {
+  if let Expr::List(list) = &expr {
+    match special_of(list, conditionals) {
+      Some(Special::Quote) => {
+        return map_symbols(expr, &mut |symbol| symbol.unmark());
+      }
+      // The data of `case` clauses are quoted too.
+      Some(Special::Case) => {
+        return Expr::List(rename_case(
+          list.clone(),
+          marks,
+          conditionals,
+          renames,
+        ));
+      }
+      _ => {}
+    }
+  }
+
+  match expr {
+    Expr::List(list) => Expr::List(map_list(list, &mut |expr| {
+      rename_symbols(expr, marks, conditionals, renames)
+    })),
+    Expr::Atom(Atom::Symbol(symbol)) => {
+      let symbol = if marks.is_marked(&symbol) {
+        symbol.unmark()
+      } else if let Some(rename) = renames.get(&symbol) {
+        *rename
+      } else {
+        symbol
+      };
+      Expr::Atom(Atom::Symbol(symbol))
+    }
+    expr => expr,
+  }
+}
+
+fn rename_case(
+  list: List,
+  marks: &Marks,
+  conditionals: &[(Symbol, Special)],
+  renames: &BTreeMap<Symbol, Symbol>,
+) -> List {
+  let mut index = 0;
+  map_list(list, &mut |expr| {
+    index += 1;
+    match expr {
+      Expr::List(List::Cons(clause)) if index > 2 => {
+        let data =
+          map_symbols(clause.head.clone(), &mut |symbol| symbol.unmark());
+        let body = map_list(clause.tail.clone(), &mut |expr| {
+          rename_symbols(expr, marks, conditionals, renames)
+        });
+        Expr::List(List::cons_spanned(data, body, clause.span))
+      }
+      expr => rename_symbols(expr, marks, conditionals, renames),
+    }
+  })
+}
+
+fn map_symbols<F>(expr: Expr, f: &mut F) -> Expr
+where
+  F: FnMut(&Symbol) -> Symbol,
+{
+  match expr {
+    Expr::List(list) => {
+      Expr::List(map_list(list, &mut |expr| map_symbols(expr, f)))
+    }
+    Expr::Atom(Atom::Symbol(symbol)) => Expr::Atom(Atom::Symbol(f(&symbol))),
+    expr => expr,
+  }
+}
+
+/// Maps over the elements of `list`, keeping the span of each element.
+fn map_list<F>(list: List, f: &mut F) -> List
+where
+  F: FnMut(Expr) -> Expr,
+{
+  let nodes: Vec<(Expr, _)> = list
+    .iter()
+    .map(|node| (f(node.head.clone()), node.span))
+    .collect();
+
+  nodes
+    .into_iter()
+    .rev()
+    .fold(List::Nil, |list, (expr, span)| {
+      List::cons_spanned(expr, list, span)
+    })
+}
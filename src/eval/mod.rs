@@ -0,0 +1,1840 @@
+use std::cell::Cell;
+use std::cmp::Ordering;
+use std::convert::TryFrom;
+use std::error::Error;
+use std::rc::Rc;
+
+use thiserror::Error;
+
+use crate::ast::{
+  self, Arity, Atom, Expr, Function, List, Macro, Map, Native, Node, Operator,
+  Parameters, Span, Special, Symbol, SymbolTable, Truthiness,
+};
+use crate::convert::{FromArguments, FromExpr, IntoArguments, IntoExpr};
+use crate::diagnostic::Diagnostic;
+use crate::env::{gc, Frame};
+use crate::read::{self, ReadError};
+
+use self::bytecode::Closure;
+use self::expand::Expansions;
+use self::hygiene::Marks;
+use self::module::Modules;
+use self::number::Number;
+
+pub use crate::env::HeapStats;
+
+pub mod bytecode;
+mod compile;
+mod expand;
+mod hygiene;
+mod module;
+mod number;
+mod pattern;
+mod resolve;
+mod vm;
+
+pub fn eval(expr: Expr) -> Result<Expr, EvalError> {
+  let mut evalutor = Evaluator::new();
+  let result = evalutor.eval_expr(expr);
+  // The result outlives the evaluator, so the names it uses have to as well.
+  evalutor.symbols.keep();
+  result
+}
+
+/// How an `Evaluator` runs expressions.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum Backend {
+  /// Walks the expression tree directly.
+  #[default]
+  Tree,
+  /// Compiles expressions to bytecode and runs them on a virtual machine.
+  Bytecode,
+}
+
+pub struct Evaluator {
+  frame: Frame,
+  /// The frame holding the prelude, the standard library and the host's
+  /// definitions, which every module's frame is a child of.
+  root: Frame,
+  expansions: Expansions,
+  modules: Modules,
+  backend: Backend,
+  /// Whether redefining a symbol in the scope that already defines it is an
+  /// error.
+  strict: bool,
+  /// Which values conditionals treat as false, shared with the `not` native.
+  truthiness: Rc<Cell<Truthiness>>,
+  /// The names interned while the evaluator is running, which are freed along
+  /// with it.
+  symbols: SymbolTable,
+  /// The symbols naming conditional forms, which are only special where they
+  /// aren't bound.
+  conditionals: [(Symbol, Special); 5],
+}
+
+impl Default for Evaluator {
+  fn default() -> Evaluator {
+    Evaluator::new()
+  }
+}
+
+impl Evaluator {
+  pub fn new() -> Evaluator {
+    Evaluator::with_backend(Backend::default())
+  }
+
+  pub fn with_backend(backend: Backend) -> Evaluator {
+    let symbols = SymbolTable::new();
+    let entered = symbols.enter();
+    let truthiness = Rc::new(Cell::new(Truthiness::default()));
+    let root = Frame::base(truthiness.clone());
+    let mut evaluator = Evaluator {
+      frame: root.clone(),
+      root: root.clone(),
+      expansions: Expansions::new(),
+      modules: Modules::new(),
+      backend,
+      strict: false,
+      truthiness,
+      symbols,
+      conditionals: [
+        (Symbol::new("cond"), Special::Cond),
+        (Symbol::new("case"), Special::Case),
+        (Symbol::new("when"), Special::When),
+        (Symbol::new("unless"), Special::Unless),
+        (Symbol::new("match"), Special::Match),
+      ],
+    };
+
+    // Inject standard library.
+    let expr = read::read(include_str!("../lib.zuko")).unwrap();
+    evaluator.eval_expr(expr).unwrap();
+
+    evaluator.frame = Frame::with_parent(root);
+
+    drop(entered);
+    evaluator
+  }
+
+  /// Enables or disables strict mode. In strict mode, `define` fails with a
+  /// `Redefinition` error instead of overwriting a symbol which is already
+  /// defined in the same scope.
+  pub fn set_strict(&mut self, strict: bool) {
+    self.strict = strict;
+  }
+
+  /// Sets which values conditionals treat as false. By default, both `false`
+  /// and `()` are, so code written before there were booleans keeps working.
+  pub fn set_truthiness(&mut self, truthiness: Truthiness) {
+    self.truthiness.set(truthiness);
+  }
+
+  /// Returns whether conditionals treat `expr` as true.
+  fn is_truthy(&self, expr: &Expr) -> bool {
+    expr.is_truthy(self.truthiness.get())
+  }
+
+  /// Binds `value` to `name` in the root frame, where every module can see
+  /// it.
+  pub fn define<V>(&mut self, name: &str, value: V)
+  where
+    V: IntoExpr,
+  {
+    let _entered = self.symbols.enter();
+    self.root.set(Symbol::new(name), value.into_expr());
+  }
+
+  /// Returns the value bound to `name`, converted into a Rust value.
+  pub fn get<T>(&self, name: &str) -> Result<T, EvalError>
+  where
+    T: FromExpr,
+  {
+    let _entered = self.symbols.enter();
+    let symbol = Symbol::new(name);
+    match self.frame.get(&symbol) {
+      Some(expr) => T::from_expr(expr),
+      None => Err(EvalError::UndefinedSymbol(symbol)),
+    }
+  }
+
+  /// Registers a native function under `name`. Unlike the natives in the
+  /// prelude, `native` can be a closure capturing host state.
+  pub fn register_native<F>(&mut self, name: &str, native: F)
+  where
+    F: Fn(Vec<Expr>) -> Result<Expr, EvalError> + 'static,
+  {
+    self.define(name, Native::new(native));
+  }
+
+  /// Registers a native function under `name` whose arguments and return
+  /// value are converted from and into Rust values. The arguments are passed
+  /// as a tuple, like `|(a, b): (f64, f64)| Ok(a + b)`.
+  pub fn register_fn<A, R, F>(&mut self, name: &str, function: F)
+  where
+    A: FromArguments,
+    R: IntoExpr,
+    F: Fn(A) -> Result<R, EvalError> + 'static,
+  {
+    self.register_native(name, move |arguments| {
+      let arguments = A::from_arguments(arguments)?;
+      function(arguments).map(IntoExpr::into_expr)
+    });
+  }
+
+  /// Calls the function bound to `name` with `arguments`, converting the
+  /// result into a Rust value.
+  pub fn call<A, R>(&mut self, name: &str, arguments: A) -> Result<R, EvalError>
+  where
+    A: IntoArguments,
+    R: FromExpr,
+  {
+    let _entered = self.symbols.enter();
+    let callee = self.eval_symbol(Symbol::new(name))?;
+    let result = self.apply(callee, arguments.into_arguments())?;
+    R::from_expr(result)
+  }
+
+  /// Frees every frame and function which is only kept alive by a cycle,
+  /// returning how many objects were freed. Collections also run on their own
+  /// as frames are created.
+  pub fn collect_garbage(&mut self) -> usize {
+    gc::collect()
+  }
+
+  /// Returns how many frames are alive, and what the collections so far have
+  /// freed.
+  pub fn heap_stats(&self) -> HeapStats {
+    gc::stats()
+  }
+
+  /// Calls `callee` with already evaluated `arguments`.
+  pub fn apply(
+    &mut self,
+    callee: Expr,
+    arguments: Vec<Expr>,
+  ) -> Result<Expr, EvalError> {
+    use ast::Atom::*;
+
+    let _entered = self.symbols.enter();
+    match callee {
+      Expr::Atom(Function(function)) => {
+        let original_frame = self.frame.clone();
+        let result = self
+          .enter_function(&function, arguments)
+          .and_then(|_| self.eval_expr(function.body().clone()));
+        self.frame = original_frame;
+        result
+      }
+      Expr::Atom(Closure(closure)) => self.run_closure(closure, arguments),
+      Expr::Atom(Native(native)) => native.call(arguments),
+      Expr::Atom(Special(ast::Special::Operator(operator))) => {
+        self.apply_operator(operator, arguments)
+      }
+      // The expansion is evaluated rather than the call itself, since the
+      // bytecode compiler can only expand macros bound to global symbols.
+      Expr::Atom(Macro(macr)) => {
+        let expr = self.expand_macro(&macr, quote_all(arguments))?;
+        self.eval_expr(expr)
+      }
+      callee => {
+        self.eval_expr(Expr::List(List::cons(callee, quote_all(arguments))))
+      }
+    }
+  }
+
+  /// Reads `source` with the names in it belonging to the evaluator, so that
+  /// they are freed along with it.
+  pub fn read(&self, source: &str) -> Result<Expr, ReadError> {
+    let _entered = self.symbols.enter();
+    read::read(source)
+  }
+
+  pub fn eval_expr(&mut self, expr: Expr) -> Result<Expr, EvalError> {
+    let _entered = self.symbols.enter();
+    if self.backend == Backend::Bytecode {
+      return self.run(expr);
+    }
+
+    // Tail calls replace the current frame, so it has to be restored once the
+    // expression has been fully evaluated (or has failed).
+    let original_frame = self.frame.clone();
+    let result = self.eval_tail(expr, None);
+    self.frame = original_frame;
+    result
+  }
+
+  /// Evaluates the expression in `node`, locating any error at it.
+  fn eval_node(&mut self, node: &Node) -> Result<Expr, EvalError> {
+    self
+      .eval_expr(node.head.clone())
+      .map_err(|error| error.with_span(node.span))
+  }
+
+  /// Evaluates `expr`, which was read from `span`, looping over expressions in
+  /// tail position instead of recursing so that tail calls run in constant
+  /// stack space.
+  fn eval_tail(
+    &mut self,
+    mut expr: Expr,
+    mut span: Option<Span>,
+  ) -> Result<Expr, EvalError> {
+    use Expr::*;
+
+    loop {
+      let step = match expr {
+        List(list) => self.eval_list(list),
+        Atom(atom) => self.eval_atom(atom).map(Step::Return),
+      };
+
+      match step.map_err(|error| error.with_span(span))? {
+        Step::Return(result) => return Ok(result),
+        Step::Continue(next, next_span) => {
+          expr = next;
+          span = next_span.or(span);
+        }
+      }
+    }
+  }
+
+  pub fn eval_list(&mut self, list: List) -> Result<Step, EvalError> {
+    use List::*;
+
+    match &list {
+      Cons(node) => self.eval_call(node),
+      Nil => Ok(Step::Return(Expr::List(Nil))),
+    }
+  }
+
+  pub fn eval_call(&mut self, call: &Rc<Node>) -> Result<Step, EvalError> {
+    use Atom::*;
+    use EvalError::NotCallable;
+
+    if let Expr::Atom(Symbol(symbol)) = &call.head {
+      if let Some(special) = self.as_conditional(symbol) {
+        return self.eval_call_special(special, call.tail.clone());
+      }
+    }
+
+    let head = self.eval_expr(call.head.clone())?;
+    let tail = call.tail.clone();
+
+    match head {
+      Expr::Atom(Function(function)) => self.eval_call_function(function, tail),
+      Expr::Atom(Closure(closure)) => {
+        Ok(Step::Return(self.eval_call_closure(closure, tail)?))
+      }
+      Expr::Atom(Macro(macr)) => self.eval_call_macro(macr, call),
+      Expr::Atom(Native(native)) => {
+        Ok(Step::Return(self.eval_call_native(native, tail)?))
+      }
+      Expr::Atom(Special(special)) => self.eval_call_special(special, tail),
+      _ => Err(NotCallable),
+    }
+  }
+
+  pub fn eval_call_function(
+    &mut self,
+    function: Function,
+    tail: List,
+  ) -> Result<Step, EvalError> {
+    let arguments = self.eval_arguments(tail)?;
+
+    self.enter_function(&function, arguments)?;
+
+    Ok(Step::Continue(function.body().clone(), function.span()))
+  }
+
+  pub fn eval_call_closure(
+    &mut self,
+    closure: Closure,
+    tail: List,
+  ) -> Result<Expr, EvalError> {
+    let arguments = self.eval_arguments(tail)?;
+
+    self.run_closure(closure, arguments)
+  }
+
+  /// Replaces the current frame with a new frame for a call to `function`,
+  /// binding its parameters to the evaluated `arguments`.
+  fn enter_function(
+    &mut self,
+    function: &Function,
+    arguments: Vec<Expr>,
+  ) -> Result<(), EvalError> {
+    let parameters = function.parameters();
+    let arguments = parameters.bind(arguments)?;
+
+    self.frame =
+      Frame::with_locals(function.frame().clone(), function.locals().clone());
+
+    // Defaults are evaluated in the function's frame, so they can refer to
+    // the parameters before them.
+    for ((name, default), argument) in parameters.iter().zip(arguments) {
+      let argument = match (argument, default) {
+        (Some(argument), _) => argument,
+        (None, Some(default)) => self.eval_expr(default.clone())?,
+        (None, None) => unreachable!(),
+      };
+      self.frame.set(*name, argument);
+    }
+
+    Ok(())
+  }
+
+  pub fn eval_call_macro(
+    &mut self,
+    macr: Macro,
+    call: &Rc<Node>,
+  ) -> Result<Step, EvalError> {
+    let expr = self.expand_call_site(call, &macr)?;
+    Ok(Step::Continue(expr, None))
+  }
+
+  /// Expands a call to `macr` without evaluating the result.
+  ///
+  /// Unhygienic macros are expanded in a child of the current frame. Hygienic
+  /// macros are expanded in a child of the frame they were created in, and the
+  /// bindings they introduce are renamed so they can't capture the caller's
+  /// symbols. Any other symbols they introduce are left alone, so they refer
+  /// to whatever they are bound to where the macro is called.
+  pub fn expand_macro(
+    &mut self,
+    macr: &Macro,
+    tail: List,
+  ) -> Result<Expr, EvalError> {
+    use Expr::*;
+
+    let mut marks = Marks::new();
+
+    // The terms might have been resolved as part of a function body.
+    let tail = resolve::unresolve(&tail);
+
+    let original_frame = self.frame.clone();
+    let argument = match macr.frame() {
+      Some(frame) => {
+        self.frame = Frame::with_parent(frame.clone());
+        marks.mark(List(tail))
+      }
+      None => {
+        self.frame = Frame::with_parent(original_frame.clone());
+        List(tail)
+      }
+    };
+
+    self.frame.set(*macr.parameter(), argument);
+
+    let result = self.eval_expr(macr.body().clone());
+
+    self.frame = original_frame;
+
+    if macr.is_hygienic() {
+      let conditionals: Vec<(Symbol, Special)> = self
+        .conditionals
+        .iter()
+        .filter(|(symbol, _)| self.as_conditional(symbol).is_some())
+        .cloned()
+        .collect();
+      Ok(hygiene::rename(result?, &marks, &conditionals))
+    } else {
+      result
+    }
+  }
+
+  /// Returns the conditional form named by `symbol`, unless it is bound.
+  ///
+  /// Unlike other special forms, the reader doesn't reserve the names of
+  /// `cond`, `case`, `when`, `unless` and `match`, since they were added
+  /// after code had already used them as names. They are only recognised in
+  /// calls whose head is one of these symbols while it is unbound, so that a
+  /// definition with the same name still takes precedence.
+  fn as_conditional(&self, symbol: &Symbol) -> Option<Special> {
+    let (_, special) = self
+      .conditionals
+      .iter()
+      .find(|(conditional, _)| conditional == symbol)?;
+    match self.frame.get(symbol) {
+      Some(_) => None,
+      None => Some(special.clone()),
+    }
+  }
+
+  pub fn eval_call_special(
+    &mut self,
+    special: Special,
+    tail: List,
+  ) -> Result<Step, EvalError> {
+    use Special::*;
+    use Step::*;
+
+    match special {
+      Begin => self.eval_call_special_begin(tail),
+      Define => Ok(Return(self.eval_call_special_define(tail)?)),
+      Function => Ok(Return(self.eval_call_special_function(tail)?)),
+      Macro => Ok(Return(self.eval_call_special_macro(tail)?)),
+      Syntax => Ok(Return(self.eval_call_special_syntax(tail)?)),
+      If => self.eval_call_special_if(tail),
+      Quote => Ok(Return(self.eval_call_special_quote(tail)?)),
+      Quasiquote => Ok(Return(self.eval_call_special_quasiquote(tail)?)),
+      Unquote | UnquoteSplicing => Err(EvalError::OutsideQuasiquote(special)),
+      MacroExpand => Ok(Return(self.eval_call_special_macroexpand(tail)?)),
+      MacroExpandOnce => {
+        Ok(Return(self.eval_call_special_macroexpand_1(tail)?))
+      }
+      Load => Ok(Return(self.eval_call_special_load(tail)?)),
+      Import => Ok(Return(self.eval_call_special_import(tail)?)),
+      Export => Ok(Return(self.eval_call_special_export(tail)?)),
+      Try => Ok(Return(self.eval_call_special_try(tail)?)),
+      Let => self.eval_call_special_let(tail),
+      LetStar => self.eval_call_special_let_star(tail),
+      Letrec => self.eval_call_special_letrec(tail),
+      Set => Ok(Return(self.eval_call_special_set(tail)?)),
+      And => self.eval_call_special_and(tail),
+      Or => self.eval_call_special_or(tail),
+      Cond => self.eval_call_special_cond(tail),
+      Case => self.eval_call_special_case(tail),
+      When => self.eval_call_special_when(tail, true),
+      Unless => self.eval_call_special_when(tail, false),
+      Match => self.eval_call_special_match(tail),
+      Operator(operator) => {
+        Ok(Return(self.eval_call_special_operator(operator, tail)?))
+      }
+    }
+  }
+
+  pub fn eval_call_native(
+    &mut self,
+    native: Native,
+    tail: List,
+  ) -> Result<Expr, EvalError> {
+    let arguments = self.eval_arguments(tail)?;
+
+    native.call(arguments)
+  }
+
+  /// Evaluates each expression in `tail`, locating any error at the
+  /// expression that caused it.
+  pub fn eval_arguments(&mut self, tail: List) -> Result<Vec<Expr>, EvalError> {
+    tail.iter().map(|node| self.eval_node(node)).collect()
+  }
+
+  pub fn eval_call_special_begin(
+    &mut self,
+    tail: List,
+  ) -> Result<Step, EvalError> {
+    use EvalError::*;
+
+    let mut nodes = tail.iter().peekable();
+
+    while let Some(node) = nodes.next() {
+      if nodes.peek().is_none() {
+        return Ok(Step::Continue(node.head.clone(), node.span));
+      }
+
+      self.eval_node(node)?;
+    }
+
+    Err(WrongArity)
+  }
+
+  pub fn eval_call_special_define(
+    &mut self,
+    tail: List,
+  ) -> Result<Expr, EvalError> {
+    use EvalError::*;
+
+    let (target, body) = match &tail {
+      List::Cons(node) => (node.head.clone(), node.tail.clone()),
+      List::Nil => return Err(WrongArity),
+    };
+    let (target, expr) = match target {
+      // `(define (name parameters) body...)` defines a function.
+      Expr::List(List::Cons(signature)) => {
+        let parameters = self.as_parameters(signature.tail.clone())?;
+        let span = body.iter().next().and_then(|node| node.span);
+        let function =
+          self.create_function(parameters, as_body(body)?, span)?;
+        (signature.head.clone(), Expr::Atom(Atom::Function(function)))
+      }
+      target => match body.iter().collect::<Vec<_>>()[..] {
+        [value] => (target, self.eval_node(value)?),
+        _ => return Err(WrongArity),
+      },
+    };
+
+    match target {
+      Expr::Atom(Atom::Address(address)) => {
+        self.check_redefinition(address.symbol)?;
+        self.frame.set_at(address.depth, address.slot, expr.clone())
+      }
+      target => {
+        let symbol = self.as_symbol(target)?;
+        self.check_redefinition(symbol)?;
+        self.frame.set(symbol, expr.clone());
+      }
+    }
+
+    Ok(expr)
+  }
+
+  /// In strict mode, fails if `symbol` is already defined in the current
+  /// frame. Definitions in enclosing frames can still be shadowed.
+  fn check_redefinition(&self, symbol: Symbol) -> Result<(), EvalError> {
+    if self.strict && self.frame.contains(&symbol) {
+      return Err(EvalError::Redefinition(symbol));
+    }
+    Ok(())
+  }
+
+  /// Evaluates `(set! name value)`, which rebinds `name` in the nearest frame
+  /// that defines it. Unlike `define`, it never creates a new binding.
+  pub fn eval_call_special_set(
+    &mut self,
+    tail: List,
+  ) -> Result<Expr, EvalError> {
+    use EvalError::*;
+
+    if tail.len() != 2 {
+      return Err(WrongArity);
+    }
+
+    let target = tail.get(0).unwrap().clone();
+    let expr = self.eval_node(tail.iter().nth(1).unwrap())?;
+
+    let (symbol, is_assigned) = match target {
+      Expr::Atom(Atom::Address(address)) => {
+        let is_assigned = self.frame.assign_at(
+          address.depth,
+          address.slot,
+          address.symbol,
+          expr.clone(),
+        );
+        (address.symbol, is_assigned)
+      }
+      target => {
+        let symbol = self.as_symbol(target)?;
+        (symbol, self.frame.assign(symbol, expr.clone()))
+      }
+    };
+
+    if !is_assigned {
+      return Err(UndefinedSymbol(symbol));
+    }
+
+    Ok(expr)
+  }
+
+  pub fn eval_call_special_function(
+    &mut self,
+    tail: List,
+  ) -> Result<Expr, EvalError> {
+    use EvalError::*;
+
+    if tail.len() != 2 {
+      return Err(WrongArity);
+    }
+
+    let parameters = self.as_list(tail.get(0).unwrap().clone())?;
+    let body = tail.iter().nth(1).unwrap();
+
+    let parameters = self.as_parameters(parameters)?;
+    let function =
+      self.create_function(parameters, body.head.clone(), body.span)?;
+
+    Ok(Expr::Atom(Atom::Function(function)))
+  }
+
+  /// Creates a function capturing the current frame, whose body was read from
+  /// `span`.
+  fn create_function(
+    &mut self,
+    mut parameters: Parameters,
+    body: Expr,
+    span: Option<Span>,
+  ) -> Result<Function, EvalError> {
+    // Expand macros in the body up front, rather than every time the function
+    // is called, and then resolve its locals to slots.
+    let names = parameters.iter().map(|(name, _)| *name).collect();
+    let body = self.expand(body, names)?;
+    let (body, locals) = resolve::resolve_function(&mut parameters, body);
+
+    let frame = self.frame.clone();
+
+    Ok(Function::new(frame, parameters, locals, body, span))
+  }
+
+  pub fn eval_call_special_macro(
+    &mut self,
+    tail: List,
+  ) -> Result<Expr, EvalError> {
+    let (parameter, body) = self.as_macro_parts(tail)?;
+
+    Ok(Expr::Atom(Atom::Macro(Macro::new(parameter, body))))
+  }
+
+  pub fn eval_call_special_syntax(
+    &mut self,
+    tail: List,
+  ) -> Result<Expr, EvalError> {
+    let (parameter, body) = self.as_macro_parts(tail)?;
+
+    let frame = self.frame.clone();
+
+    Ok(Expr::Atom(Atom::Macro(Macro::hygienic(
+      frame, parameter, body,
+    ))))
+  }
+
+  fn as_macro_parts(
+    &mut self,
+    tail: List,
+  ) -> Result<(Symbol, Expr), EvalError> {
+    use EvalError::*;
+
+    if tail.len() != 2 {
+      return Err(WrongArity);
+    }
+
+    let parameters = self.as_list(tail.get(0).unwrap().clone())?;
+    let body = tail.get(1).unwrap().clone();
+
+    if parameters.len() != 1 {
+      return Err(WrongArity);
+    }
+
+    let parameter = self.as_symbol(parameters.get(0).unwrap().clone())?;
+
+    Ok((parameter, body))
+  }
+
+  pub fn eval_call_special_if(
+    &mut self,
+    tail: List,
+  ) -> Result<Step, EvalError> {
+    use EvalError::*;
+
+    if tail.len() != 2 && tail.len() != 3 {
+      return Err(WrongArity);
+    }
+
+    let mut nodes = tail.iter();
+    let condition = self.eval_node(nodes.next().unwrap())?;
+    let consequent = nodes.next().unwrap();
+
+    if self.is_truthy(&condition) {
+      Ok(Step::Continue(consequent.head.clone(), consequent.span))
+    } else {
+      // Without an alternative, `if` evaluates to `()`.
+      match nodes.next() {
+        Some(alternative) => {
+          Ok(Step::Continue(alternative.head.clone(), alternative.span))
+        }
+        None => Ok(Step::Return(Expr::List(List::Nil))),
+      }
+    }
+  }
+
+  /// Evaluates the expressions in order, stopping at the first falsy one.
+  /// Returns the value it stopped at, or `true` if there are none.
+  pub fn eval_call_special_and(
+    &mut self,
+    tail: List,
+  ) -> Result<Step, EvalError> {
+    self.eval_short_circuit(tail, false, Expr::Atom(Atom::Boolean(true)))
+  }
+
+  /// Evaluates the expressions in order, stopping at the first truthy one.
+  /// Returns the value it stopped at, or `()` if there are none.
+  pub fn eval_call_special_or(
+    &mut self,
+    tail: List,
+  ) -> Result<Step, EvalError> {
+    self.eval_short_circuit(tail, true, Expr::List(List::Nil))
+  }
+
+  /// Evaluates the expressions until one has the truthiness `stop_at`, and
+  /// returns it. The last expression is evaluated in tail position.
+  fn eval_short_circuit(
+    &mut self,
+    tail: List,
+    stop_at: bool,
+    empty: Expr,
+  ) -> Result<Step, EvalError> {
+    let mut nodes = tail.iter().peekable();
+
+    while let Some(node) = nodes.next() {
+      if nodes.peek().is_none() {
+        return Ok(Step::Continue(node.head.clone(), node.span));
+      }
+
+      let value = self.eval_node(node)?;
+      if self.is_truthy(&value) == stop_at {
+        return Ok(Step::Return(value));
+      }
+    }
+
+    Ok(Step::Return(empty))
+  }
+
+  /// Evaluates `(cond (test body...)... (else body...))`, which evaluates the
+  /// body of the first clause whose test is truthy. A clause without a body
+  /// returns the value of its test, and if no clause matches, `cond`
+  /// evaluates to `()`.
+  pub fn eval_call_special_cond(
+    &mut self,
+    tail: List,
+  ) -> Result<Step, EvalError> {
+    for node in tail.iter() {
+      let (test, body) = self.as_clause(node.head.clone())?;
+
+      if is_else(&test) {
+        return self.eval_call_special_begin(body);
+      }
+
+      let value = self
+        .eval_expr(test)
+        .map_err(|error| error.with_span(node.span))?;
+      if self.is_truthy(&value) {
+        if body.is_empty() {
+          return Ok(Step::Return(value));
+        }
+        return self.eval_call_special_begin(body);
+      }
+    }
+
+    Ok(Step::Return(Expr::List(List::Nil)))
+  }
+
+  /// Evaluates `(case key ((datum...) body...)... (else body...))`, which
+  /// evaluates the body of the first clause listing a datum equal to the key.
+  /// The data aren't evaluated, and are compared with the key like `=` does,
+  /// so lists match if their elements do.
+  pub fn eval_call_special_case(
+    &mut self,
+    tail: List,
+  ) -> Result<Step, EvalError> {
+    use EvalError::*;
+
+    let mut nodes = tail.iter();
+    let key = self.eval_node(nodes.next().ok_or(WrongArity)?)?;
+
+    for node in nodes {
+      let (data, body) = self.as_clause(node.head.clone())?;
+
+      let is_match = is_else(&data)
+        || self
+          .as_list(data)?
+          .iter()
+          .any(|node| is_equal(&key, &node.head));
+      if is_match {
+        return self.eval_call_special_begin(body);
+      }
+    }
+
+    Ok(Step::Return(Expr::List(List::Nil)))
+  }
+
+  /// Evaluates `when`, or `unless` if `expected` is false, which evaluates
+  /// its body if the condition's truthiness is `expected`, and evaluates to
+  /// `()` otherwise.
+  pub fn eval_call_special_when(
+    &mut self,
+    tail: List,
+    expected: bool,
+  ) -> Result<Step, EvalError> {
+    let (condition, body) = match &tail {
+      List::Cons(node) if !node.tail.is_empty() => (node, node.tail.clone()),
+      _ => return Err(EvalError::WrongArity),
+    };
+
+    let condition = self.eval_node(condition)?;
+    if self.is_truthy(&condition) == expected {
+      self.eval_call_special_begin(body)
+    } else {
+      Ok(Step::Return(Expr::List(List::Nil)))
+    }
+  }
+
+  /// Evaluates `(match value (pattern body...)...)`, which evaluates the body
+  /// of the first clause whose pattern matches the value, in a frame binding
+  /// the symbols in the pattern. A clause can also have a guard, as in
+  /// `(pattern :when guard body...)`, which has to be truthy for it to match.
+  pub fn eval_call_special_match(
+    &mut self,
+    tail: List,
+  ) -> Result<Step, EvalError> {
+    use EvalError::*;
+
+    let mut nodes = tail.iter();
+    let value = self.eval_node(nodes.next().ok_or(WrongArity)?)?;
+
+    for node in nodes {
+      let (pattern, guard, body) = self.as_match_clause(node.head.clone())?;
+
+      let mut bindings = Vec::new();
+      if !pattern::matches(&pattern, &value, &mut bindings)? {
+        continue;
+      }
+
+      let names = bindings.iter().map(|(name, _)| *name).collect();
+      let mut exprs: Vec<&Expr> = guard.iter().collect();
+      exprs.push(&body);
+      let locals = resolve::frame_locals(names, &exprs);
+
+      let original_frame = self.frame.clone();
+      self.frame = Frame::with_locals(original_frame.clone(), locals);
+      for (name, value) in bindings {
+        self.frame.set(name, value);
+      }
+
+      if let Some(guard) = guard {
+        let guard = self
+          .eval_expr(guard)
+          .map_err(|error| error.with_span(node.span))?;
+        if !self.is_truthy(&guard) {
+          self.frame = original_frame;
+          continue;
+        }
+      }
+
+      return Ok(Step::Continue(body, node.span));
+    }
+
+    Err(NoMatch(value))
+  }
+
+  /// Parses a clause of a `match` like `(pattern :when guard body...)`, where
+  /// the guard can be left out, into its pattern, guard and body. The body is
+  /// wrapped in a `begin`.
+  fn as_match_clause(
+    &mut self,
+    expr: Expr,
+  ) -> Result<(Expr, Option<Expr>, Expr), EvalError> {
+    use EvalError::*;
+
+    let (pattern, mut body) = self.as_clause(expr)?;
+
+    let mut guard = None;
+    if let List::Cons(node) = &body {
+      if is_guard(&node.head) {
+        match &node.tail {
+          List::Cons(rest) => {
+            guard = Some(rest.head.clone());
+            body = rest.tail.clone();
+          }
+          List::Nil => return Err(WrongArity),
+        }
+      }
+    }
+
+    if body.is_empty() {
+      return Err(WrongArity);
+    }
+    let body = List::cons(Expr::Atom(Atom::Special(Special::Begin)), body);
+
+    Ok((pattern, guard, Expr::List(body)))
+  }
+
+  /// Splits a clause of a `cond` or `case` into its head and its body.
+  fn as_clause(&mut self, expr: Expr) -> Result<(Expr, List), EvalError> {
+    use EvalError::*;
+
+    match self.as_list(expr)? {
+      List::Cons(node) => Ok((node.head.clone(), node.tail.clone())),
+      List::Nil => Err(WrongArity),
+    }
+  }
+
+  pub fn eval_call_special_quote(
+    &mut self,
+    tail: List,
+  ) -> Result<Expr, EvalError> {
+    use EvalError::*;
+
+    if tail.len() != 1 {
+      return Err(WrongArity);
+    }
+
+    let expr = tail.get(0).unwrap().clone();
+
+    Ok(expr)
+  }
+
+  pub fn eval_call_special_quasiquote(
+    &mut self,
+    tail: List,
+  ) -> Result<Expr, EvalError> {
+    use EvalError::*;
+
+    if tail.len() != 1 {
+      return Err(WrongArity);
+    }
+
+    self.quasiquote(tail.get(0).unwrap().clone(), 1)
+  }
+
+  /// Quotes `expr`, evaluating the expressions unquoted at `depth`. Nested
+  /// quasiquotes increase the depth and unquotes decrease it, so only the
+  /// unquotes belonging to the outermost quasiquote are evaluated.
+  fn quasiquote(
+    &mut self,
+    expr: Expr,
+    depth: usize,
+  ) -> Result<Expr, EvalError> {
+    use ast::Special::*;
+
+    let list = match expr {
+      Expr::List(list) => list,
+      atom => return Ok(atom),
+    };
+
+    let special = match list.get(0) {
+      Some(Expr::Atom(Atom::Special(special))) if list.len() == 2 => {
+        Some(special.clone())
+      }
+      _ => None,
+    };
+
+    match special {
+      Some(Unquote) if depth == 1 => {
+        return self.eval_expr(list.get(1).unwrap().clone());
+      }
+      Some(Unquote) | Some(UnquoteSplicing) => {
+        return self.quasiquote_elements(list, depth - 1);
+      }
+      Some(Quasiquote) => return self.quasiquote_elements(list, depth + 1),
+      _ => {}
+    }
+
+    self.quasiquote_elements(list, depth)
+  }
+
+  /// Quasiquotes the elements of `list` at `depth`, splicing in the elements
+  /// of any lists unquoted with `unquote-splicing`.
+  fn quasiquote_elements(
+    &mut self,
+    list: List,
+    depth: usize,
+  ) -> Result<Expr, EvalError> {
+    use EvalError::*;
+
+    let mut elements = Vec::new();
+
+    for node in list.iter() {
+      let splice = match &node.head {
+        Expr::List(element) if depth == 1 => match element.get(0) {
+          Some(Expr::Atom(Atom::Special(Special::UnquoteSplicing))) => {
+            if element.len() != 2 {
+              return Err(WrongArity);
+            }
+            Some(element.get(1).unwrap().clone())
+          }
+          _ => None,
+        },
+        _ => None,
+      };
+
+      match splice {
+        Some(expr) => {
+          let spliced = self
+            .eval_expr(expr)
+            .map_err(|error| error.with_span(node.span))?;
+          let spliced = self.as_list(spliced)?;
+          elements.extend(spliced.into_iter().map(|expr| (expr, None)));
+        }
+        None => {
+          let element = self.quasiquote(node.head.clone(), depth)?;
+          elements.push((element, node.span));
+        }
+      }
+    }
+
+    Ok(Expr::List(
+      elements
+        .into_iter()
+        .rev()
+        .fold(List::Nil, |list, (expr, span)| {
+          List::cons_spanned(expr, list, span)
+        }),
+    ))
+  }
+
+  pub fn eval_call_special_macroexpand(
+    &mut self,
+    tail: List,
+  ) -> Result<Expr, EvalError> {
+    use EvalError::*;
+
+    if tail.len() != 1 {
+      return Err(WrongArity);
+    }
+
+    let expr = self.eval_expr(tail.get(0).unwrap().clone())?;
+
+    self.macroexpand(expr)
+  }
+
+  pub fn eval_call_special_macroexpand_1(
+    &mut self,
+    tail: List,
+  ) -> Result<Expr, EvalError> {
+    use EvalError::*;
+
+    if tail.len() != 1 {
+      return Err(WrongArity);
+    }
+
+    let expr = self.eval_expr(tail.get(0).unwrap().clone())?;
+
+    self.macroexpand_1(expr)
+  }
+
+  pub fn eval_call_special_load(
+    &mut self,
+    tail: List,
+  ) -> Result<Expr, EvalError> {
+    use EvalError::*;
+
+    if tail.len() != 1 {
+      return Err(WrongArity);
+    }
+
+    let path = match self.eval_expr(tail.get(0).unwrap().clone())? {
+      Expr::Atom(Atom::String(path)) => path,
+      _ => return Err(InvalidType),
+    };
+
+    self.load(&path)
+  }
+
+  pub fn eval_call_special_import(
+    &mut self,
+    tail: List,
+  ) -> Result<Expr, EvalError> {
+    let (path, alias) = self.as_import(tail)?;
+
+    let mut frame = self.frame.clone();
+    self.import(&path, &alias, &mut frame)?;
+
+    Ok(Expr::List(List::Nil))
+  }
+
+  pub fn eval_call_special_export(
+    &mut self,
+    tail: List,
+  ) -> Result<Expr, EvalError> {
+    let names = self.as_export(tail)?;
+
+    self.export(names);
+
+    Ok(Expr::List(List::Nil))
+  }
+
+  /// Evaluates the body of a `try`, passing any error it raises to the
+  /// `catch` clause, and then evaluates the `finally` clause regardless.
+  pub fn eval_call_special_try(
+    &mut self,
+    tail: List,
+  ) -> Result<Expr, EvalError> {
+    let (body, catch, finally) = self.as_try_parts(tail)?;
+
+    let result = match (self.eval_expr(body), catch) {
+      (Err(error), Some((name, handler))) => {
+        let original_frame = self.frame.clone();
+        let locals = resolve::frame_locals(vec![name], &[&handler]);
+        self.frame = Frame::with_locals(original_frame.clone(), locals);
+        self.frame.set(name, error.into_value());
+        let result = self.eval_expr(handler);
+        self.frame = original_frame;
+        result
+      }
+      (result, _) => result,
+    };
+
+    if let Some(finally) = finally {
+      self.eval_expr(finally)?;
+    }
+
+    result
+  }
+
+  /// Parses a `try` like `(try body (catch error handler) (finally cleanup))`,
+  /// where either clause can be left out, but not both.
+  #[allow(clippy::type_complexity)]
+  fn as_try_parts(
+    &mut self,
+    tail: List,
+  ) -> Result<(Expr, Option<(Symbol, Expr)>, Option<Expr>), EvalError> {
+    use EvalError::*;
+
+    let mut exprs = tail.into_iter();
+    let body = exprs.next().ok_or(WrongArity)?;
+
+    let mut catch = None;
+    let mut finally = None;
+
+    for expr in exprs {
+      let clause = self.as_list(expr)?;
+      let name = match clause.get(0) {
+        Some(Expr::Atom(Atom::Symbol(name))) => name.to_string(),
+        _ => return Err(InvalidType),
+      };
+
+      match name.as_str() {
+        "catch" if catch.is_none() && finally.is_none() => {
+          if clause.len() != 3 {
+            return Err(WrongArity);
+          }
+          let symbol = self.as_symbol(clause.get(1).unwrap().clone())?;
+          catch = Some((symbol, clause.get(2).unwrap().clone()));
+        }
+        "finally" if finally.is_none() => {
+          if clause.len() != 2 {
+            return Err(WrongArity);
+          }
+          finally = Some(clause.get(1).unwrap().clone());
+        }
+        _ => return Err(InvalidType),
+      }
+    }
+
+    if catch.is_none() && finally.is_none() {
+      return Err(WrongArity);
+    }
+
+    Ok((body, catch, finally))
+  }
+
+  /// Evaluates the values of a `let` in the current frame, and then its body
+  /// in a child frame binding them. A named `let` like
+  /// `(let loop ((i 0)) body)` binds `loop` to a function taking the
+  /// bindings as parameters, and calls it with their values.
+  pub fn eval_call_special_let(
+    &mut self,
+    tail: List,
+  ) -> Result<Step, EvalError> {
+    let name = match tail.get(0) {
+      Some(Expr::Atom(Atom::Symbol(name))) => Some(*name),
+      _ => None,
+    };
+    let tail = match (name, &tail) {
+      (Some(_), List::Cons(node)) => node.tail.clone(),
+      _ => tail,
+    };
+
+    let (bindings, body, span) = self.as_let_parts(tail)?;
+    let names: Vec<Symbol> = bindings.iter().map(|(name, _)| *name).collect();
+    let values = bindings
+      .into_iter()
+      .map(|(_, expr)| self.eval_expr(expr))
+      .collect::<Result<Vec<_>, _>>()?;
+
+    match name {
+      Some(name) => {
+        self.frame = Frame::with_locals(self.frame.clone(), Rc::from([name]));
+
+        let parameters = Parameters {
+          required: names,
+          ..Parameters::default()
+        };
+        let function = self.create_function(parameters, body, span)?;
+        self
+          .frame
+          .set(name, Expr::Atom(Atom::Function(function.clone())));
+
+        self.enter_function(&function, values)?;
+        Ok(Step::Continue(function.body().clone(), span))
+      }
+      None => {
+        let locals = resolve::frame_locals(names.clone(), &[&body]);
+        self.frame = Frame::with_locals(self.frame.clone(), locals);
+        for (name, value) in names.into_iter().zip(values) {
+          self.frame.set(name, value);
+        }
+        Ok(Step::Continue(body, span))
+      }
+    }
+  }
+
+  /// Evaluates each value of a `let*` in a child frame binding the ones
+  /// before it, and then its body in a child frame binding all of them.
+  pub fn eval_call_special_let_star(
+    &mut self,
+    tail: List,
+  ) -> Result<Step, EvalError> {
+    let (bindings, body, span) = self.as_let_parts(tail)?;
+
+    if bindings.is_empty() {
+      let locals = resolve::frame_locals(Vec::new(), &[&body]);
+      self.frame = Frame::with_locals(self.frame.clone(), locals);
+    }
+
+    let mut bindings = bindings.into_iter().peekable();
+    while let Some((name, expr)) = bindings.next() {
+      let value = self.eval_expr(expr)?;
+
+      // The next value is evaluated in this frame, as is the body after the
+      // last one.
+      let next = match bindings.peek() {
+        Some((_, next)) => next,
+        None => &body,
+      };
+      let locals = resolve::frame_locals(vec![name], &[next]);
+      self.frame = Frame::with_locals(self.frame.clone(), locals);
+      self.frame.set(name, value);
+    }
+
+    Ok(Step::Continue(body, span))
+  }
+
+  /// Evaluates the values of a `letrec` in a child frame binding all of them,
+  /// so that functions among them can refer to each other.
+  pub fn eval_call_special_letrec(
+    &mut self,
+    tail: List,
+  ) -> Result<Step, EvalError> {
+    let (bindings, body, span) = self.as_let_parts(tail)?;
+
+    let names: Vec<Symbol> = bindings.iter().map(|(name, _)| *name).collect();
+    let mut exprs: Vec<&Expr> = bindings.iter().map(|(_, expr)| expr).collect();
+    exprs.push(&body);
+    let locals = resolve::frame_locals(names, &exprs);
+    self.frame = Frame::with_locals(self.frame.clone(), locals);
+
+    for (name, expr) in bindings {
+      let value = self.eval_expr(expr)?;
+      self.frame.set(name, value);
+    }
+
+    Ok(Step::Continue(body, span))
+  }
+
+  /// Parses the bindings and body of a `let` like `((a 1) (b 2)) body...`,
+  /// wrapping a body of several expressions in a `begin`. Also returns where
+  /// the body was read from, which is where its first expression was.
+  #[allow(clippy::type_complexity)]
+  fn as_let_parts(
+    &mut self,
+    tail: List,
+  ) -> Result<(Vec<(Symbol, Expr)>, Expr, Option<Span>), EvalError> {
+    use EvalError::*;
+
+    let (bindings, body, span) = match &tail {
+      List::Cons(node) => (
+        node.head.clone(),
+        as_body(node.tail.clone())?,
+        node.tail.iter().next().and_then(|node| node.span),
+      ),
+      List::Nil => return Err(WrongArity),
+    };
+
+    let bindings = self
+      .as_list(bindings)?
+      .into_iter()
+      .map(|binding| {
+        let binding = self.as_list(binding)?;
+        if binding.len() != 2 {
+          return Err(WrongArity);
+        }
+        let name = self.as_symbol(binding.get(0).unwrap().clone())?;
+        Ok((name, binding.get(1).unwrap().clone()))
+      })
+      .collect::<Result<_, _>>()?;
+
+    Ok((bindings, body, span))
+  }
+
+  pub fn eval_call_special_operator(
+    &mut self,
+    operator: Operator,
+    tail: List,
+  ) -> Result<Expr, EvalError> {
+    let arguments = self.eval_arguments(tail)?;
+
+    self.apply_operator(operator, arguments)
+  }
+
+  /// Applies `operator` to already evaluated `arguments`.
+  pub fn apply_operator(
+    &mut self,
+    operator: Operator,
+    arguments: Vec<Expr>,
+  ) -> Result<Expr, EvalError> {
+    use ast::Atom::*;
+    use EvalError::*;
+    use Expr::*;
+    use Operator::*;
+
+    let result = match operator {
+      Add => self
+        .as_numbers(arguments)?
+        .into_iter()
+        .try_fold(Number::Integer(0), Number::add)?
+        .into_expr(),
+      Sub => {
+        let numbers = self.as_numbers(arguments)?;
+        match numbers.split_first() {
+          Some((first, [])) => first.neg()?.into_expr(),
+          Some((first, rest)) => rest
+            .iter()
+            .try_fold(*first, |left, right| left.sub(*right))?
+            .into_expr(),
+          None => return Err(WrongArity),
+        }
+      }
+      Mul => self
+        .as_numbers(arguments)?
+        .into_iter()
+        .try_fold(Number::Integer(1), Number::mul)?
+        .into_expr(),
+      Div => {
+        let numbers = self.as_numbers(arguments)?;
+        match numbers.split_first() {
+          Some((first, [])) => Number::Integer(1).div(*first)?.into_expr(),
+          Some((first, rest)) => rest
+            .iter()
+            .try_fold(*first, |left, right| left.div(*right))?
+            .into_expr(),
+          None => return Err(WrongArity),
+        }
+      }
+      IntDiv => {
+        let numbers = self.as_numbers(arguments)?;
+        match numbers.as_slice() {
+          [left, right] => left.floor_div(*right)?.into_expr(),
+          _ => return Err(WrongArity),
+        }
+      }
+      Mod => {
+        let numbers = self.as_numbers(arguments)?;
+        match numbers.as_slice() {
+          [left, right] => left.modulo(*right)?.into_expr(),
+          _ => return Err(WrongArity),
+        }
+      }
+      BitAnd => {
+        let integers = self.as_integers(arguments)?;
+        Atom(Integer(
+          integers.into_iter().fold(-1, |left, right| left & right),
+        ))
+      }
+      BitOr => {
+        let integers = self.as_integers(arguments)?;
+        Atom(Integer(
+          integers.into_iter().fold(0, |left, right| left | right),
+        ))
+      }
+      BitXor => {
+        let integers = self.as_integers(arguments)?;
+        Atom(Integer(
+          integers.into_iter().fold(0, |left, right| left ^ right),
+        ))
+      }
+      BitNot => match self.as_integers(arguments)?.as_slice() {
+        [integer] => Atom(Integer(!integer)),
+        _ => return Err(WrongArity),
+      },
+      Shl => self.eval_shift(arguments, i64::checked_shl)?,
+      Shr => self.eval_shift(arguments, i64::checked_shr)?,
+      Gt => self.eval_comparison(arguments, Ordering::is_gt)?,
+      Lt => self.eval_comparison(arguments, Ordering::is_lt)?,
+      Ge => self.eval_comparison(arguments, Ordering::is_ge)?,
+      Le => self.eval_comparison(arguments, Ordering::is_le)?,
+      Eq => {
+        if arguments.is_empty() {
+          return Err(WrongArity);
+        }
+        arguments
+          .windows(2)
+          .all(|pair| is_equal(&pair[0], &pair[1]))
+          .into_expr()
+      }
+      Ne => {
+        if arguments.is_empty() {
+          return Err(WrongArity);
+        }
+        arguments
+          .windows(2)
+          .all(|pair| !is_equal(&pair[0], &pair[1]))
+          .into_expr()
+      }
+    };
+
+    Ok(result)
+  }
+
+  /// Checks that `compare` holds between each pair of adjacent arguments, so
+  /// that `(< a b c)` tests whether the arguments are increasing. Nothing
+  /// holds for `nan`, which is unordered.
+  fn eval_comparison<F>(
+    &mut self,
+    arguments: Vec<Expr>,
+    compare: F,
+  ) -> Result<Expr, EvalError>
+  where
+    F: Fn(Ordering) -> bool,
+  {
+    use EvalError::*;
+
+    let numbers = self.as_numbers(arguments)?;
+
+    if numbers.is_empty() {
+      return Err(WrongArity);
+    }
+
+    let result = numbers
+      .windows(2)
+      .all(|pair| pair[0].compare(pair[1]).is_some_and(&compare));
+
+    Ok(result.into_expr())
+  }
+
+  /// Shifts an integer by a number of bits, which has to be less than 64.
+  fn eval_shift<F>(
+    &mut self,
+    arguments: Vec<Expr>,
+    shift: F,
+  ) -> Result<Expr, EvalError>
+  where
+    F: Fn(i64, u32) -> Option<i64>,
+  {
+    use EvalError::*;
+
+    match self.as_integers(arguments)?.as_slice() {
+      [integer, bits] => {
+        let bits = u32::try_from(*bits).map_err(|_| IntegerOverflow)?;
+        let result = shift(*integer, bits).ok_or(IntegerOverflow)?;
+        Ok(Expr::Atom(Atom::Integer(result)))
+      }
+      _ => Err(WrongArity),
+    }
+  }
+
+  pub fn eval_atom(&mut self, atom: Atom) -> Result<Expr, EvalError> {
+    use Atom::*;
+
+    match atom {
+      Symbol(symbol) => self.eval_symbol(symbol),
+      Address(address) => {
+        let symbol = address.symbol;
+        match self.frame.get_at(address.depth, address.slot, &symbol) {
+          Some(expr) => Ok(expr),
+          None => Err(EvalError::UndefinedSymbol(symbol)),
+        }
+      }
+      Vector(vector) => {
+        let vector = vector
+          .iter()
+          .map(|expr| self.eval_expr(expr.clone()))
+          .collect::<Result<_, _>>()?;
+        Ok(Expr::Atom(Vector(vector)))
+      }
+      Map(map) => {
+        let map = map
+          .iter()
+          .map(|(key, value)| {
+            let key = self.eval_expr(key.clone())?;
+            let value = self.eval_expr(value.clone())?;
+            Ok((key, value))
+          })
+          .collect::<Result<_, _>>()?;
+        Ok(Expr::Atom(Map(map)))
+      }
+      atom => Ok(Expr::Atom(atom)),
+    }
+  }
+
+  pub fn eval_symbol(&mut self, symbol: Symbol) -> Result<Expr, EvalError> {
+    use EvalError::*;
+
+    match self.frame.get(&symbol) {
+      Some(expr) => Ok(expr.clone()),
+      None => Err(UndefinedSymbol(symbol)),
+    }
+  }
+
+  /// Parses a parameter list like `(a (b 1) & rest :c (:d 2))`.
+  fn as_parameters(&mut self, list: List) -> Result<Parameters, EvalError> {
+    use ast::Atom::*;
+    use EvalError::*;
+    use Expr::*;
+
+    let mut parameters = Parameters::default();
+    let mut exprs = list.into_iter();
+
+    while let Some(expr) = exprs.next() {
+      let is_positional_allowed = parameters.rest.is_none();
+
+      match expr {
+        Atom(Symbol(symbol)) if &*symbol.name() == "&" => {
+          let rest = self.as_symbol(exprs.next().ok_or(InvalidType)?)?;
+          if !is_positional_allowed {
+            return Err(InvalidType);
+          }
+          parameters.rest = Some(rest);
+        }
+        Atom(Symbol(symbol)) => {
+          if !is_positional_allowed || !parameters.optional.is_empty() {
+            return Err(InvalidType);
+          }
+          parameters.required.push(symbol);
+        }
+        Atom(Keyword(keyword)) => {
+          parameters.keyword.push((keyword, List(ast::List::Nil)));
+        }
+        List(list) if list.len() == 2 => {
+          let default = list.get(1).unwrap().clone();
+          match list.get(0).unwrap().clone() {
+            Atom(Symbol(symbol)) if is_positional_allowed => {
+              parameters.optional.push((symbol, default));
+            }
+            Atom(Keyword(keyword)) => {
+              parameters.keyword.push((keyword, default));
+            }
+            _ => return Err(InvalidType),
+          }
+        }
+        _ => return Err(InvalidType),
+      }
+    }
+
+    Ok(parameters)
+  }
+
+  fn as_symbol(&mut self, expr: Expr) -> Result<Symbol, EvalError> {
+    use Atom::*;
+    use EvalError::*;
+
+    match expr {
+      Expr::Atom(Symbol(symbol)) => Ok(symbol),
+      _ => Err(InvalidType),
+    }
+  }
+
+  fn as_list(&mut self, expr: Expr) -> Result<List, EvalError> {
+    use EvalError::*;
+    use Expr::*;
+
+    match expr {
+      List(list) => Ok(list),
+      _ => Err(InvalidType),
+    }
+  }
+
+  fn as_numbers(&mut self, exprs: Vec<Expr>) -> Result<Vec<Number>, EvalError> {
+    exprs.into_iter().map(Number::from_expr).collect()
+  }
+
+  fn as_integers(&mut self, exprs: Vec<Expr>) -> Result<Vec<i64>, EvalError> {
+    exprs
+      .into_iter()
+      .map(|expr| self.as_integer(expr))
+      .collect()
+  }
+
+  fn as_integer(&mut self, expr: Expr) -> Result<i64, EvalError> {
+    use Atom::*;
+    use EvalError::*;
+
+    match expr {
+      Expr::Atom(Integer(integer)) => Ok(integer),
+      _ => Err(InvalidType),
+    }
+  }
+}
+
+/// The outcome of evaluating a single form.
+pub enum Step {
+  /// The form has been fully evaluated.
+  Return(Expr),
+  /// The form still has to evaluate this expression in tail position, in the
+  /// evaluator's current frame. Errors are located at the given span, or at
+  /// the form itself if the expression wasn't read from source code.
+  Continue(Expr, Option<Span>),
+}
+
+/// Returns whether `expr` is the `else` of a `cond` or `case` clause.
+fn is_else(expr: &Expr) -> bool {
+  matches!(expr, Expr::Atom(Atom::Symbol(symbol)) if &*symbol.name() == "else")
+}
+
+/// Returns whether `expr` is the `:when` before the guard of a `match`
+/// clause.
+fn is_guard(expr: &Expr) -> bool {
+  matches!(expr, Expr::Atom(Atom::Keyword(keyword)) if &*keyword.name() == "when")
+}
+
+/// Compares numbers numerically, so that `1` is equal to `1.0` and `nan` is
+/// not equal to itself, and everything else structurally.
+fn is_equal(left: &Expr, right: &Expr) -> bool {
+  match (
+    Number::from_expr(left.clone()),
+    Number::from_expr(right.clone()),
+  ) {
+    (Ok(left), Ok(right)) => left.compare(right) == Some(Ordering::Equal),
+    _ => left == right,
+  }
+}
+
+/// Quotes each of `exprs`, so that passing them as the terms of a special form
+/// or macro call doesn't evaluate them again.
+fn quote_all(exprs: Vec<Expr>) -> List {
+  exprs.into_iter().rev().fold(List::Nil, |tail, expr| {
+    let quoted = List::cons(
+      Expr::Atom(Atom::Special(Special::Quote)),
+      List::cons(expr, List::Nil),
+    );
+    List::cons(Expr::List(quoted), tail)
+  })
+}
+
+/// Returns the expressions making up the body of a form as one expression,
+/// wrapping them in a `begin` if there are several.
+fn as_body(exprs: List) -> Result<Expr, EvalError> {
+  match &exprs {
+    List::Cons(node) if node.tail.is_empty() => Ok(node.head.clone()),
+    List::Cons(_) => {
+      let begin = Expr::Atom(Atom::Special(Special::Begin));
+      Ok(Expr::List(List::cons(begin, exprs)))
+    }
+    List::Nil => Err(EvalError::WrongArity),
+  }
+}
+
+/// Describes a raised value, using its `:message` if it is a map with one.
+fn describe_raised(expr: &Expr) -> String {
+  let message = Expr::Atom(Atom::Keyword(Symbol::new("message")));
+
+  match expr {
+    Expr::Atom(Atom::Map(map)) => match map.get(&message) {
+      Some(Expr::Atom(Atom::String(message))) => message.clone(),
+      _ => format!("raised {}", expr),
+    },
+    expr => format!("raised {}", expr),
+  }
+}
+
+#[derive(Debug, Error)]
+pub enum EvalError {
+  #[error("type is invalid")]
+  InvalidType,
+  #[error("arity is wrong")]
+  WrongArity,
+  #[error("expected {0} arguments but received {1}")]
+  WrongArgumentCount(Arity, usize),
+  #[error("keyword ':{0}' is not a parameter")]
+  UnknownKeyword(Symbol),
+  #[error("keyword ':{0}' is missing a value")]
+  MissingKeywordValue(Symbol),
+  #[error("'{0}' is undefined")]
+  UndefinedSymbol(Symbol),
+  #[error("'{0}' is already defined")]
+  Redefinition(Symbol),
+  #[error("no pattern matched {0}")]
+  NoMatch(Expr),
+  #[error("expression not callable")]
+  NotCallable,
+  #[error("index {0} is out of bounds for length {1}")]
+  IndexOutOfBounds(usize, usize),
+  #[error("start {0} exceeds end {1}")]
+  StartExceedsEnd(usize, usize),
+  #[error("integer overflow")]
+  IntegerOverflow,
+  #[error("division by zero")]
+  DivisionByZero,
+  #[error("'{0}' used outside of quasiquote")]
+  OutsideQuasiquote(Special),
+  #[error("file '{0}' not found")]
+  FileNotFound(String),
+  #[error("'{0}' is imported while it is being imported")]
+  ImportCycle(String),
+  #[error("{0}")]
+  Read(ReadError),
+  #[error("{0}")]
+  Located(Box<Diagnostic>, Box<EvalError>),
+  #[error("{0}")]
+  Native(Box<dyn Error>),
+  #[error("{}", describe_raised(.0))]
+  Raised(Expr),
+  #[error("{1}")]
+  Spanned(Span, Box<EvalError>),
+}
+
+impl EvalError {
+  /// Creates an error for a native to return, which Zuko code can catch like
+  /// any other error.
+  pub fn native<E>(error: E) -> EvalError
+  where
+    E: Into<Box<dyn Error>>,
+  {
+    EvalError::Native(error.into())
+  }
+
+  /// Converts the error into the value bound by a `catch` clause. Raised
+  /// values are passed on as they are, while other errors become maps like
+  /// `{:kind :undefined-symbol :message "'x' is undefined" :symbol x}`.
+  pub fn into_value(self) -> Expr {
+    use EvalError::*;
+
+    let error = match self {
+      Spanned(_, error) | Located(_, error) => return error.into_value(),
+      Raised(expr) => return expr,
+      error => error,
+    };
+
+    let kind = match &error {
+      InvalidType => "invalid-type",
+      WrongArity => "wrong-arity",
+      WrongArgumentCount(_, _) => "wrong-argument-count",
+      UnknownKeyword(_) => "unknown-keyword",
+      MissingKeywordValue(_) => "missing-keyword-value",
+      UndefinedSymbol(_) => "undefined-symbol",
+      Redefinition(_) => "redefinition",
+      NoMatch(_) => "no-match",
+      NotCallable => "not-callable",
+      IndexOutOfBounds(_, _) => "index-out-of-bounds",
+      StartExceedsEnd(_, _) => "start-exceeds-end",
+      IntegerOverflow => "integer-overflow",
+      DivisionByZero => "division-by-zero",
+      OutsideQuasiquote(_) => "outside-quasiquote",
+      FileNotFound(_) => "file-not-found",
+      ImportCycle(_) => "import-cycle",
+      Read(_) => "read",
+      Native(_) => "native",
+      Spanned(_, _) | Located(_, _) | Raised(_) => unreachable!(),
+    };
+
+    let keyword = |name: &str| Expr::Atom(Atom::Keyword(Symbol::new(name)));
+
+    let mut map = Map::new().insert(keyword("kind"), keyword(kind)).insert(
+      keyword("message"),
+      Expr::Atom(Atom::String(error.to_string())),
+    );
+    match error {
+      UndefinedSymbol(symbol) | Redefinition(symbol) => {
+        map = map.insert(keyword("symbol"), Expr::Atom(Atom::Symbol(symbol)));
+      }
+      NoMatch(value) => map = map.insert(keyword("value"), value),
+      _ => {}
+    }
+
+    Expr::Atom(Atom::Map(map))
+  }
+
+  /// Returns where the error occurred, if known.
+  pub fn span(&self) -> Option<Span> {
+    match self {
+      EvalError::Spanned(span, _) => Some(*span),
+      _ => None,
+    }
+  }
+
+  /// Attaches `span` to the error, unless it was already located by a more
+  /// deeply nested expression.
+  pub fn with_span(self, span: Option<Span>) -> EvalError {
+    match (self, span) {
+      (error @ EvalError::Spanned(_, _), _) => error,
+      (error, Some(span)) => EvalError::Spanned(span, Box::new(error)),
+      (error, None) => error,
+    }
+  }
+}
@@ -0,0 +1,221 @@
+use std::collections::HashMap;
+use std::fs;
+use std::iter;
+use std::mem;
+use std::path::{Path, PathBuf};
+
+use crate::ast::{Atom, Expr, List, Span, Symbol};
+use crate::diagnostic::Diagnostic;
+use crate::env::Frame;
+use crate::read;
+
+use super::{EvalError, Evaluator};
+
+/// The modules that have been imported, so that each one is only evaluated
+/// once.
+#[derive(Default)]
+pub struct Modules {
+  /// The bindings exported by each module, keyed by its canonical path. A
+  /// module which is still being evaluated has no bindings yet.
+  entries: HashMap<PathBuf, Option<Vec<(Symbol, Expr)>>>,
+  /// The names exported so far by the module being evaluated, if any.
+  exports: Option<Vec<Symbol>>,
+  /// The file being evaluated, which relative paths are resolved against.
+  path: Option<PathBuf>,
+  search_paths: Vec<PathBuf>,
+}
+
+impl Modules {
+  pub fn new() -> Modules {
+    Modules::default()
+  }
+}
+
+impl Evaluator {
+  /// Sets the file being evaluated, so that `load` and `import` can resolve
+  /// paths relative to it.
+  pub fn set_path<P>(&mut self, path: P)
+  where
+    P: Into<PathBuf>,
+  {
+    self.modules.path = Some(path.into());
+  }
+
+  /// Adds a directory to search for files passed to `load` and `import`, if
+  /// they aren't found relative to the file being evaluated.
+  pub fn add_search_path<P>(&mut self, path: P)
+  where
+    P: Into<PathBuf>,
+  {
+    self.modules.search_paths.push(path.into());
+  }
+
+  /// Reads and evaluates the file at `path` in the current frame, returning
+  /// the value of its last expression.
+  pub fn load(&mut self, path: &str) -> Result<Expr, EvalError> {
+    let path = self.resolve_path(path)?;
+    self.eval_file(&path)
+  }
+
+  /// Evaluates the module at `path`, unless it has already been, and binds
+  /// each binding it exports as `alias/name` in `frame`.
+  pub fn import(
+    &mut self,
+    path: &str,
+    alias: &Symbol,
+    frame: &mut Frame,
+  ) -> Result<(), EvalError> {
+    use EvalError::*;
+
+    let resolved = self.resolve_path(path)?;
+    let key = fs::canonicalize(&resolved).unwrap_or_else(|_| resolved.clone());
+
+    let exports = match self.modules.entries.get(&key) {
+      Some(Some(exports)) => exports.clone(),
+      Some(None) => return Err(ImportCycle(path.to_string())),
+      None => {
+        self.modules.entries.insert(key.clone(), None);
+        match self.eval_module(&resolved) {
+          Ok(exports) => {
+            self.modules.entries.insert(key, Some(exports.clone()));
+            exports
+          }
+          Err(error) => {
+            self.modules.entries.remove(&key);
+            return Err(error);
+          }
+        }
+      }
+    };
+
+    for (name, expr) in exports {
+      frame.set(Symbol::new(format!("{}/{}", alias, name)), expr);
+    }
+
+    Ok(())
+  }
+
+  /// Marks `names` as exported from the module being evaluated. Outside of a
+  /// module, such as in the file being run, this does nothing.
+  pub fn export(&mut self, names: Vec<Symbol>) {
+    if let Some(exports) = &mut self.modules.exports {
+      exports.extend(names);
+    }
+  }
+
+  /// Evaluates the module at `path` in its own frame, returning the bindings
+  /// it exports.
+  fn eval_module(
+    &mut self,
+    path: &Path,
+  ) -> Result<Vec<(Symbol, Expr)>, EvalError> {
+    use EvalError::*;
+
+    let frame = Frame::with_parent(self.root.clone());
+    let original_frame = mem::replace(&mut self.frame, frame);
+    let original_exports = self.modules.exports.replace(Vec::new());
+
+    let result = self.eval_file(path);
+
+    let frame = mem::replace(&mut self.frame, original_frame);
+    let exports = mem::replace(&mut self.modules.exports, original_exports);
+    result?;
+
+    exports
+      .unwrap_or_default()
+      .into_iter()
+      .map(|name| match frame.get(&name) {
+        Some(expr) => Ok((name, expr)),
+        None => Err(UndefinedSymbol(name)),
+      })
+      .collect()
+  }
+
+  /// Reads and evaluates the file at `path`, locating any error within it.
+  fn eval_file(&mut self, path: &Path) -> Result<Expr, EvalError> {
+    use EvalError::*;
+
+    let source = fs::read_to_string(path).map_err(|error| {
+      EvalError::native(format!(
+        "failed to read '{}': {}",
+        path.display(),
+        error
+      ))
+    })?;
+
+    let original_path = self.modules.path.replace(path.to_path_buf());
+    let result = read::read(&source)
+      .map_err(Read)
+      .and_then(|expr| self.eval_expr(expr));
+    self.modules.path = original_path;
+
+    result.map_err(|error| {
+      let span = match &error {
+        Read(error) => Some(Span::from(error.position())),
+        error => error.span(),
+      };
+      match span {
+        Some(span) => {
+          let message = error.to_string();
+          let path = path.display().to_string();
+          let diagnostic = Diagnostic::new(path, &source, span, message);
+          Located(Box::new(diagnostic), Box::new(error))
+        }
+        None => error,
+      }
+    })
+  }
+
+  /// Finds the file at `path`, relative to either the file being evaluated or
+  /// one of the search paths.
+  fn resolve_path(&self, path: &str) -> Result<PathBuf, EvalError> {
+    use EvalError::*;
+
+    let relative = match self.modules.path.as_ref().and_then(|p| p.parent()) {
+      Some(directory) => directory.join(path),
+      None => PathBuf::from(path),
+    };
+
+    iter::once(relative)
+      .chain(self.modules.search_paths.iter().map(|dir| dir.join(path)))
+      .find(|path| path.is_file())
+      .ok_or_else(|| FileNotFound(path.to_string()))
+  }
+
+  /// Parses the arguments of an `import`, which are either a symbol like
+  /// `lib/math` naming `lib/math.zuko`, or a string holding the path, followed
+  /// by an optional alias. The alias defaults to the name of the file.
+  pub(super) fn as_import(
+    &mut self,
+    tail: List,
+  ) -> Result<(String, Symbol), EvalError> {
+    use EvalError::*;
+
+    if tail.len() != 1 && tail.len() != 2 {
+      return Err(WrongArity);
+    }
+
+    let path = match tail.get(0).unwrap() {
+      Expr::Atom(Atom::Symbol(symbol)) => format!("{}.zuko", symbol),
+      Expr::Atom(Atom::String(string)) => string.clone(),
+      _ => return Err(InvalidType),
+    };
+
+    let alias = match tail.get(1) {
+      Some(expr) => self.as_symbol(expr.clone())?,
+      None => {
+        let stem = Path::new(&path).file_stem().ok_or(InvalidType)?;
+        Symbol::new(stem.to_string_lossy())
+      }
+    };
+
+    Ok((path, alias))
+  }
+
+  pub(super) fn as_export(
+    &mut self,
+    tail: List,
+  ) -> Result<Vec<Symbol>, EvalError> {
+    tail.into_iter().map(|expr| self.as_symbol(expr)).collect()
+  }
+}
@@ -0,0 +1,144 @@
+use std::cmp::Ordering;
+
+use crate::ast::{Atom, Expr};
+
+use super::EvalError;
+
+/// An operand of an arithmetic operator. Integer arithmetic is exact and fails
+/// on overflow, while an operation involving a float is done in floats.
+#[derive(Clone, Copy, Debug)]
+pub enum Number {
+  Integer(i64),
+  Float(f64),
+}
+
+impl Number {
+  pub fn from_expr(expr: Expr) -> Result<Number, EvalError> {
+    match expr {
+      Expr::Atom(Atom::Integer(integer)) => Ok(Number::Integer(integer)),
+      Expr::Atom(Atom::Float(float)) => Ok(Number::Float(float)),
+      _ => Err(EvalError::InvalidType),
+    }
+  }
+
+  pub fn into_expr(self) -> Expr {
+    match self {
+      Number::Integer(integer) => Expr::Atom(Atom::Integer(integer)),
+      Number::Float(float) => Expr::Atom(Atom::Float(float)),
+    }
+  }
+
+  pub fn as_float(self) -> f64 {
+    match self {
+      Number::Integer(integer) => integer as f64,
+      Number::Float(float) => float,
+    }
+  }
+
+  pub fn neg(self) -> Result<Number, EvalError> {
+    match self {
+      Number::Integer(integer) => integer
+        .checked_neg()
+        .map(Number::Integer)
+        .ok_or(EvalError::IntegerOverflow),
+      Number::Float(float) => Ok(Number::Float(-float)),
+    }
+  }
+
+  pub fn add(self, other: Number) -> Result<Number, EvalError> {
+    self.combine(other, i64::checked_add, |left, right| left + right)
+  }
+
+  pub fn sub(self, other: Number) -> Result<Number, EvalError> {
+    self.combine(other, i64::checked_sub, |left, right| left - right)
+  }
+
+  pub fn mul(self, other: Number) -> Result<Number, EvalError> {
+    self.combine(other, i64::checked_mul, |left, right| left * right)
+  }
+
+  /// Divides in floats, so that `(/ 1 2)` is `0.5`.
+  pub fn div(self, other: Number) -> Result<Number, EvalError> {
+    other.check_divisor()?;
+    Ok(Number::Float(self.as_float() / other.as_float()))
+  }
+
+  /// Divides, rounding towards negative infinity.
+  pub fn floor_div(self, other: Number) -> Result<Number, EvalError> {
+    other.check_divisor()?;
+    self.combine(
+      other,
+      |left, right| {
+        let quotient = left.checked_div(right)?;
+        if (left % right != 0) && ((left < 0) != (right < 0)) {
+          Some(quotient - 1)
+        } else {
+          Some(quotient)
+        }
+      },
+      |left, right| (left / right).floor(),
+    )
+  }
+
+  /// Takes the remainder of `floor_div`, which has the sign of the divisor.
+  pub fn modulo(self, other: Number) -> Result<Number, EvalError> {
+    other.check_divisor()?;
+    self.combine(
+      other,
+      |left, right| {
+        let remainder = left.checked_rem(right)?;
+        if remainder != 0 && ((remainder < 0) != (right < 0)) {
+          Some(remainder + right)
+        } else {
+          Some(remainder)
+        }
+      },
+      |left, right| {
+        let remainder = left % right;
+        if remainder != 0.0 && ((remainder < 0.0) != (right < 0.0)) {
+          remainder + right
+        } else {
+          remainder
+        }
+      },
+    )
+  }
+
+  /// Compares numerically, so that `1` and `1.0` are equal and `nan` is
+  /// unordered.
+  pub fn compare(self, other: Number) -> Option<Ordering> {
+    match (self, other) {
+      (Number::Integer(left), Number::Integer(right)) => Some(left.cmp(&right)),
+      (left, right) => left.as_float().partial_cmp(&right.as_float()),
+    }
+  }
+
+  /// Dividing by an exact zero is an error, while floats follow IEEE 754, so
+  /// `(/ 1 0.0)` is `inf`.
+  fn check_divisor(self) -> Result<(), EvalError> {
+    match self {
+      Number::Integer(0) => Err(EvalError::DivisionByZero),
+      _ => Ok(()),
+    }
+  }
+
+  fn combine<I, F>(
+    self,
+    other: Number,
+    integer: I,
+    float: F,
+  ) -> Result<Number, EvalError>
+  where
+    I: Fn(i64, i64) -> Option<i64>,
+    F: Fn(f64, f64) -> f64,
+  {
+    match (self, other) {
+      (Number::Integer(left), Number::Integer(right)) => integer(left, right)
+        .map(Number::Integer)
+        .ok_or(EvalError::IntegerOverflow),
+      (left, right) => {
+        Ok(Number::Float(float(left.as_float(), right.as_float())))
+      }
+    }
+  }
+}
@@ -0,0 +1,113 @@
+use crate::ast::{Atom, Expr, List, Special, Symbol};
+
+use super::{is_equal, EvalError};
+
+/// Returns the symbols bound by `pattern`, in the order that `matches` binds
+/// them, without repeating any.
+pub fn names(pattern: &Expr) -> Vec<Symbol> {
+  let mut names = Vec::new();
+  collect_names(pattern, &mut names);
+  names
+}
+
+fn collect_names(pattern: &Expr, names: &mut Vec<Symbol>) {
+  match pattern {
+    Expr::Atom(Atom::Symbol(symbol))
+      if !is_wildcard(symbol) && !is_dot(symbol) && !names.contains(symbol) =>
+    {
+      names.push(*symbol);
+    }
+    Expr::List(list) if as_quoted(list).is_none() => {
+      for node in list.iter() {
+        collect_names(&node.head, names);
+      }
+    }
+    _ => {}
+  }
+}
+
+/// Tests whether `value` has the shape of `pattern`, pushing the value of
+/// each symbol in it onto `bindings` in the order of `names`.
+///
+/// `_` matches anything, and any other symbol matches anything and binds it,
+/// though a symbol appearing twice has to match equal values. Lists match
+/// lists of the same length element by element, unless they end in `. rest`,
+/// which matches the remaining elements. Quoted expressions and every other
+/// atom match values which are `=` to them.
+pub fn matches(
+  pattern: &Expr,
+  value: &Expr,
+  bindings: &mut Vec<(Symbol, Expr)>,
+) -> Result<bool, EvalError> {
+  match pattern {
+    Expr::Atom(Atom::Symbol(symbol)) if is_wildcard(symbol) => Ok(true),
+    Expr::Atom(Atom::Symbol(symbol)) if is_dot(symbol) => {
+      Err(EvalError::InvalidType)
+    }
+    Expr::Atom(Atom::Symbol(symbol)) => {
+      match bindings.iter().find(|(name, _)| name == symbol) {
+        Some((_, bound)) => Ok(is_equal(bound, value)),
+        None => {
+          bindings.push((*symbol, value.clone()));
+          Ok(true)
+        }
+      }
+    }
+    Expr::List(list) => match as_quoted(list) {
+      Some(expr) => Ok(is_equal(expr, value)),
+      None => match value {
+        Expr::List(values) => matches_list(list, values, bindings),
+        _ => Ok(false),
+      },
+    },
+    pattern => Ok(is_equal(pattern, value)),
+  }
+}
+
+fn matches_list(
+  patterns: &List,
+  values: &List,
+  bindings: &mut Vec<(Symbol, Expr)>,
+) -> Result<bool, EvalError> {
+  let mut patterns = patterns.iter();
+  let mut values = values.clone();
+
+  while let Some(node) = patterns.next() {
+    if let Expr::Atom(Atom::Symbol(symbol)) = &node.head {
+      if is_dot(symbol) {
+        let rest = match (patterns.next(), patterns.next()) {
+          (Some(rest), None) => rest,
+          _ => return Err(EvalError::InvalidType),
+        };
+        return matches(&rest.head, &Expr::List(values), bindings);
+      }
+    }
+
+    let value = match &values {
+      List::Cons(value) => value.clone(),
+      List::Nil => return Ok(false),
+    };
+    if !matches(&node.head, &value.head, bindings)? {
+      return Ok(false);
+    }
+    values = value.tail.clone();
+  }
+
+  Ok(values.is_empty())
+}
+
+/// Returns the quoted expression if `list` is like `(quote expr)`.
+fn as_quoted(list: &List) -> Option<&Expr> {
+  match (list.len(), list.get(0)) {
+    (2, Some(Expr::Atom(Atom::Special(Special::Quote)))) => list.get(1),
+    _ => None,
+  }
+}
+
+fn is_wildcard(symbol: &Symbol) -> bool {
+  &*symbol.name() == "_"
+}
+
+fn is_dot(symbol: &Symbol) -> bool {
+  &*symbol.name() == "."
+}
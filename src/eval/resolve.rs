@@ -0,0 +1,612 @@
+//! Resolves the local variables of a function body to addresses.
+//!
+//! Each call to a function gets a frame with a slot for each of its
+//! parameters and each symbol defined in its body. Likewise, `let` forms and
+//! `catch` and `match` clauses get a frame with a slot for each symbol they
+//! bind and each symbol defined within the expressions evaluated in that
+//! frame. Every reference to one of these locals is replaced with the slot it
+//! lives in and how many frames up that slot is, so evaluating it skips
+//! looking it up by name. Free variables are left as symbols and are still
+//! looked up by name.
+//!
+//! A local which hasn't been defined yet is looked up by name in the frames
+//! around it, so a body can use a global before shadowing it with `define`,
+//! just as before.
+
+use std::rc::Rc;
+
+use crate::ast::{Address, Atom, Expr, List, Parameters, Special, Symbol};
+
+use super::{as_body, pattern};
+
+/// Resolves the defaults and body of a function, returning the resolved body
+/// along with the names of the slots in each call's frame.
+pub fn resolve_function(
+  parameters: &mut Parameters,
+  body: Expr,
+) -> (Expr, Rc<[Symbol]>) {
+  let names = parameters.iter().map(|(name, _)| *name).collect();
+  let locals = collect_locals(names, &[&body]);
+
+  let mut resolver = Resolver {
+    scopes: vec![locals],
+  };
+  for (_, default) in parameters.optional.iter_mut() {
+    *default = resolver.resolve(default.clone());
+  }
+  for (_, default) in parameters.keyword.iter_mut() {
+    *default = resolver.resolve(default.clone());
+  }
+  let body = resolver.resolve(body);
+
+  let locals = resolver.scopes.pop().unwrap();
+  (body, Rc::from(locals))
+}
+
+/// Returns the names of the slots in a frame binding `names`, in which
+/// `exprs` are then evaluated.
+pub fn frame_locals(names: Vec<Symbol>, exprs: &[&Expr]) -> Rc<[Symbol]> {
+  Rc::from(collect_locals(names, exprs))
+}
+
+/// Turns addresses back into symbols, so that a macro expanded while the
+/// function runs sees the terms as they were written.
+pub fn unresolve(list: &List) -> List {
+  map_elements(list, |_, expr| unresolve_expr(expr))
+}
+
+fn unresolve_expr(expr: Expr) -> Expr {
+  match expr {
+    Expr::List(list) => Expr::List(unresolve(&list)),
+    Expr::Atom(Atom::Address(address)) => {
+      Expr::Atom(Atom::Symbol(address.symbol))
+    }
+    Expr::Atom(Atom::Vector(vector)) => Expr::Atom(Atom::Vector(
+      vector.iter().cloned().map(unresolve_expr).collect(),
+    )),
+    Expr::Atom(Atom::Map(map)) => Expr::Atom(Atom::Map(
+      map
+        .iter()
+        .map(|(key, value)| {
+          (unresolve_expr(key.clone()), unresolve_expr(value.clone()))
+        })
+        .collect(),
+    )),
+    atom => atom,
+  }
+}
+
+struct Resolver {
+  /// The locals of each scope, from the outermost to the innermost.
+  scopes: Vec<Vec<Symbol>>,
+}
+
+impl Resolver {
+  fn resolve(&mut self, expr: Expr) -> Expr {
+    use Special::*;
+
+    let list = match expr {
+      Expr::List(list) => list,
+      Expr::Atom(atom) => return self.resolve_atom(atom),
+    };
+
+    match list.get(0) {
+      Some(Expr::Atom(Atom::Special(special))) => match special {
+        Quote | Macro | Syntax | Import | Export => Expr::List(list),
+        Quasiquote => {
+          Expr::List(map_elements(&list, |index, expr| match index {
+            1 => self.resolve_quasiquote(expr, 1),
+            _ => expr,
+          }))
+        }
+        Function => self.resolve_function(list),
+        Define => self.resolve_define(list),
+        Try => self.resolve_try(list),
+        Let | LetStar | Letrec => self.resolve_let(special.clone(), list),
+        Cond => Expr::List(map_elements(&list, |index, expr| match index {
+          0 => expr,
+          _ => self.resolve_clause(expr, false),
+        })),
+        Match => self.resolve_match(list),
+        Case => Expr::List(map_elements(&list, |index, expr| match index {
+          0 => expr,
+          1 => self.resolve(expr),
+          _ => self.resolve_clause(expr, true),
+        })),
+        _ => self.resolve_elements(&list),
+      },
+      _ => self.resolve_elements(&list),
+    }
+  }
+
+  fn resolve_atom(&mut self, atom: Atom) -> Expr {
+    match atom {
+      Atom::Symbol(symbol) => self.lookup(symbol),
+      Atom::Vector(vector) => Expr::Atom(Atom::Vector(
+        vector
+          .iter()
+          .map(|expr| self.resolve(expr.clone()))
+          .collect(),
+      )),
+      Atom::Map(map) => Expr::Atom(Atom::Map(
+        map
+          .iter()
+          .map(|(key, value)| {
+            (self.resolve(key.clone()), self.resolve(value.clone()))
+          })
+          .collect(),
+      )),
+      atom => Expr::Atom(atom),
+    }
+  }
+
+  /// Returns the address of the innermost local named `symbol`, or the
+  /// symbol itself if it is a free variable.
+  fn lookup(&self, symbol: Symbol) -> Expr {
+    for (depth, locals) in self.scopes.iter().rev().enumerate() {
+      if let Some(slot) = locals.iter().position(|local| local == &symbol) {
+        return Expr::Atom(Atom::Address(Address {
+          symbol,
+          depth,
+          slot,
+        }));
+      }
+    }
+    Expr::Atom(Atom::Symbol(symbol))
+  }
+
+  fn resolve_elements(&mut self, list: &List) -> Expr {
+    Expr::List(map_elements(list, |_, expr| self.resolve(expr)))
+  }
+
+  /// Resolves the elements of a `cond` or `case` clause, which isn't a call
+  /// itself. The data of a `case` clause are left alone, like quoted ones.
+  fn resolve_clause(&mut self, clause: Expr, is_case: bool) -> Expr {
+    match clause {
+      Expr::List(clause) => {
+        Expr::List(map_elements(&clause, |index, expr| match index {
+          0 if is_case => expr,
+          _ => self.resolve(expr),
+        }))
+      }
+      expr => expr,
+    }
+  }
+
+  /// Resolves a function created within the body in a scope of its own,
+  /// leaving its parameter names alone.
+  fn resolve_function(&mut self, list: List) -> Expr {
+    let parameters = match (list.len(), list.get(1)) {
+      (3, Some(Expr::List(parameters))) => parameters.clone(),
+      _ => return Expr::List(list),
+    };
+    let body = list.get(2).unwrap().clone();
+
+    let (parameters, body) = self.resolve_function_parts(&parameters, body);
+
+    Expr::List(map_elements(&list, |index, expr| match index {
+      1 => Expr::List(parameters.clone()),
+      2 => body.clone(),
+      _ => expr,
+    }))
+  }
+
+  /// Resolves a `define`, which might define a function as in
+  /// `(define (name parameters) body)`.
+  fn resolve_define(&mut self, list: List) -> Expr {
+    let (signature, body) = match (list.get(1), list.iter().nth(1)) {
+      (Some(Expr::List(List::Cons(signature))), Some(node)) => {
+        match as_body(node.tail.clone()) {
+          Ok(body) => (signature.clone(), body),
+          Err(_) => return self.resolve_elements(&list),
+        }
+      }
+      _ => return self.resolve_elements(&list),
+    };
+    let list = take_elements(&list, 3);
+
+    let (parameters, body) = self.resolve_function_parts(&signature.tail, body);
+    let name = self.resolve(signature.head.clone());
+    let signature = List::cons_spanned(name, parameters, signature.span);
+
+    Expr::List(map_elements(&list, |index, expr| match index {
+      1 => Expr::List(signature.clone()),
+      2 => body.clone(),
+      _ => expr,
+    }))
+  }
+
+  fn resolve_function_parts(
+    &mut self,
+    parameters: &List,
+    body: Expr,
+  ) -> (List, Expr) {
+    self
+      .scopes
+      .push(collect_locals(parameter_names(parameters), &[&body]));
+
+    let parameters = map_elements(parameters, |_, parameter| match parameter {
+      Expr::List(parameter) if parameter.len() == 2 => {
+        Expr::List(map_elements(&parameter, |index, expr| match index {
+          1 => self.resolve(expr),
+          _ => expr,
+        }))
+      }
+      parameter => parameter,
+    });
+    let body = self.resolve(body);
+
+    self.scopes.pop();
+
+    (parameters, body)
+  }
+
+  /// Resolves the values and body of a `let`, `let*` or `letrec` in the
+  /// scopes that `Evaluator` creates frames for.
+  fn resolve_let(&mut self, special: Special, list: List) -> Expr {
+    let (name, bindings, body) = match as_let(&special, &list) {
+      Some(parts) => parts,
+      None => return Expr::List(list),
+    };
+    let names: Vec<Symbol> = bindings.iter().map(|(name, _)| *name).collect();
+
+    let depth = self.scopes.len();
+    let mut values = Vec::new();
+
+    match (special, name) {
+      (Special::LetStar, _) => {
+        if bindings.is_empty() {
+          self.scopes.push(collect_locals(Vec::new(), &[&body]));
+        }
+        for (index, (name, value)) in bindings.iter().enumerate() {
+          values.push(self.resolve(value.clone()));
+          let next = match bindings.get(index + 1) {
+            Some((_, next)) => next,
+            None => &body,
+          };
+          self.scopes.push(collect_locals(vec![*name], &[next]));
+        }
+      }
+      (Special::Letrec, _) => {
+        let mut exprs: Vec<&Expr> =
+          bindings.iter().map(|(_, value)| value).collect();
+        exprs.push(&body);
+        self.scopes.push(collect_locals(names, &exprs));
+        for (_, value) in bindings.iter() {
+          values.push(self.resolve(value.clone()));
+        }
+      }
+      (_, name) => {
+        for (_, value) in bindings.iter() {
+          values.push(self.resolve(value.clone()));
+        }
+        // A named `let` binds its name in a frame of its own, around the
+        // frame of each call to the function.
+        if let Some(name) = name {
+          self.scopes.push(vec![name]);
+        }
+        self.scopes.push(collect_locals(names, &[&body]));
+      }
+    }
+
+    let body = self.resolve(body);
+    self.scopes.truncate(depth);
+
+    // A body of several expressions is replaced by the `begin` wrapping them.
+    let bindings_index = if name.is_some() { 2 } else { 1 };
+    let list = take_elements(&list, bindings_index + 2);
+    let mut values = values.into_iter();
+    Expr::List(map_elements(&list, |index, expr| match expr {
+      Expr::List(bindings) if index == bindings_index => {
+        Expr::List(map_elements(&bindings, |_, binding| match binding {
+          Expr::List(binding) => {
+            Expr::List(map_elements(&binding, |index, expr| match index {
+              1 => values.next().unwrap(),
+              _ => expr,
+            }))
+          }
+          binding => binding,
+        }))
+      }
+      _ if index == bindings_index + 1 => body.clone(),
+      expr => expr,
+    }))
+  }
+
+  /// Resolves the `catch` clause of a `try` in a scope of its own, since the
+  /// error is bound in a frame of its own.
+  fn resolve_try(&mut self, list: List) -> Expr {
+    Expr::List(map_elements(&list, |index, expr| {
+      let clause = match expr {
+        Expr::List(clause) if index > 1 => clause,
+        expr => return self.resolve(expr),
+      };
+
+      match as_catch(&clause) {
+        Some((name, handler)) => {
+          self.scopes.push(collect_locals(vec![name], &[handler]));
+          let clause = map_elements(&clause, |index, expr| match index {
+            2 => self.resolve(expr),
+            _ => expr,
+          });
+          self.scopes.pop();
+          Expr::List(clause)
+        }
+        None => Expr::List(map_elements(&clause, |index, expr| match index {
+          0 => expr,
+          _ => self.resolve(expr),
+        })),
+      }
+    }))
+  }
+
+  /// Resolves a `match`, each of whose clauses binds the symbols in its
+  /// pattern in a scope of its own. The patterns are left alone.
+  fn resolve_match(&mut self, list: List) -> Expr {
+    Expr::List(map_elements(&list, |index, expr| {
+      let clause = match expr {
+        Expr::List(List::Cons(clause)) if index > 1 => clause,
+        expr if index == 1 => return self.resolve(expr),
+        expr => return expr,
+      };
+
+      let exprs: Vec<&Expr> =
+        clause.tail.iter().map(|node| &node.head).collect();
+      self
+        .scopes
+        .push(collect_locals(pattern::names(&clause.head), &exprs));
+      let tail = map_elements(&clause.tail, |_, expr| self.resolve(expr));
+      self.scopes.pop();
+
+      Expr::List(List::cons_spanned(clause.head.clone(), tail, clause.span))
+    }))
+  }
+
+  /// Resolves the expressions unquoted at `depth`, mirroring how
+  /// `Evaluator::quasiquote` decides which ones to evaluate.
+  fn resolve_quasiquote(&mut self, expr: Expr, depth: usize) -> Expr {
+    use Special::*;
+
+    let list = match expr {
+      Expr::List(list) => list,
+      atom => return atom,
+    };
+
+    let special = match list.get(0) {
+      Some(Expr::Atom(Atom::Special(special))) if list.len() == 2 => {
+        Some(special.clone())
+      }
+      _ => None,
+    };
+
+    let depth = match special {
+      Some(Unquote) if depth == 1 => return self.resolve_unquote(&list),
+      Some(Unquote) | Some(UnquoteSplicing) => depth.saturating_sub(1),
+      Some(Quasiquote) => depth + 1,
+      _ => depth,
+    };
+
+    Expr::List(map_elements(&list, |_, expr| match &expr {
+      Expr::List(element)
+        if depth == 1
+          && element.len() == 2
+          && element.get(0)
+            == Some(&Expr::Atom(Atom::Special(UnquoteSplicing))) =>
+      {
+        self.resolve_unquote(element)
+      }
+      _ => self.resolve_quasiquote(expr, depth),
+    }))
+  }
+
+  fn resolve_unquote(&mut self, list: &List) -> Expr {
+    Expr::List(map_elements(list, |index, expr| match index {
+      1 => self.resolve(expr),
+      _ => expr,
+    }))
+  }
+}
+
+/// Returns the locals of a scope, which are `names` followed by the symbols
+/// defined directly within `exprs`.
+///
+/// A body that has already been resolved as part of an enclosing function
+/// defines addresses rather than symbols, and those keep their slots.
+fn collect_locals(names: Vec<Symbol>, exprs: &[&Expr]) -> Vec<Symbol> {
+  let mut defines = Vec::new();
+  for expr in exprs {
+    collect_defines(expr, &mut defines);
+  }
+
+  let mut addresses: Vec<Address> = defines
+    .iter()
+    .filter_map(|expr| match expr {
+      Expr::Atom(Atom::Address(address)) => Some(*address),
+      _ => None,
+    })
+    .collect();
+  addresses.sort_by_key(|address| address.slot);
+  let symbols = defines.iter().filter_map(|expr| match expr {
+    Expr::Atom(Atom::Symbol(symbol)) => Some(*symbol),
+    _ => None,
+  });
+
+  let mut locals = Vec::new();
+  let defined = addresses
+    .iter()
+    .map(|address| address.symbol)
+    .chain(symbols);
+  for symbol in names.into_iter().chain(defined) {
+    if !locals.contains(&symbol) {
+      locals.push(symbol);
+    }
+  }
+
+  locals
+}
+
+/// Collects the targets of the `define`s evaluated in the same frame as
+/// `expr`. Collecting too many only leaves some slots unused, so quasiquotes
+/// are searched as a whole.
+fn collect_defines(expr: &Expr, defines: &mut Vec<Expr>) {
+  use Special::*;
+
+  let list = match expr {
+    Expr::List(list) => list,
+    Expr::Atom(_) => return,
+  };
+
+  let special = match list.get(0) {
+    Some(Expr::Atom(Atom::Special(special))) => Some(special),
+    _ => None,
+  };
+  match special {
+    Some(Quote) | Some(Function) | Some(Macro) | Some(Syntax) => return,
+    Some(Define) => match list.get(1) {
+      // The parameters and body of a function belong to its own frame.
+      Some(Expr::List(List::Cons(signature))) => {
+        defines.push(signature.head.clone());
+        return;
+      }
+      target => defines.extend(target.cloned()),
+    },
+    Some(special @ Let) | Some(special @ LetStar) | Some(special @ Letrec) => {
+      // Only the values of a `let` and the first value of a `let*` are
+      // evaluated in the enclosing frame.
+      if let Some((_, bindings, _)) = as_let(special, list) {
+        let count = match special {
+          Let => bindings.len(),
+          LetStar => 1,
+          _ => 0,
+        };
+        for (_, value) in bindings.iter().take(count) {
+          collect_defines(value, defines);
+        }
+      }
+      return;
+    }
+    _ => {}
+  }
+
+  // The clauses of a `match` bind in frames of their own.
+  if special == Some(&Match) {
+    if let Some(value) = list.get(1) {
+      collect_defines(value, defines);
+    }
+    return;
+  }
+
+  // The `catch` clause of a `try` binds in a frame of its own.
+  let is_try = special == Some(&Try);
+  for node in list.iter() {
+    if let Expr::List(clause) = &node.head {
+      if is_try && as_catch(clause).is_some() {
+        continue;
+      }
+    }
+    collect_defines(&node.head, defines);
+  }
+}
+
+/// Returns the parameter names in a parameter list, in the same order as
+/// `Parameters::iter`.
+fn parameter_names(parameters: &List) -> Vec<Symbol> {
+  let mut positional = Vec::new();
+  let mut keyword = Vec::new();
+
+  for node in parameters.iter() {
+    let name = match &node.head {
+      Expr::List(parameter) => parameter.get(0),
+      expr => Some(expr),
+    };
+    match name {
+      Some(Expr::Atom(Atom::Symbol(symbol))) if &*symbol.name() != "&" => {
+        positional.push(*symbol)
+      }
+      Some(Expr::Atom(Atom::Keyword(symbol))) => keyword.push(*symbol),
+      _ => {}
+    }
+  }
+
+  positional.extend(keyword);
+  positional
+}
+
+/// Returns the name, bindings and body of a `let`, `let*` or `letrec`. Only
+/// `let` can be named, as in `(let loop ((i 0)) body)`.
+#[allow(clippy::type_complexity)]
+fn as_let(
+  special: &Special,
+  list: &List,
+) -> Option<(Option<Symbol>, Vec<(Symbol, Expr)>, Expr)> {
+  let mut nodes = list.iter().skip(1);
+
+  let name = match (special, list.get(1)) {
+    (Special::Let, Some(Expr::Atom(Atom::Symbol(name)))) => {
+      nodes.next();
+      Some(*name)
+    }
+    _ => None,
+  };
+
+  let node = nodes.next()?;
+  let (bindings, body) = match &node.head {
+    Expr::List(bindings) => (bindings, as_body(node.tail.clone()).ok()?),
+    _ => return None,
+  };
+
+  let bindings = bindings
+    .iter()
+    .map(|node| match &node.head {
+      Expr::List(binding) if binding.len() == 2 => match binding.get(0) {
+        Some(Expr::Atom(Atom::Symbol(name))) => {
+          Some((*name, binding.get(1).unwrap().clone()))
+        }
+        _ => None,
+      },
+      _ => None,
+    })
+    .collect::<Option<_>>()?;
+
+  Some((name, bindings, body))
+}
+
+/// Returns the name and handler of a `(catch name handler)` clause.
+fn as_catch(clause: &List) -> Option<(Symbol, &Expr)> {
+  match (clause.len(), clause.get(0), clause.get(1)) {
+    (
+      3,
+      Some(Expr::Atom(Atom::Symbol(keyword))),
+      Some(Expr::Atom(Atom::Symbol(name))),
+    ) if &*keyword.name() == "catch" => Some((*name, clause.get(2).unwrap())),
+    _ => None,
+  }
+}
+
+/// Maps each element of `list` along with its index, keeping their spans.
+/// Returns a list of the first `len` elements of `list`.
+fn take_elements(list: &List, len: usize) -> List {
+  let nodes: Vec<_> = list.iter().take(len).collect();
+
+  nodes.into_iter().rev().fold(List::Nil, |list, node| {
+    List::cons_spanned(node.head.clone(), list, node.span)
+  })
+}
+
+fn map_elements<F>(list: &List, mut f: F) -> List
+where
+  F: FnMut(usize, Expr) -> Expr,
+{
+  let elements: Vec<_> = list
+    .iter()
+    .enumerate()
+    .map(|(index, node)| (f(index, node.head.clone()), node.span))
+    .collect();
+
+  elements
+    .into_iter()
+    .rev()
+    .fold(List::Nil, |list, (expr, span)| {
+      List::cons_spanned(expr, list, span)
+    })
+}
@@ -0,0 +1,470 @@
+use std::cell::RefCell;
+use std::mem;
+use std::rc::Rc;
+
+use crate::ast::{self, Atom, Expr, List, Special, Symbol};
+
+use super::bytecode::{self, Capture, Cell, Closure, Instruction};
+use super::{pattern, EvalError, Evaluator};
+
+/// A call to a closure which has not returned yet.
+struct CallFrame {
+  closure: Closure,
+  ip: usize,
+  locals: Vec<Local>,
+  /// The height of the stack when the call was made.
+  base: usize,
+}
+
+enum Local {
+  Value(Option<Expr>),
+  /// A local captured by a closure, which has to be shared with it.
+  Cell(Cell),
+}
+
+impl Local {
+  fn get(&self) -> Option<Expr> {
+    match self {
+      Local::Value(value) => value.clone(),
+      Local::Cell(cell) => cell.borrow().clone(),
+    }
+  }
+
+  fn set(&mut self, expr: Expr) {
+    match self {
+      Local::Value(value) => *value = Some(expr),
+      Local::Cell(cell) => *cell.borrow_mut() = Some(expr),
+    }
+  }
+
+  fn is_bound(&self) -> bool {
+    match self {
+      Local::Value(value) => value.is_some(),
+      Local::Cell(cell) => cell.borrow().is_some(),
+    }
+  }
+}
+
+struct Machine {
+  frame: CallFrame,
+  /// The calls waiting for the current call to return.
+  frames: Vec<CallFrame>,
+  stack: Vec<Expr>,
+}
+
+impl Machine {
+  /// Returns the value on top of the stack to the caller, or returns it from
+  /// the machine if there is no caller.
+  fn ret(&mut self) -> Option<Expr> {
+    let result = self.stack.pop().unwrap();
+    self.stack.truncate(self.frame.base);
+
+    match self.frames.pop() {
+      Some(caller) => {
+        self.frame = caller;
+        self.stack.push(result);
+        None
+      }
+      None => Some(result),
+    }
+  }
+
+  /// Pops the top `count` values off the stack, in the order they were
+  /// pushed.
+  fn pop(&mut self, count: usize) -> Vec<Expr> {
+    self.stack.split_off(self.stack.len() - count)
+  }
+}
+
+impl Evaluator {
+  /// Compiles `expr` to bytecode and runs it.
+  pub fn run(&mut self, expr: Expr) -> Result<Expr, EvalError> {
+    // The forms of a top-level `begin` are compiled one at a time, so that a
+    // macro defined by one form can be used by the forms after it.
+    if let Expr::List(List::Cons(node)) = &expr {
+      if let Expr::Atom(Atom::Special(Special::Begin)) = node.head {
+        let mut result = Err(EvalError::WrongArity);
+        for node in node.tail.iter() {
+          let expr = self
+            .run(node.head.clone())
+            .map_err(|error| error.with_span(node.span))?;
+          result = Ok(expr);
+        }
+        return result;
+      }
+    }
+
+    let prototype = self.compile(&expr)?;
+    let closure =
+      Closure::new(Rc::new(prototype), Vec::new(), self.frame.clone());
+    self.run_closure(closure, Vec::new())
+  }
+
+  /// Calls `closure` with already evaluated `arguments`.
+  pub fn run_closure(
+    &mut self,
+    closure: Closure,
+    arguments: Vec<Expr>,
+  ) -> Result<Expr, EvalError> {
+    let mut machine = Machine {
+      frame: enter_closure(closure, arguments, 0)?,
+      frames: Vec::new(),
+      stack: Vec::new(),
+    };
+
+    loop {
+      match self.step(&mut machine) {
+        Ok(Some(result)) => return Ok(result),
+        Ok(None) => {}
+        Err(error) => {
+          let frame = &machine.frame;
+          let span = frame.closure.prototype().spans[frame.ip - 1];
+          return Err(error.with_span(span));
+        }
+      }
+    }
+  }
+
+  /// Runs a single instruction, returning the result once the outermost call
+  /// returns.
+  fn step(&mut self, machine: &mut Machine) -> Result<Option<Expr>, EvalError> {
+    use EvalError::*;
+    use Instruction::*;
+
+    let prototype = machine.frame.closure.prototype().clone();
+    let instruction = prototype.code[machine.frame.ip];
+    machine.frame.ip += 1;
+
+    match instruction {
+      Constant(index) => machine.stack.push(prototype.constants[index].clone()),
+      Pop => {
+        machine.stack.pop();
+      }
+      Dup => {
+        let expr = machine.stack.last().unwrap().clone();
+        machine.stack.push(expr);
+      }
+      GetLocal(slot) => {
+        let expr = match machine.frame.locals[slot].get() {
+          Some(expr) => expr,
+          None => get_global(&machine.frame, prototype.locals[slot])?,
+        };
+        machine.stack.push(expr);
+      }
+      SetLocal(slot) => {
+        let expr = machine.stack.last().unwrap().clone();
+        machine.frame.locals[slot].set(expr);
+      }
+      GetUpvalue(index) => {
+        let expr = machine.frame.closure.upvalues()[index].borrow().clone();
+        let expr = match expr {
+          Some(expr) => expr,
+          None => get_global(&machine.frame, prototype.upvalues[index])?,
+        };
+        machine.stack.push(expr);
+      }
+      GetGlobal(index) => {
+        let expr = get_global(&machine.frame, prototype.symbols[index])?;
+        machine.stack.push(expr);
+      }
+      DefineLocal(slot) => {
+        if self.strict && machine.frame.locals[slot].is_bound() {
+          return Err(Redefinition(prototype.locals[slot]));
+        }
+        let expr = machine.stack.last().unwrap().clone();
+        machine.frame.locals[slot].set(expr);
+      }
+      DefineGlobal(index) => {
+        let symbol = prototype.symbols[index];
+        let expr = machine.stack.last().unwrap().clone();
+        let mut frame = machine.frame.closure.frame().clone();
+        if self.strict && frame.contains(&symbol) {
+          return Err(Redefinition(symbol));
+        }
+        frame.set(symbol, expr);
+      }
+      AssignLocal(slot) => {
+        if !machine.frame.locals[slot].is_bound() {
+          return Err(UndefinedSymbol(prototype.locals[slot]));
+        }
+        let expr = machine.stack.last().unwrap().clone();
+        machine.frame.locals[slot].set(expr);
+      }
+      AssignUpvalue(index) => {
+        let mut upvalue = machine.frame.closure.upvalues()[index].borrow_mut();
+        if upvalue.is_none() {
+          return Err(UndefinedSymbol(prototype.upvalues[index]));
+        }
+        *upvalue = Some(machine.stack.last().unwrap().clone());
+      }
+      AssignGlobal(index) => {
+        let symbol = prototype.symbols[index];
+        let expr = machine.stack.last().unwrap().clone();
+        let mut frame = machine.frame.closure.frame().clone();
+        if !frame.assign(symbol, expr) {
+          return Err(UndefinedSymbol(symbol));
+        }
+      }
+      Jump(target) => machine.frame.ip = target,
+      JumpIfFalse(target) => {
+        if !self.is_truthy(&machine.stack.pop().unwrap()) {
+          machine.frame.ip = target;
+        }
+      }
+      JumpIfFalseOrPop(target) => {
+        if self.is_truthy(machine.stack.last().unwrap()) {
+          machine.stack.pop();
+        } else {
+          machine.frame.ip = target;
+        }
+      }
+      JumpIfTrueOrPop(target) => {
+        if self.is_truthy(machine.stack.last().unwrap()) {
+          machine.frame.ip = target;
+        } else {
+          machine.stack.pop();
+        }
+      }
+      JumpIfBound(slot, target) => {
+        if machine.frame.locals[slot].is_bound() {
+          machine.frame.ip = target;
+        }
+      }
+      Call(count) => {
+        let arguments = machine.pop(count);
+        match machine.stack.pop().unwrap() {
+          Expr::Atom(Atom::Closure(closure)) => {
+            let base = machine.stack.len();
+            let callee = enter_closure(closure, arguments, base)?;
+            let caller = mem::replace(&mut machine.frame, callee);
+            machine.frames.push(caller);
+          }
+          callee => {
+            let result = self.apply_from(&machine.frame, callee, arguments)?;
+            machine.stack.push(result);
+          }
+        }
+      }
+      TailCall(count) => {
+        let arguments = machine.pop(count);
+        match machine.stack.pop().unwrap() {
+          Expr::Atom(Atom::Closure(closure)) => {
+            let base = machine.frame.base;
+            machine.frame = enter_closure(closure, arguments, base)?;
+            machine.stack.truncate(base);
+          }
+          callee => {
+            let result = self.apply_from(&machine.frame, callee, arguments)?;
+            machine.stack.push(result);
+            return Ok(machine.ret());
+          }
+        }
+      }
+      Return => return Ok(machine.ret()),
+      Closure(index) => {
+        let callee = &prototype.prototypes[index];
+        let frame = &mut machine.frame;
+        let upvalues = callee
+          .captures
+          .iter()
+          .map(|capture| match capture {
+            Capture::Local(slot) => match &frame.locals[*slot] {
+              Local::Cell(cell) => cell.clone(),
+              Local::Value(_) => unreachable!(),
+            },
+            Capture::Upvalue(index) => frame.closure.upvalues()[*index].clone(),
+          })
+          .collect();
+        let closure = bytecode::Closure::new(
+          callee.clone(),
+          upvalues,
+          frame.closure.frame().clone(),
+        );
+        machine.stack.push(Expr::Atom(Atom::Closure(closure)));
+      }
+      Macro(parameter, body) => {
+        let macr = ast::Macro::new(
+          prototype.symbols[parameter],
+          prototype.constants[body].clone(),
+        );
+        machine.stack.push(Expr::Atom(Atom::Macro(macr)));
+      }
+      Syntax(parameter, body) => {
+        let macr = ast::Macro::hygienic(
+          machine.frame.closure.frame().clone(),
+          prototype.symbols[parameter],
+          prototype.constants[body].clone(),
+        );
+        machine.stack.push(Expr::Atom(Atom::Macro(macr)));
+      }
+      Operator(operator, count) => {
+        let arguments = machine.pop(count);
+        let result = self.apply_operator(operator, arguments)?;
+        machine.stack.push(result);
+      }
+      List(count) => {
+        let exprs = machine.pop(count);
+        machine.stack.push(Expr::List(into_list(exprs)));
+      }
+      Append(count) => {
+        let mut exprs = Vec::new();
+        for list in machine.pop(count) {
+          exprs.extend(self.as_list(list)?);
+        }
+        machine.stack.push(Expr::List(into_list(exprs)));
+      }
+      Vector(count) => {
+        let exprs = machine.pop(count);
+        let vector = exprs.into_iter().collect();
+        machine.stack.push(Expr::Atom(Atom::Vector(vector)));
+      }
+      Map(count) => {
+        let mut exprs = machine.pop(count * 2).into_iter();
+        let mut entries = Vec::new();
+        while let (Some(key), Some(value)) = (exprs.next(), exprs.next()) {
+          entries.push((key, value));
+        }
+        let map = entries.into_iter().collect();
+        machine.stack.push(Expr::Atom(Atom::Map(map)));
+      }
+      MacroExpand => {
+        let expr = machine.stack.pop().unwrap();
+        let result = self.macroexpand(expr)?;
+        machine.stack.push(result);
+      }
+      MacroExpandOnce => {
+        let expr = machine.stack.pop().unwrap();
+        let result = self.macroexpand_1(expr)?;
+        machine.stack.push(result);
+      }
+      Load => {
+        let path = match machine.stack.pop().unwrap() {
+          Expr::Atom(Atom::String(path)) => path,
+          _ => return Err(InvalidType),
+        };
+        let result = self.load(&path)?;
+        machine.stack.push(result);
+      }
+      Import(path, alias) => {
+        if let Expr::Atom(Atom::String(path)) = &prototype.constants[path] {
+          let alias = &prototype.symbols[alias];
+          let mut frame = machine.frame.closure.frame().clone();
+          self.import(path, alias, &mut frame)?;
+        }
+        machine.stack.push(Expr::List(ast::List::Nil));
+      }
+      Export(names) => {
+        if let Expr::Atom(Atom::Vector(names)) = &prototype.constants[names] {
+          let names = names
+            .iter()
+            .map(|name| self.as_symbol(name.clone()))
+            .collect::<Result<_, _>>()?;
+          self.export(names);
+        }
+        machine.stack.push(Expr::List(ast::List::Nil));
+      }
+      Try => {
+        let mut clauses = machine.pop(3).into_iter();
+        let body = clauses.next().unwrap();
+        let catch = clauses.next().unwrap();
+        let finally = clauses.next().unwrap();
+
+        // Missing clauses are compiled to `()`, which isn't necessarily falsy.
+        let is_missing = |clause: &Expr| clause == &Expr::List(ast::List::Nil);
+        let result = match self.apply(body, Vec::new()) {
+          Err(error) if !is_missing(&catch) => {
+            self.apply(catch, vec![error.into_value()])
+          }
+          result => result,
+        };
+        if !is_missing(&finally) {
+          self.apply(finally, Vec::new())?;
+        }
+
+        machine.stack.push(result?);
+      }
+      Match(pattern, slot) => {
+        let value = machine.stack.pop().unwrap();
+        let mut bindings = Vec::new();
+        let pattern = &prototype.constants[pattern];
+        let is_match = pattern::matches(pattern, &value, &mut bindings)?;
+        if is_match {
+          for (offset, (_, expr)) in bindings.into_iter().enumerate() {
+            machine.frame.locals[slot + offset].set(expr);
+          }
+        }
+        machine.stack.push(Expr::Atom(Atom::Boolean(is_match)));
+      }
+      Instruction::NoMatch => {
+        let value = machine.stack.pop().unwrap();
+        return Err(EvalError::NoMatch(value));
+      }
+    }
+
+    Ok(None)
+  }
+
+  /// Calls a `callee` which isn't a closure from `caller`. Macros and special
+  /// forms called as values are expanded and evaluated in the frame of the
+  /// calling closure, so they see the same globals as the code calling them.
+  fn apply_from(
+    &mut self,
+    caller: &CallFrame,
+    callee: Expr,
+    arguments: Vec<Expr>,
+  ) -> Result<Expr, EvalError> {
+    let frame = caller.closure.frame().clone();
+    let original_frame = mem::replace(&mut self.frame, frame);
+    let result = self.apply(callee, arguments);
+    self.frame = original_frame;
+    result
+  }
+}
+
+/// Creates the call frame for a call to `closure`, binding its parameters to
+/// `arguments`. Parameters which weren't passed an argument are left unset,
+/// for the closure to evaluate their defaults.
+fn enter_closure(
+  closure: Closure,
+  arguments: Vec<Expr>,
+  base: usize,
+) -> Result<CallFrame, EvalError> {
+  let prototype = closure.prototype();
+  let arguments = prototype.parameters.bind(arguments)?;
+
+  let mut locals: Vec<Local> =
+    arguments.into_iter().map(Local::Value).collect();
+  locals.resize_with(prototype.locals.len(), || Local::Value(None));
+
+  for &slot in prototype.cells.iter() {
+    let value = match &mut locals[slot] {
+      Local::Value(value) => value.take(),
+      Local::Cell(_) => unreachable!(),
+    };
+    locals[slot] = Local::Cell(Rc::new(RefCell::new(value)));
+  }
+
+  Ok(CallFrame {
+    closure,
+    ip: 0,
+    locals,
+    base,
+  })
+}
+
+/// Looks `symbol` up in the global frame of the closure being run. Locals and
+/// upvalues which haven't been defined yet are looked up here too, so they
+/// still refer to whatever they mean outside the function.
+fn get_global(frame: &CallFrame, symbol: Symbol) -> Result<Expr, EvalError> {
+  match frame.closure.frame().get(&symbol) {
+    Some(expr) => Ok(expr),
+    None => Err(EvalError::UndefinedSymbol(symbol)),
+  }
+}
+
+fn into_list(exprs: Vec<Expr>) -> List {
+  exprs
+    .into_iter()
+    .rev()
+    .fold(List::Nil, |list, expr| List::cons(expr, list))
+}
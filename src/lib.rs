@@ -5,48 +5,89 @@ use rustyline::error::ReadlineError;
 use rustyline::Editor;
 use thiserror::Error;
 
-use crate::ast::Expr;
-use crate::eval::{EvalError, Evaluator};
+use crate::ast::{Expr, Span};
+use crate::diagnostic::Diagnostic;
+use crate::eval::{Backend, EvalError, Evaluator};
 use crate::read::ReadError;
 
 mod env;
 
 pub mod ast;
+pub mod convert;
+pub mod diagnostic;
 pub mod eval;
 pub mod read;
 
 pub fn run() -> Result<(), RunError> {
-  let args: Vec<String> = std::env::args().collect();
+  let args: Vec<String> = std::env::args().skip(1).collect();
 
-  if let Some(path) = args.get(1) {
-    run_file(path)
+  let backend = if args.iter().any(|arg| arg == "--bytecode") {
+    Backend::Bytecode
   } else {
-    run_repl()
+    Backend::Tree
+  };
+  let strict = args.iter().any(|arg| arg == "--strict");
+
+  if let Some(path) = args.iter().find(|arg| !arg.starts_with("--")) {
+    run_file(path, backend, strict)
+  } else {
+    run_repl(backend, strict)
   }
 }
 
-fn run_file(path: &str) -> Result<(), RunError> {
+fn run_file(
+  path: &str,
+  backend: Backend,
+  strict: bool,
+) -> Result<(), RunError> {
   let source = fs::read_to_string(path)?;
 
-  let expr = read::read(&source)?;
-  eval::eval(expr)?;
+  let mut evaluator = new_evaluator(backend, strict);
+  evaluator.set_path(path);
+
+  read_and_eval_file(&mut evaluator, &source)
+    .map_err(|error| error.locate(path, &source))?;
 
   Ok(())
 }
 
-fn run_repl() -> Result<(), RunError> {
+fn read_and_eval_file(
+  evaluator: &mut Evaluator,
+  source: &str,
+) -> Result<(), RunError> {
+  let expr = read::read(source)?;
+  evaluator.eval_expr(expr)?;
+  Ok(())
+}
+
+/// Creates an evaluator which also searches the directories listed in the
+/// `ZUKO_PATH` environment variable for files to import.
+fn new_evaluator(backend: Backend, strict: bool) -> Evaluator {
+  let mut evaluator = Evaluator::with_backend(backend);
+  evaluator.set_strict(strict);
+
+  if let Some(paths) = std::env::var_os("ZUKO_PATH") {
+    for path in std::env::split_paths(&paths) {
+      evaluator.add_search_path(path);
+    }
+  }
+
+  evaluator
+}
+
+fn run_repl(backend: Backend, strict: bool) -> Result<(), RunError> {
   println!("Zuko v1.0.0");
 
   let mut editor = Editor::<()>::new();
   editor.set_auto_add_history(true);
 
-  let mut evaluator = Evaluator::new();
+  let mut evaluator = new_evaluator(backend, strict);
 
   loop {
     match editor.readline("> ") {
       Ok(line) => match read_and_eval_line(&mut evaluator, &line) {
         Ok(expr) => println!("{}", expr),
-        Err(error) => println!("error: {}", error),
+        Err(error) => println!("error: {}", error.locate("<repl>", &line)),
       },
       Err(ReadlineError::Interrupted) => break,
       Err(ReadlineError::Eof) => break,
@@ -75,4 +116,31 @@ pub enum RunError {
   Read(#[from] ReadError),
   #[error("{0}")]
   Eval(#[from] EvalError),
+  #[error("{0}")]
+  Located(Diagnostic),
+}
+
+impl RunError {
+  pub fn span(&self) -> Option<Span> {
+    use RunError::*;
+
+    match self {
+      Io(_) => None,
+      Read(error) => Some(Span::from(error.position())),
+      Eval(error) => error.span(),
+      Located(diagnostic) => Some(diagnostic.span),
+    }
+  }
+
+  /// Turns the error into a diagnostic pointing into `source`, if the error
+  /// knows where it occurred.
+  pub fn locate(self, path: &str, source: &str) -> RunError {
+    match self.span() {
+      Some(span) => {
+        let message = self.to_string();
+        RunError::Located(Diagnostic::new(path, source, span, message))
+      }
+      None => self,
+    }
+  }
 }
@@ -2,7 +2,10 @@ use std::iter::{Iterator, Peekable};
 
 use thiserror::Error;
 
-use crate::ast::{self, Atom, Expr, List, Operator, Special, Symbol};
+use crate::ast::{
+  self, Atom, Expr, List, Map, Operator, Position, Span, Special, Symbol,
+  Vector,
+};
 
 pub fn read(source: &str) -> Result<Expr, ReadError> {
   use List::*;
@@ -12,7 +15,7 @@ pub fn read(source: &str) -> Result<Expr, ReadError> {
   let mut exprs = vec![];
 
   loop {
-    exprs.push(reader.read_expr()?);
+    exprs.push(reader.read_spanned_expr()?);
     if reader.is_empty() {
       break;
     }
@@ -20,8 +23,8 @@ pub fn read(source: &str) -> Result<Expr, ReadError> {
 
   exprs.reverse();
   let mut list = Nil;
-  for expr in exprs.into_iter() {
-    list = List::cons(expr, list);
+  for (expr, span) in exprs.into_iter() {
+    list = List::cons_spanned(expr, list, Some(span));
   }
 
   Ok(Expr::List(List::cons(
@@ -35,6 +38,7 @@ where
   I: Iterator<Item = char>,
 {
   source: Peekable<I>,
+  position: Position,
 }
 
 impl<I> Reader<I>
@@ -44,6 +48,7 @@ where
   pub fn new(source: I) -> Reader<I> {
     Reader {
       source: source.peekable(),
+      position: Position::start(),
     }
   }
 
@@ -51,63 +56,139 @@ where
     self.source.peek().is_none()
   }
 
+  pub fn position(&self) -> Position {
+    self.position
+  }
+
   pub fn read_expr(&mut self) -> Result<Expr, ReadError> {
+    let (expr, _) = self.read_spanned_expr()?;
+    Ok(expr)
+  }
+
+  pub fn read_spanned_expr(&mut self) -> Result<(Expr, Span), ReadError> {
     use Expr::*;
     use ReadError::*;
 
     self.skip_whitespace_or_comment();
 
+    let start = self.position;
     let expr = match self.source.peek() {
       Some('(') => List(self.read_list()?),
+      Some('\'') | Some('`') | Some(',') => List(self.read_quoted()?),
       Some(_) => Atom(self.read_atom()?),
-      None => return Err(UnexpectedEndOfInput),
+      None => return Err(UnexpectedEndOfInput(self.position)),
     };
+    let span = Span::new(start, self.position);
 
     self.skip_whitespace_or_comment();
 
-    Ok(expr)
+    Ok((expr, span))
   }
 
   pub fn read_list(&mut self) -> Result<List, ReadError> {
     use List::*;
+
+    let mut exprs = self.read_sequence('(', ')')?;
+
+    exprs.reverse();
+    let mut list = Nil;
+    for (expr, span) in exprs.into_iter() {
+      list = List::cons_spanned(expr, list, Some(span));
+    }
+
+    Ok(list)
+  }
+
+  /// Reads a vector literal like `[1 2 3]`.
+  pub fn read_vector(&mut self) -> Result<Vector, ReadError> {
+    let exprs = self.read_sequence('[', ']')?;
+
+    Ok(exprs.into_iter().map(|(expr, _)| expr).collect())
+  }
+
+  /// Reads a map literal like `{"a" 1 "b" 2}`, which alternates between keys
+  /// and values.
+  pub fn read_map(&mut self) -> Result<Map, ReadError> {
     use ReadError::*;
 
-    match self.source.peek() {
-      Some('(') => {}
-      Some(char) => return Err(UnexpectedChar(*char)),
-      None => return Err(UnexpectedEndOfInput),
+    let start = self.position;
+    let exprs = self.read_sequence('{', '}')?;
+
+    if exprs.len() % 2 != 0 {
+      return Err(MissingMapValue(start));
     }
-    self.source.next();
 
-    // Catch empty lists
-    self.skip_whitespace_or_comment();
-    if let Some(')') = self.source.peek() {
-      self.source.next();
-      return Ok(List::Nil);
+    let mut exprs = exprs.into_iter().map(|(expr, _)| expr);
+    let mut map = Map::new();
+    while let (Some(key), Some(value)) = (exprs.next(), exprs.next()) {
+      map = map.insert(key, value);
     }
 
-    let mut exprs = vec![self.read_expr()?];
+    Ok(map)
+  }
 
+  /// Reads the expressions between `open` and `close`.
+  fn read_sequence(
+    &mut self,
+    open: char,
+    close: char,
+  ) -> Result<Vec<(Expr, Span)>, ReadError> {
+    use ReadError::*;
+
+    match self.source.peek() {
+      Some(char) if *char == open => {}
+      Some(char) => return Err(UnexpectedChar(*char, self.position)),
+      None => return Err(UnexpectedEndOfInput(self.position)),
+    }
+    self.next();
+
+    let mut exprs = vec![];
+
+    self.skip_whitespace_or_comment();
     loop {
       match self.source.peek() {
-        Some(')') => {
-          self.source.next();
+        Some(char) if *char == close => {
+          self.next();
           break;
         }
-        None => return Err(UnexpectedEndOfInput),
+        None => return Err(UnexpectedEndOfInput(self.position)),
         _ => {}
       }
 
-      exprs.push(self.read_expr()?);
+      exprs.push(self.read_spanned_expr()?);
     }
 
-    exprs.reverse();
-    let mut list = Nil;
-    for expr in exprs.into_iter() {
-      list = List::cons(expr, list);
-    }
+    Ok(exprs)
+  }
 
-    Ok(list)
+  /// Reads `'x`, `` `x ``, `,x` and `,@x` as `(quote x)`, `(quasiquote x)`,
+  /// `(unquote x)` and `(unquote-splicing x)` respectively.
+  pub fn read_quoted(&mut self) -> Result<List, ReadError> {
+    use ReadError::*;
+    use Special::*;
+
+    let start = self.position;
+
+    let special = match self.next() {
+      Some('\'') => Quote,
+      Some('`') => Quasiquote,
+      Some(',') if self.source.peek() == Some(&'@') => {
+        self.next();
+        UnquoteSplicing
+      }
+      Some(',') => Unquote,
+      Some(char) => return Err(UnexpectedChar(char, start)),
+      None => return Err(UnexpectedEndOfInput(start)),
+    };
+    let special_span = Span::new(start, self.position);
+
+    let (expr, span) = self.read_spanned_expr()?;
+
+    Ok(List::cons_spanned(
+      Expr::Atom(Atom::Special(special)),
+      List::cons_spanned(expr, List::Nil, Some(span)),
+      Some(special_span),
+    ))
   }
 
   pub fn read_atom(&mut self) -> Result<Atom, ReadError> {
@@ -117,42 +198,86 @@ where
 
     let atom = match self.source.peek() {
       Some('"') => String(self.read_string()?),
-      Some(char) if char.is_digit(10) => Number(self.read_number()?),
+      Some('[') => Vector(self.read_vector()?),
+      Some('{') => Map(self.read_map()?),
+      Some(char) if is_number_start(*char) => self.read_number()?,
+      Some('+') | Some('-') => self.read_signed_number_or_operator()?,
+      Some(':') => Keyword(self.read_keyword()?),
+      Some('&') => {
+        self.next();
+        Symbol(ast::Symbol::new("&"))
+      }
+      Some('_') => {
+        self.next();
+        Symbol(ast::Symbol::new("_"))
+      }
       Some(char) if is_operator(*char) => {
         Special(Operator(self.read_operator()?))
       }
       Some(char) if is_symbol(*char) => self.read_symbol_or_special()?,
-      Some(char) => return Err(UnexpectedChar(*char)),
-      None => return Err(UnexpectedEndOfInput),
+      Some(char) => return Err(UnexpectedChar(*char, self.position)),
+      None => return Err(UnexpectedEndOfInput(self.position)),
     };
 
     Ok(atom)
   }
 
-  pub fn read_number(&mut self) -> Result<f64, ReadError> {
+  pub fn read_number(&mut self) -> Result<Atom, ReadError> {
     use ReadError::*;
 
-    let mut buf = Vec::new();
-    let mut has_decimal = false;
+    let start = self.position;
+    let token = self.read_token();
+
+    // A lone `.` separates the rest of a list in `match` patterns.
+    if token == "." {
+      return Ok(Atom::Symbol(ast::Symbol::new(".")));
+    }
+
+    parse_number(&token).ok_or(InvalidNumber(token, start))
+  }
+
+  /// Reads either a signed number like `-5` or the `+` or `-` operator,
+  /// depending on whether a number immediately follows the sign.
+  pub fn read_signed_number_or_operator(&mut self) -> Result<Atom, ReadError> {
+    use ast::Operator::*;
+    use Atom::*;
+    use ReadError::*;
+
+    let start = self.position;
+
+    let sign = match self.next() {
+      Some(sign) => sign,
+      None => return Err(UnexpectedEndOfInput(self.position)),
+    };
+    let operator = match sign {
+      '+' => Add,
+      '-' => Sub,
+      char => return Err(UnexpectedChar(char, start)),
+    };
+
+    match self.source.peek() {
+      Some(char) if is_number_start(*char) || is_symbol(*char) => {
+        let token = format!("{}{}", sign, self.read_token());
+        parse_number(&token).ok_or(InvalidNumber(token, start))
+      }
+      _ => Ok(Special(ast::Special::Operator(operator))),
+    }
+  }
+
+  /// Reads characters up until the next delimiter.
+  fn read_token(&mut self) -> String {
+    let mut buf = String::new();
 
     loop {
       match self.source.peek() {
-        Some('.') if !has_decimal => has_decimal = true,
-        Some(char) if char.is_digit(10) => {}
-        Some(')') => break,
-        Some(char) if char.is_whitespace() => break,
-        Some(char) => return Err(UnexpectedChar(*char)),
+        Some(char) if is_delimiter(*char) => break,
+        Some(_) => {}
         None => break,
       }
-      let char = self.source.next().unwrap();
-
-      buf.push(char);
+      buf.push(self.next().unwrap());
     }
 
-    let buf: String = buf.into_iter().collect();
-    let number = buf.parse().unwrap();
-
-    Ok(number)
+    buf
   }
 
   pub fn read_symbol_or_special(&mut self) -> Result<Atom, ReadError> {
@@ -160,13 +285,39 @@ where
 
     let symbol = self.read_symbol()?;
 
-    let special = match symbol.as_str() {
+    let special = match &*symbol.name() {
       "begin" => Begin,
       "define" => Define,
       "function" => Function,
       "macro" => Macro,
+      "syntax" => Syntax,
       "if" => If,
       "quote" => Quote,
+      "quasiquote" => Quasiquote,
+      "unquote" => Unquote,
+      "unquote-splicing" => UnquoteSplicing,
+      "macroexpand" => MacroExpand,
+      "macroexpand-1" => MacroExpandOnce,
+      "load" => Load,
+      "import" => Import,
+      "export" => Export,
+      "try" => Try,
+      "let" => Let,
+      "let*" => LetStar,
+      "letrec" => Letrec,
+      "set!" => Set,
+      "and" => And,
+      "or" => Or,
+      "bit-and" => Operator(ast::Operator::BitAnd),
+      "bit-or" => Operator(ast::Operator::BitOr),
+      "bit-xor" => Operator(ast::Operator::BitXor),
+      "bit-not" => Operator(ast::Operator::BitNot),
+      "shift-left" => Operator(ast::Operator::Shl),
+      "shift-right" => Operator(ast::Operator::Shr),
+      "true" => return Ok(Atom::Boolean(true)),
+      "false" => return Ok(Atom::Boolean(false)),
+      "inf" => return Ok(Atom::Float(f64::INFINITY)),
+      "nan" => return Ok(Atom::Float(f64::NAN)),
       _ => return Ok(Atom::Symbol(symbol)),
     };
 
@@ -179,29 +330,40 @@ where
     let mut buf = Vec::new();
     let mut should_break = false;
     let mut prev_punct_dist = 0;
+    let mut last_position = self.position;
 
     loop {
       match self.source.peek() {
-        Some(')') => break,
+        Some(')') | Some(']') | Some('}') => break,
         Some(char) if char.is_whitespace() => break,
-        Some(char) if should_break => return Err(UnexpectedChar(*char)),
+        Some(char) if should_break => {
+          return Err(UnexpectedChar(*char, self.position))
+        }
         Some(char) if char.is_alphabetic() && char.is_lowercase() => {}
+        Some(char) if char.is_ascii_digit() => {}
         Some('-') | Some('/') if prev_punct_dist > 0 => {
           prev_punct_dist = -1;
         }
-        Some('?') => should_break = true,
-        Some(char) => return Err(UnexpectedChar(*char)),
+        // Arrows like `string->number` read as a single separator.
+        Some('>') if buf.last() == Some(&'-') => {
+          prev_punct_dist = -1;
+        }
+        // Only the last character can be `?`, `*` or `!`, as in `list?`,
+        // `let*` and `set!`.
+        Some('?') | Some('*') | Some('!') => should_break = true,
+        Some(char) => return Err(UnexpectedChar(*char, self.position)),
         None => break,
       }
-      let char = self.source.next().unwrap();
+      last_position = self.position;
+      let char = self.next().unwrap();
 
       buf.push(char);
       prev_punct_dist += 1;
     }
 
     let last_char = buf.last().cloned();
-    if let Some('-') | Some('/') = last_char {
-      return Err(UnexpectedChar(last_char.unwrap()));
+    if let Some('-') | Some('/') | Some('>') = last_char {
+      return Err(UnexpectedChar(last_char.unwrap(), last_position));
     }
 
     let buf: String = buf.into_iter().collect();
@@ -209,6 +371,23 @@ where
     Ok(Symbol::new(buf))
   }
 
+  pub fn read_keyword(&mut self) -> Result<Symbol, ReadError> {
+    use ReadError::*;
+
+    match self.source.peek() {
+      Some(':') => {}
+      Some(char) => return Err(UnexpectedChar(*char, self.position)),
+      None => return Err(UnexpectedEndOfInput(self.position)),
+    }
+    self.next();
+
+    match self.source.peek() {
+      Some(char) if is_symbol(*char) => self.read_symbol(),
+      Some(char) => Err(UnexpectedChar(*char, self.position)),
+      None => Err(UnexpectedEndOfInput(self.position)),
+    }
+  }
+
   pub fn read_operator(&mut self) -> Result<Operator, ReadError> {
     use Operator::*;
     use ReadError::*;
@@ -222,33 +401,62 @@ where
       Some('>') => Gt,
       Some('<') => Lt,
       Some('=') => Eq,
-      Some(char) => return Err(UnexpectedChar(*char)),
-      None => return Err(UnexpectedEndOfInput),
+      Some('!') => Ne,
+      Some(char) => return Err(UnexpectedChar(*char, self.position)),
+      None => return Err(UnexpectedEndOfInput(self.position)),
     };
-    self.source.next();
+    self.next();
+
+    // Catch two character operators.
+    let operator = match (operator, self.source.peek()) {
+      (Gt, Some('=')) => Ge,
+      (Lt, Some('=')) => Le,
+      (Div, Some('/')) => IntDiv,
+      (Ne, Some('=')) => Ne,
+      (Ne, Some(char)) => return Err(UnexpectedChar(*char, self.position)),
+      (Ne, None) => return Err(UnexpectedEndOfInput(self.position)),
+      (operator, _) => return Ok(operator),
+    };
+    self.next();
 
     Ok(operator)
   }
 
+  /// Reads a string literal, which can contain the escapes `\n`, `\t`, `\r`,
+  /// `\\`, `\"` and `\u{...}`, or a raw string literal wrapped in `"""`.
   pub fn read_string(&mut self) -> Result<String, ReadError> {
     use ReadError::*;
 
     match self.source.peek() {
       Some('"') => {}
-      Some(char) => return Err(UnexpectedChar(*char)),
-      None => return Err(UnexpectedEndOfInput),
+      Some(char) => return Err(UnexpectedChar(*char, self.position)),
+      None => return Err(UnexpectedEndOfInput(self.position)),
+    }
+    self.next();
+
+    // Two quotes are either an empty string, or the start of a raw string.
+    if let Some('"') = self.source.peek() {
+      self.next();
+      if let Some('"') = self.source.peek() {
+        self.next();
+        return self.read_raw_string();
+      }
+      return Ok(String::new());
     }
-    self.source.next();
 
     let mut buf = Vec::new();
 
     loop {
       match self.source.peek() {
         Some('"') => break,
+        Some('\\') => {
+          buf.push(self.read_escape()?);
+          continue;
+        }
         Some(_) => {}
-        None => return Err(UnexpectedEndOfInput),
+        None => return Err(UnexpectedEndOfInput(self.position)),
       }
-      let char = self.source.next().unwrap();
+      let char = self.next().unwrap();
 
       buf.push(char);
     }
@@ -256,20 +464,108 @@ where
     let buf: String = buf.into_iter().collect();
 
     // Get rid of final quote.
-    self.source.next();
+    self.next();
 
     Ok(buf)
   }
 
+  /// Reads the rest of a raw string after its opening `"""`, up to the next
+  /// `"""`. Its contents are kept as they are, except for a newline right
+  /// after the opening quotes, so that the string can start on its own line.
+  fn read_raw_string(&mut self) -> Result<String, ReadError> {
+    use ReadError::*;
+
+    if let Some('\n') = self.source.peek() {
+      self.next();
+    }
+
+    let mut buf = Vec::new();
+    let mut quotes = 0;
+
+    while quotes < 3 {
+      let char = match self.next() {
+        Some(char) => char,
+        None => return Err(UnexpectedEndOfInput(self.position)),
+      };
+
+      if char == '"' {
+        quotes += 1;
+      } else {
+        buf.extend(std::iter::repeat_n('"', quotes));
+        buf.push(char);
+        quotes = 0;
+      }
+    }
+
+    Ok(buf.into_iter().collect())
+  }
+
+  /// Reads an escape sequence within a string, like `\n` or `\u{1f600}`.
+  fn read_escape(&mut self) -> Result<char, ReadError> {
+    use ReadError::*;
+
+    let start = self.position;
+    self.next();
+
+    let char = match self.next() {
+      Some('n') => '\n',
+      Some('t') => '\t',
+      Some('r') => '\r',
+      Some('\\') => '\\',
+      Some('"') => '"',
+      Some('u') => return self.read_unicode_escape(start),
+      Some(char) => return Err(InvalidEscape(format!("\\{}", char), start)),
+      None => return Err(UnexpectedEndOfInput(self.position)),
+    };
+
+    Ok(char)
+  }
+
+  /// Reads the `{...}` of a `\u{...}` escape, which holds the code point of
+  /// the character in hexadecimal.
+  fn read_unicode_escape(
+    &mut self,
+    start: Position,
+  ) -> Result<char, ReadError> {
+    use ReadError::*;
+
+    let mut escape = vec!['\\', 'u'];
+    loop {
+      match self.next() {
+        Some('}') => {
+          escape.push('}');
+          break;
+        }
+        Some('"') | None => {
+          let escape = escape.into_iter().collect();
+          return Err(InvalidEscape(escape, start));
+        }
+        Some(char) => escape.push(char),
+      }
+    }
+
+    let escape: String = escape.into_iter().collect();
+    escape
+      .strip_prefix("\\u{")
+      .and_then(|digits| digits.strip_suffix('}'))
+      .filter(|digits| !digits.is_empty() && digits.len() <= 6)
+      .and_then(|digits| u32::from_str_radix(digits, 16).ok())
+      .and_then(std::char::from_u32)
+      .ok_or(InvalidEscape(escape, start))
+  }
+
   pub fn skip_whitespace_or_comment(&mut self) {
     loop {
       match self.source.peek() {
-        Some(';') => self.skip_comment(),
+        Some(';') => {
+          self.skip_comment();
+          continue;
+        }
         Some(char) if !char.is_whitespace() => break,
         None => break,
         _ => {}
       }
-      self.source.next();
+      self.next();
     }
   }
 
@@ -279,7 +575,7 @@ where
       Some(_) => return,
       None => return,
     };
-    self.source.next();
+    self.next();
 
     loop {
       match self.source.peek() {
@@ -287,29 +583,119 @@ where
         Some(_) => {}
         None => return,
       }
-      self.source.next();
+      self.next();
     }
 
     // Get rid of final newline.
-    self.source.next();
+    self.next();
+  }
+
+  fn next(&mut self) -> Option<char> {
+    let char = self.source.next()?;
+    self.position.advance(char);
+    Some(char)
   }
 }
 
 #[derive(Debug, Error)]
 pub enum ReadError {
   #[error("unexpected end of input")]
-  UnexpectedEndOfInput,
+  UnexpectedEndOfInput(Position),
   #[error("unexpected char '{0}'")]
-  UnexpectedChar(char),
+  UnexpectedChar(char, Position),
+  #[error("invalid number '{0}'")]
+  InvalidNumber(String, Position),
+  #[error("map literal is missing a value")]
+  MissingMapValue(Position),
+  #[error("invalid escape '{0}'")]
+  InvalidEscape(String, Position),
+}
+
+impl ReadError {
+  pub fn position(&self) -> Position {
+    use ReadError::*;
+
+    match self {
+      UnexpectedEndOfInput(position) => *position,
+      UnexpectedChar(_, position) => *position,
+      InvalidNumber(_, position) => *position,
+      MissingMapValue(position) => *position,
+      InvalidEscape(_, position) => *position,
+    }
+  }
 }
 
 fn is_symbol(char: char) -> bool {
   char.is_alphabetic() && char.is_lowercase()
 }
 
+fn is_number_start(char: char) -> bool {
+  char.is_ascii_digit() || char == '.'
+}
+
+fn is_delimiter(char: char) -> bool {
+  char.is_whitespace()
+    || matches!(
+      char,
+      '(' | ')' | '[' | ']' | '{' | '}' | '"' | ';' | '\'' | '`' | ','
+    )
+}
+
 fn is_operator(char: char) -> bool {
-  match char {
-    '+' | '-' | '*' | '/' | '%' | '>' | '<' | '=' => true,
-    _ => false,
+  matches!(char, '+' | '-' | '*' | '/' | '%' | '>' | '<' | '=' | '!')
+}
+
+/// Parses a number literal, which may be signed and be written in decimal
+/// (with an optional exponent), hexadecimal (`0x`), binary (`0b`), or be one
+/// of `inf` and `nan`. Literals without a decimal point or exponent are
+/// integers, and are invalid if they don't fit in 64 bits. Other literals are
+/// invalid if they are too large for a float.
+fn parse_number(token: &str) -> Option<Atom> {
+  let (sign, unsigned) = match token.chars().next() {
+    Some('-') => ("-", &token[1..]),
+    Some('+') => ("", &token[1..]),
+    _ => ("", token),
+  };
+
+  if let Some(digits) = unsigned.strip_prefix("0x") {
+    return parse_integer(sign, digits, 16);
+  } else if let Some(digits) = unsigned.strip_prefix("0b") {
+    return parse_integer(sign, digits, 2);
+  } else if unsigned.chars().all(|char| char.is_ascii_digit()) {
+    return parse_integer(sign, unsigned, 10);
+  }
+
+  let magnitude: f64 = if unsigned == "inf" {
+    f64::INFINITY
+  } else if unsigned == "nan" {
+    f64::NAN
+  } else {
+    // Rust accepts spellings like "infinity" and "NaN", so only let through
+    // characters that can actually appear in a decimal literal.
+    let is_decimal = unsigned.starts_with(is_number_start)
+      && unsigned.contains(|char: char| char.is_ascii_digit())
+      && unsigned
+        .chars()
+        .all(|char| char.is_ascii_digit() || ".eE+-".contains(char));
+    if !is_decimal {
+      return None;
+    }
+    let magnitude: f64 = unsigned.parse().ok()?;
+    if magnitude.is_infinite() {
+      return None;
+    }
+    magnitude
+  };
+
+  let number = if sign == "-" { -magnitude } else { magnitude };
+  Some(Atom::Float(number))
+}
+
+fn parse_integer(sign: &str, digits: &str, radix: u32) -> Option<Atom> {
+  if digits.is_empty() || !digits.chars().all(|char| char.is_digit(radix)) {
+    return None;
   }
+  i64::from_str_radix(&format!("{}{}", sign, digits), radix)
+    .ok()
+    .map(Atom::Integer)
 }
@@ -10,7 +10,7 @@ pub fn fibonacci() {
   let read_expr = read::read(&source).unwrap();
   let eval_expr = eval::eval(read_expr).unwrap();
 
-  assert_eq!(eval_expr, Expr::Atom(Atom::Number(6765.0)))
+  assert_eq!(eval_expr, Expr::Atom(Atom::Integer(6765)))
 }
 
 #[test]
@@ -30,5 +30,789 @@ pub fn square_root() {
   let read_expr = read::read(&source).unwrap();
   let eval_expr = eval::eval(read_expr).unwrap();
 
-  assert_eq!(eval_expr, Expr::Atom(Atom::Number(2.0000000929222947)))
+  assert_eq!(eval_expr, Expr::Atom(Atom::Float(2.0000000929222947)))
+}
+
+#[test]
+pub fn error_span() {
+  let source = fs::read_to_string("tests/error-span.zuko").unwrap();
+
+  let read_expr = read::read(&source).unwrap();
+  let error = eval::eval(read_expr).unwrap_err();
+  let span = error.span().unwrap();
+
+  assert_eq!((span.start.line, span.start.column), (3, 19))
+}
+
+#[test]
+pub fn error_span_backends() {
+  use zuko::eval::{Backend, Evaluator};
+
+  for (source, expected) in [
+    ("(define (f) (begin (print 1) missing))\n(f)", (1, 30)),
+    ("(define (f x) (+ x \"one\"))\n(f 1)", (1, 15)),
+    (
+      "(define (f n) (if (= n 0) (+ n \"a\") (f (- n 1))))\n(f 3)",
+      (1, 27),
+    ),
+    ("(if missing 1 2)", (1, 5)),
+    ("(list 1 (+ 1 \"a\"))", (1, 9)),
+    ("(let ((x 1)) (+ x \"a\"))", (1, 14)),
+    ("(let* ((a 1) (b 2)) (+ a \"x\"))", (1, 21)),
+    ("(letrec ((a 1)) (+ a \"x\"))", (1, 17)),
+    ("(let loop ((i 0)) (+ i \"x\"))", (1, 19)),
+    ("(let ((x missing)) x)", (1, 1)),
+    ("(letrec ((a missing)) a)", (1, 1)),
+    ("(cond ((= 1 2) 1) (missing 2))", (1, 19)),
+    ("(when 1 missing)", (1, 9)),
+    ("(define x missing)", (1, 11)),
+    ("(set! x missing)", (1, 9)),
+    ("(match 1 (x (+ x \"a\")))", (1, 13)),
+  ] {
+    for backend in [Backend::Tree, Backend::Bytecode] {
+      let read_expr = read::read(source).unwrap();
+      let error = Evaluator::with_backend(backend)
+        .eval_expr(read_expr)
+        .unwrap_err();
+      let span = error.span().unwrap();
+      assert_eq!(
+        (span.start.line, span.start.column),
+        expected,
+        "{:?}: {}",
+        backend,
+        source
+      );
+    }
+  }
+}
+
+#[test]
+pub fn tail_calls() {
+  use std::thread;
+  use zuko::eval::{Backend, Evaluator};
+
+  for backend in [Backend::Tree, Backend::Bytecode] {
+    let source = fs::read_to_string("tests/tail-calls.zuko").unwrap();
+
+    // The loops would overflow such a small stack if tail calls took up
+    // stack space.
+    let eval_expr = thread::Builder::new()
+      .stack_size(256 * 1024)
+      .spawn(move || {
+        let read_expr = read::read(&source).unwrap();
+        let mut evaluator = Evaluator::with_backend(backend);
+        evaluator.eval_expr(read_expr).unwrap().to_string()
+      })
+      .unwrap()
+      .join()
+      .unwrap();
+
+    assert_eq!(eval_expr, "(\"done\" 100000 4999950000)");
+  }
+}
+
+#[test]
+pub fn numbers() {
+  let source = fs::read_to_string("tests/numbers.zuko").unwrap();
+
+  let read_expr = read::read(&source).unwrap();
+  let eval_expr = eval::eval(read_expr).unwrap();
+
+  assert_eq!(eval_expr.to_string(), "(8.25 () ())")
+}
+
+#[test]
+pub fn malformed_numbers() {
+  for source in &["1.2.3", "1e", "0x", "0b12", "-x", "1e400", "-1e400"] {
+    assert!(read::read(source).is_err());
+  }
+}
+
+#[test]
+pub fn integers() {
+  let source = fs::read_to_string("tests/integers.zuko").unwrap();
+
+  let read_expr = read::read(&source).unwrap();
+  let eval_expr = eval::eval(read_expr).unwrap();
+
+  assert_eq!(
+    eval_expr.to_string(),
+    "(9007199254740993 10.0 1.0 3.5 3 -4 1 -1 3.0 true true true true 2 3.0 \
+     8 14 6 -1 1024 -4 :integer-overflow :division-by-zero inf)"
+  );
+}
+
+#[test]
+pub fn integer_errors() {
+  for source in &[
+    "(/ 1 0)",
+    "(% 5 0)",
+    "(- -9223372036854775807 2)",
+    "(* 4611686018427387904 2)",
+    "(// -9223372036854775808 -1)",
+    "(shift-left 1 64)",
+    "(bit-and 1 2.0)",
+    "(integer nan)",
+    "(substring \"abc\" 1.0)",
+  ] {
+    assert!(
+      eval::eval(read::read(source).unwrap()).is_err(),
+      "{}",
+      source
+    );
+  }
+
+  for source in &["9223372036854775808", "-0x8000000000000001"] {
+    assert!(read::read(source).is_err());
+  }
+
+  use zuko::read::Reader;
+
+  for source in &["1", "1.0", "-0.5", "1e300", "-9223372036854775808"] {
+    let expr = Reader::new(source.chars()).read_expr().unwrap();
+    assert_eq!(expr.to_string(), *source);
+  }
+}
+
+#[test]
+pub fn variadic_operators() {
+  let source = fs::read_to_string("tests/variadic-operators.zuko").unwrap();
+
+  let read_expr = read::read(&source).unwrap();
+  let eval_expr = eval::eval(read_expr).unwrap();
+
+  assert_eq!(eval_expr, Expr::Atom(Atom::Float(69.25)))
+}
+
+#[test]
+pub fn parameters() {
+  let source = fs::read_to_string("tests/parameters.zuko").unwrap();
+
+  let read_expr = read::read(&source).unwrap();
+  let eval_expr = eval::eval(read_expr).unwrap();
+
+  assert_eq!(eval_expr, Expr::Atom(Atom::Integer(131)))
+}
+
+#[test]
+pub fn wrong_argument_count() {
+  let source = "((function (a (b 1)) a) 1 2 3)";
+
+  let read_expr = read::read(source).unwrap();
+  let error = eval::eval(read_expr).unwrap_err();
+
+  assert_eq!(
+    error.to_string(),
+    "expected 1 to 2 arguments but received 3"
+  )
+}
+
+#[test]
+pub fn hygiene() {
+  let source = fs::read_to_string("tests/hygiene.zuko").unwrap();
+
+  let read_expr = read::read(&source).unwrap();
+  let eval_expr = eval::eval(read_expr).unwrap();
+
+  assert_eq!(eval_expr.to_string(), "(3102 (1 2) 3)")
+}
+
+#[test]
+pub fn macroexpand() {
+  let source = fs::read_to_string("tests/macroexpand.zuko").unwrap();
+
+  let read_expr = read::read(&source).unwrap();
+  let eval_expr = eval::eval(read_expr).unwrap();
+
+  assert_eq!(
+    eval_expr.to_string(),
+    "((unless (< n 0) a b) (if (< n 0) b a) (abs n) 3 2)"
+  )
+}
+
+#[test]
+pub fn quasiquote() {
+  let source = fs::read_to_string("tests/quasiquote.zuko").unwrap();
+
+  let read_expr = read::read(&source).unwrap();
+  let eval_expr = eval::eval(read_expr).unwrap();
+
+  assert_eq!(
+    eval_expr.to_string(),
+    "((x 1 2 3 (+ 1 2 3) \"uno\") \
+     (1 (quasiquote (2 (unquote (3 1))))) \
+     (unquote x) \
+     9)"
+  )
+}
+
+#[test]
+pub fn embedding() {
+  use std::cell::RefCell;
+  use std::rc::Rc;
+
+  use zuko::eval::Evaluator;
+
+  let mut evaluator = Evaluator::new();
+
+  let log = Rc::new(RefCell::new(Vec::new()));
+  let host_log = log.clone();
+  evaluator.register_fn("log", move |(message,): (String,)| {
+    host_log.borrow_mut().push(message);
+    Ok(())
+  });
+  evaluator
+    .register_fn("hypot", |(a, b): (f64, f64)| Ok((a * a + b * b).sqrt()));
+  evaluator.define("scale", 2);
+
+  let source = "(define scaled-hypot
+                        (function (a b)
+                                  (begin (log \"called\")
+                                         (* scale (hypot a b)))))";
+  evaluator.eval_expr(read::read(source).unwrap()).unwrap();
+
+  let result: f64 = evaluator.call("scaled-hypot", (3, 4)).unwrap();
+  let abs: Expr = evaluator.get("abs").unwrap();
+  let absolutes: Vec<i64> =
+    evaluator.call("map", (vec![1, -2, 3], abs)).unwrap();
+
+  assert_eq!(result, 10.0);
+  assert_eq!(absolutes, vec![1, 2, 3]);
+  assert_eq!(*log.borrow(), vec!["called".to_string()]);
+}
+
+#[test]
+pub fn collections() {
+  let source = fs::read_to_string("tests/collections.zuko").unwrap();
+
+  let read_expr = read::read(&source).unwrap();
+  let eval_expr = eval::eval(read_expr).unwrap();
+
+  assert_eq!(
+    eval_expr.to_string(),
+    "([1 2 :three \"four\"] 1000 999 1000 500 :missing :index-out-of-bounds \
+     :invalid-type 31 28 27 1 {\"bob\" 28} 500 996004 () \"vector\" 58 \"value\" \
+     (1 2 3) [1 2 3] true true)"
+  );
+
+  let source = "(get [1 2] 2)";
+  let error = eval::eval(read::read(source).unwrap()).unwrap_err();
+
+  assert_eq!(error.to_string(), "index 2 is out of bounds for length 2");
+}
+
+#[test]
+pub fn collection_literals() {
+  use zuko::read::Reader;
+
+  let source = "[1 [2 3] {:a [4]} \"five\"]";
+
+  let read_expr = Reader::new(source.chars()).read_expr().unwrap();
+  let printed = read_expr.to_string();
+
+  assert_eq!(printed, source);
+  assert_eq!(Reader::new(printed.chars()).read_expr().unwrap(), read_expr);
+}
+
+#[test]
+pub fn equality() {
+  let source = fs::read_to_string("tests/equality.zuko").unwrap();
+
+  let read_expr = read::read(&source).unwrap();
+  let eval_expr = eval::eval(read_expr).unwrap();
+
+  assert_eq!(
+    eval_expr.to_string(),
+    "(true true false true true true true false true false true true true \"list\")"
+  );
+}
+
+// Functions are hashed by identity, so their frames being mutable is fine.
+#[allow(clippy::mutable_key_type)]
+#[test]
+pub fn hashing() {
+  use std::collections::HashMap;
+
+  use zuko::read::Reader;
+
+  let mut counts = HashMap::new();
+  for source in &["(1 (2 3))", "[1 2]", "(1 (2 3))", "-0", "0", "[1 2]"] {
+    let expr = Reader::new(source.chars()).read_expr().unwrap();
+    *counts.entry(expr).or_insert(0) += 1;
+  }
+
+  let count = |source: &str| {
+    let expr = Reader::new(source.chars()).read_expr().unwrap();
+    counts.get(&expr).cloned()
+  };
+
+  assert_eq!(counts.len(), 3);
+  assert_eq!(count("(1 (2 3))"), Some(2));
+  assert_eq!(count("[1 2]"), Some(2));
+  assert_eq!(count("0"), Some(2));
+}
+
+#[test]
+pub fn bytecode() {
+  use zuko::eval::{Backend, Evaluator};
+
+  let eval_with = |backend, path: &str| {
+    let source = fs::read_to_string(path).unwrap();
+    let read_expr = read::read(&source).unwrap();
+    Evaluator::with_backend(backend).eval_expr(read_expr)
+  };
+
+  for path in &[
+    "tests/fibonacci.zuko",
+    "tests/fizz-buzz.zuko",
+    "tests/square-root.zuko",
+    "tests/tail-calls.zuko",
+    "tests/numbers.zuko",
+    "tests/integers.zuko",
+    "tests/variadic-operators.zuko",
+    "tests/parameters.zuko",
+    "tests/hygiene.zuko",
+    "tests/macroexpand.zuko",
+    "tests/quasiquote.zuko",
+    "tests/collections.zuko",
+    "tests/equality.zuko",
+    "tests/strings.zuko",
+    "tests/errors.zuko",
+    "tests/let.zuko",
+    "tests/set.zuko",
+    "tests/conditionals.zuko",
+    "tests/booleans.zuko",
+    "tests/match.zuko",
+    "tests/macro-values.zuko",
+  ] {
+    let tree = eval_with(Backend::Tree, path).unwrap();
+    let bytecode = eval_with(Backend::Bytecode, path).unwrap();
+    assert_eq!(bytecode.to_string(), tree.to_string(), "{}", path);
+  }
+
+  // Locals used before they are defined refer to what the symbol means
+  // outside the function.
+  for source in &[
+    "(define (f) (begin (define a inner) (define inner 3) (list a inner)))
+     (define inner 9)
+     (f)",
+    "(define (f)
+       (begin (define x 1)
+              (define (g) (begin (define a x) (define x 2) (list a x)))
+              (g)))
+     (f)",
+    "(define (f) (begin (define (g) y) (define a (g)) (define y 5) (list a (g))))
+     (define y 4)
+     (f)",
+  ] {
+    let eval_source = |backend| {
+      let read_expr = read::read(source).unwrap();
+      Evaluator::with_backend(backend).eval_expr(read_expr).unwrap()
+    };
+    let tree = eval_source(Backend::Tree);
+    let bytecode = eval_source(Backend::Bytecode);
+    assert_eq!(bytecode.to_string(), tree.to_string(), "{}", source);
+  }
+
+  let error = eval_with(Backend::Bytecode, "tests/error-span.zuko");
+  let span = error.unwrap_err().span().unwrap();
+  assert_eq!((span.start.line, span.start.column), (3, 19));
+}
+
+#[test]
+pub fn macro_values() {
+  let source = fs::read_to_string("tests/macro-values.zuko").unwrap();
+
+  let read_expr = read::read(&source).unwrap();
+  let eval_expr = eval::eval(read_expr).unwrap();
+
+  assert_eq!(eval_expr.to_string(), "(7 3 3 2)")
+}
+
+#[test]
+pub fn strings() {
+  let source = fs::read_to_string("tests/strings.zuko").unwrap();
+
+  let read_expr = read::read(&source).unwrap();
+  let eval_expr = eval::eval(read_expr).unwrap();
+
+  assert_eq!(
+    eval_expr.to_string(),
+    "(11 \"foobär\" \"café\" \"aïv\" :start-exceeds-end (\"a\" \"b\" \"\" \"c\") \
+     (\"日\" \"本\") \"x, y, z\" \"padded\" \"STRASSE\" \"àéî\" 3 () 31 () \"2.5\" \
+     made-up \"symbol->string\" (\"a\" \"ñ\" \"b\"))"
+  );
+}
+
+#[test]
+pub fn string_errors() {
+  for source in &[
+    "(substring \"abc\" 2 5)",
+    "(substring \"abc\" 3 1)",
+    "(substring \"日本\" 1.5)",
+    "(string-length 'abc)",
+    "(string-join (list \"a\" 1))",
+  ] {
+    assert!(eval::eval(read::read(source).unwrap()).is_err());
+  }
+
+  let source = "(substring \"日本語\" 1 4)";
+  let error = eval::eval(read::read(source).unwrap()).unwrap_err();
+
+  assert_eq!(error.to_string(), "index 4 is out of bounds for length 3");
+
+  let source = "(substring \"abc\" 3 1)";
+  let error = eval::eval(read::read(source).unwrap()).unwrap_err();
+
+  assert_eq!(error.to_string(), "start 3 exceeds end 1");
+}
+
+#[test]
+pub fn string_escapes() {
+  let source = fs::read_to_string("tests/string-escapes.zuko").unwrap();
+
+  let read_expr = read::read(&source).unwrap();
+  let eval_expr = eval::eval(read_expr).unwrap();
+
+  assert_eq!(
+    eval_expr.to_string(),
+    "(\"tab:\\there\" \"quote: \\\"hi\\\"\" \"back\\\\slash\" \
+     \"line\\nbreak\" \"Hé😀\" \"raw \\\"quoted\\\" \\\\n text\\nover two lines\" \
+     \"\" 2)"
+  );
+
+  for source in &["\"\\q\"", "\"\\u{110000}\"", "\"\\u{}\"", "\"\\u{zz}\""] {
+    assert!(read::read(source).is_err());
+  }
+}
+
+#[test]
+pub fn string_round_trip() {
+  use zuko::read::Reader;
+
+  for string in &["", "\"", "\\", "a\nb\tc\rd", "\u{0}\u{7f}", "é😀", "\"\"\""]
+  {
+    let expr = Expr::Atom(Atom::String(string.to_string()));
+    let printed = format!("{}", expr);
+    let read_expr = Reader::new(printed.chars()).read_expr().unwrap();
+
+    assert_eq!(read_expr, expr, "{}", printed);
+  }
+}
+
+#[test]
+pub fn modules() {
+  use std::cell::Cell;
+  use std::rc::Rc;
+
+  use zuko::eval::{Backend, Evaluator};
+
+  for backend in &[Backend::Tree, Backend::Bytecode] {
+    let loads = Rc::new(Cell::new(0));
+
+    let mut evaluator = Evaluator::with_backend(*backend);
+    evaluator.set_path("tests/modules.zuko");
+    evaluator.add_search_path("tests/modules/lib");
+    let host_loads = loads.clone();
+    evaluator.register_fn("count-load", move |()| {
+      host_loads.set(host_loads.get() + 1);
+      Ok(())
+    });
+
+    let source = fs::read_to_string("tests/modules.zuko").unwrap();
+    let eval_expr = evaluator.eval_expr(read::read(&source).unwrap()).unwrap();
+
+    assert_eq!(eval_expr.to_string(), "(13.0 3.25 3.25 1 6.5)");
+    assert_eq!(loads.get(), 2);
+
+    let source = "geometry/square";
+    let error = evaluator
+      .eval_expr(read::read(source).unwrap())
+      .unwrap_err();
+    assert_eq!(error.to_string(), "'geometry/square' is undefined");
+
+    let source = "(import modules/cycle)";
+    let error = evaluator
+      .eval_expr(read::read(source).unwrap())
+      .unwrap_err();
+    assert!(error
+      .to_string()
+      .contains("'cycle.zuko' is imported while it is being imported"));
+  }
+}
+
+#[test]
+pub fn errors() {
+  let source = fs::read_to_string("tests/errors.zuko").unwrap();
+
+  let read_expr = read::read(&source).unwrap();
+  let eval_expr = eval::eval(read_expr).unwrap();
+
+  assert_eq!(
+    eval_expr.to_string(),
+    "(3.0 (4) 43 :undefined-symbol :invalid-type :wrong-arity \
+     :index-out-of-bounds :error get-undefined \"from finally\" \"caught!\" 1)"
+  );
+}
+
+#[test]
+pub fn uncaught_errors() {
+  use zuko::eval::{EvalError, Evaluator};
+
+  let source = "(error \"something broke\" 1 2)";
+  let error = eval::eval(read::read(source).unwrap()).unwrap_err();
+  assert_eq!(error.to_string(), "something broke");
+
+  let source = "(begin (raise '(1 2)))";
+  let error = eval::eval(read::read(source).unwrap()).unwrap_err();
+  assert_eq!(error.to_string(), "raised (1 2)");
+
+  let mut evaluator = Evaluator::new();
+  evaluator.register_native("fail", |_| Err(EvalError::native("host failure")));
+
+  let source = "(try (fail) (catch e (list (get e :kind) (get e :message))))";
+  let eval_expr = evaluator.eval_expr(read::read(source).unwrap()).unwrap();
+  assert_eq!(eval_expr.to_string(), "(:native \"host failure\")");
+}
+
+#[test]
+pub fn garbage_collection() {
+  let source = fs::read_to_string("tests/gc.zuko").unwrap();
+
+  let read_expr = read::read(&source).unwrap();
+  let eval_expr = eval::eval(read_expr).unwrap();
+
+  assert_eq!(
+    eval_expr.to_string(),
+    "((1001 2001 3001 4001 5001) true true (1001 2001 3001 4001 5001))"
+  );
+}
+
+#[test]
+pub fn heap_stats() {
+  use zuko::eval::{Backend, Evaluator};
+
+  for backend in &[Backend::Tree, Backend::Bytecode] {
+    let mut evaluator = Evaluator::with_backend(*backend);
+    evaluator.collect_garbage();
+
+    let source = "(define cycle
+                          (function ()
+                                    (begin (define self (function () self))
+                                           (self))))";
+    evaluator.eval_expr(read::read(source).unwrap()).unwrap();
+    let frames = evaluator.heap_stats().frames;
+    for _ in 0..100 {
+      evaluator.eval_expr(read::read("(cycle)").unwrap()).unwrap();
+    }
+
+    evaluator.collect_garbage();
+    let stats = evaluator.heap_stats();
+    assert_eq!(stats.frames, frames);
+    assert!(stats.collections >= 2);
+
+    let source = "((cycle))";
+    let eval_expr = evaluator.eval_expr(read::read(source).unwrap()).unwrap();
+    assert_eq!(eval_expr.to_string(), "Function");
+  }
+}
+
+#[test]
+pub fn symbols() {
+  use zuko::ast::Symbol;
+
+  let apple = Symbol::new("apple");
+  assert_eq!(apple, Symbol::new(String::from("apple")));
+  assert_ne!(apple, Symbol::new("banana"));
+  assert_eq!(apple.to_string(), "apple");
+
+  let gensym = Symbol::gensym("apple");
+  assert_ne!(gensym, apple);
+  assert_ne!(gensym, Symbol::gensym("apple"));
+  assert!(gensym.to_string().starts_with("apple#"));
+
+  let marked = apple.mark();
+  assert_eq!(marked, apple);
+  assert!(!marked.is_same(&apple));
+  assert!(marked.unmark().is_same(&apple));
+
+  let names: Vec<String> = (0..20000).map(|i| format!("name{}", i)).collect();
+  let source = format!("(count (quote ({})))", names.join(" "));
+  let eval_expr = eval::eval(read::read(&source).unwrap()).unwrap();
+  assert_eq!(eval_expr, Expr::Atom(Atom::Integer(20000)));
+}
+
+#[test]
+pub fn symbol_reclamation() {
+  use zuko::ast::Symbol;
+  use zuko::eval::{Backend, Evaluator};
+
+  for backend in [Backend::Tree, Backend::Bytecode].iter().copied() {
+    let mut evaluator = Evaluator::with_backend(backend);
+    evaluator.collect_garbage();
+    let count = Symbol::interned_count();
+
+    // Names read or created by an evaluator are freed along with it.
+    let mut stale = Vec::new();
+    for i in 0..100 {
+      let mut short_lived = Evaluator::with_backend(backend);
+      let source = format!(
+        "(begin (define name-{} (gensym (string->symbol \"prefix-{}\")))
+                (quote stale-{}))",
+        i, i, i
+      );
+      let read_expr = short_lived.read(&source).unwrap();
+      stale.push(short_lived.eval_expr(read_expr).unwrap());
+    }
+    assert_eq!(Symbol::interned_count(), count);
+
+    // Symbols which outlive their evaluator lose their name, and are never
+    // equal to symbols interned since, even if their id is reused.
+    let read_expr = evaluator.read("(quote stale-0)").unwrap();
+    let fresh = evaluator.eval_expr(read_expr).unwrap();
+    assert_ne!(stale[0], fresh);
+    assert_eq!(stale[0].to_string(), "#<freed>");
+    assert_eq!(fresh.to_string(), "stale-0");
+  }
+}
+
+#[test]
+pub fn scopes() {
+  let source = fs::read_to_string("tests/scopes.zuko").unwrap();
+
+  let read_expr = read::read(&source).unwrap();
+  let eval_expr = eval::eval(read_expr).unwrap();
+
+  assert_eq!(
+    eval_expr.to_string(),
+    "(5 (1 0) (1 2) (3 4) (1 5) 1 (5 7) (a 4 4 4) (6 a))"
+  )
+}
+
+#[test]
+pub fn let_forms() {
+  let source = fs::read_to_string("tests/let.zuko").unwrap();
+
+  let read_expr = read::read(&source).unwrap();
+  let eval_expr = eval::eval(read_expr).unwrap();
+
+  assert_eq!(
+    eval_expr.to_string(),
+    "((2 1) (0 1 2 3 4) :odd (11 22) :undefined-symbol 5.0 (1 2 3) 10 100000)"
+  )
+}
+
+#[test]
+pub fn set() {
+  let source = fs::read_to_string("tests/set.zuko").unwrap();
+
+  let read_expr = read::read(&source).unwrap();
+  let eval_expr = eval::eval(read_expr).unwrap();
+
+  assert_eq!(
+    eval_expr.to_string(),
+    "(3 6 1 7 (:undefined-symbol missing))"
+  )
+}
+
+#[test]
+pub fn strict() {
+  use zuko::eval::{Backend, Evaluator};
+
+  for backend in [Backend::Tree, Backend::Bytecode].iter().copied() {
+    let mut evaluator = Evaluator::with_backend(backend);
+    evaluator.set_strict(true);
+
+    let mut eval_source =
+      |source: &str| evaluator.eval_expr(read::read(source).unwrap());
+
+    let source = "(begin
+                    (define x 1)
+                    (define (shadow) (begin (define x 2) x))
+                    (list (shadow) (set! x 3)))";
+    assert_eq!(eval_source(source).unwrap().to_string(), "(2 3)");
+
+    let error = eval_source("(define x 4)").unwrap_err();
+    assert_eq!(error.to_string(), "'x' is already defined");
+
+    let source = "((function () (begin (define y 1) (define y 2))))";
+    let error = eval_source(source).unwrap_err();
+    assert_eq!(error.to_string(), "'y' is already defined");
+
+    assert_eq!(eval_source("x").unwrap().to_string(), "3");
+  }
+}
+
+#[test]
+pub fn conditionals() {
+  let source = fs::read_to_string("tests/conditionals.zuko").unwrap();
+
+  let read_expr = read::read(&source).unwrap();
+  let eval_expr = eval::eval(read_expr).unwrap();
+
+  assert_eq!(
+    eval_expr.to_string(),
+    "((() 2 true () true false) \
+     (1 () () 2) \
+     (:negative :zero :one :two :many) \
+     (:small :letter :structure :structure :small :other) \
+     (3 2 1) \
+     :really () () () \
+     (\"Fizz\" \"Buzz\" 7 \"FizzBuzz\") \
+     ((1 2) -3) \
+     (2 1 :mine))"
+  )
+}
+
+#[test]
+pub fn booleans() {
+  let source = fs::read_to_string("tests/booleans.zuko").unwrap();
+
+  let read_expr = read::read(&source).unwrap();
+  let eval_expr = eval::eval(read_expr).unwrap();
+
+  assert_eq!(
+    eval_expr.to_string(),
+    "(true false true false false false true true true :no :no false :yes)"
+  )
+}
+
+#[test]
+pub fn truthiness() {
+  use zuko::ast::Truthiness;
+  use zuko::eval::{Backend, Evaluator};
+
+  for backend in [Backend::Tree, Backend::Bytecode].iter().copied() {
+    let mut evaluator = Evaluator::with_backend(backend);
+    evaluator.set_truthiness(Truthiness::FalseOnly);
+    // The setting belongs to the evaluator it was made on.
+    let mut default = Evaluator::with_backend(backend);
+
+    let source = "(list (if () :yes :no)
+                        (if false :yes :no)
+                        (not ())
+                        (or () 1)
+                        (try 1 (catch e 2)))";
+    let eval_expr = evaluator.eval_expr(read::read(source).unwrap()).unwrap();
+    assert_eq!(eval_expr.to_string(), "(:yes :no false () 1)");
+    let eval_expr = default.eval_expr(read::read(source).unwrap()).unwrap();
+    assert_eq!(eval_expr.to_string(), "(:no :no true 1 1)");
+  }
+}
+
+#[test]
+pub fn pattern_matching() {
+  let source = fs::read_to_string("tests/match.zuko").unwrap();
+
+  let read_expr = read::read(&source).unwrap();
+  let eval_expr = eval::eval(read_expr).unwrap();
+
+  assert_eq!(
+    eval_expr.to_string(),
+    "(10 \
+     (:zero :greeting :quit :same :pair (:diagonal 3) (:point 1 2) \
+     (:nested 1 2) (:number 7) :other) \
+     (1 (2 3)) 6 :outer \
+     (:no-match (1 2) \"no pattern matched (1 2)\"))"
+  )
 }